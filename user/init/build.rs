@@ -0,0 +1,4 @@
+fn main() {
+    println!("cargo:rustc-link-arg=-Tuser/init/linker.ld");
+    println!("cargo:rerun-if-changed=user/init/linker.ld");
+}
@@ -0,0 +1,220 @@
+#![no_std]
+#![no_main]
+
+use vlib::{
+    as_str,
+    initctl::{INITCTL, REQ_HALT, REQ_NONE, REQ_POWEROFF, REQ_REBOOT},
+    println,
+    syscalls::{
+        O_CREAT, O_RDONLY, O_RDWR, WAIT_ANY, WAIT_FOREVER, close, exit, kill, open, read, reboot,
+        shm_map, shm_open, spawn, wait,
+    },
+    time::sleep_ms,
+};
+
+const CONFIG: &[u8] = b"/system/init.conf";
+const DEFAULT_CONFIG: &[u8] = b"respawn /initrd/bin/shell\n";
+const MAX_SERVICES: usize = 16;
+const POLL_MS: u64 = 100;
+const STOP_TIMEOUT_MS: u64 = 2000;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    // run to completion before starting the next entry
+    Wait,
+    Once,
+    Respawn,
+}
+
+#[derive(Clone, Copy)]
+struct Service<'a> {
+    mode: Mode,
+    path: &'a [u8],
+    pid: Option<u64>,
+}
+
+fn load_config(buf: &mut [u8]) -> usize {
+    let fd = open(CONFIG, O_RDONLY);
+    if fd < 0 {
+        println!("init: no {}, using built-in defaults", as_str!(CONFIG));
+        buf[..DEFAULT_CONFIG.len()].copy_from_slice(DEFAULT_CONFIG);
+        return DEFAULT_CONFIG.len();
+    }
+
+    let mut len = 0;
+    while len < buf.len() {
+        let n = read(fd as u64, &mut buf[len..]) as usize;
+        if n == 0 {
+            break;
+        }
+        len += n;
+    }
+    close(fd as u64);
+    len
+}
+
+fn parse_config<'a>(config: &'a [u8], services: &mut [Option<Service<'a>>]) -> usize {
+    let mut count = 0;
+
+    for (lineno, line) in config.split(|&b| b == b'\n').enumerate() {
+        let line = line.trim_ascii();
+        if line.is_empty() || line[0] == b'#' {
+            continue;
+        }
+
+        let mut words = line
+            .split(|b| b.is_ascii_whitespace())
+            .filter(|w| !w.is_empty());
+        let (Some(mode), Some(path)) = (words.next(), words.next()) else {
+            println!("init: line {}: expected '<mode> <path>'", lineno + 1);
+            continue;
+        };
+
+        let mode = match mode {
+            b"wait" => Mode::Wait,
+            b"once" => Mode::Once,
+            b"respawn" => Mode::Respawn,
+            _ => {
+                println!(
+                    "init: line {}: unknown mode '{}'",
+                    lineno + 1,
+                    as_str!(mode)
+                );
+                continue;
+            }
+        };
+
+        if count == services.len() {
+            println!("init: too many services, ignoring the rest");
+            break;
+        }
+
+        services[count] = Some(Service {
+            mode,
+            path,
+            pid: None,
+        });
+        count += 1;
+    }
+
+    count
+}
+
+fn start(service: &mut Service) {
+    let pid = spawn(service.path);
+    if pid < 0 {
+        println!("init: failed to start {}", as_str!(service.path));
+        service.pid = None;
+        // a respawn entry that cannot start would otherwise be retried forever
+        service.mode = Mode::Once;
+        return;
+    }
+    service.pid = Some(pid as u64);
+
+    if service.mode == Mode::Wait {
+        let mut status = 0;
+        wait(pid as u64, &mut status, WAIT_FOREVER);
+        service.pid = None;
+        if status != 0 {
+            println!(
+                "init: {} exited with status {}",
+                as_str!(service.path),
+                status
+            );
+        }
+    }
+}
+
+fn reap(services: &mut [Option<Service>], pid: u64, status: u64) {
+    let Some(service) = services.iter_mut().flatten().find(|s| s.pid == Some(pid)) else {
+        // an orphan handed to us by the kernel
+        return;
+    };
+
+    service.pid = None;
+    if service.mode == Mode::Respawn {
+        println!(
+            "init: {} exited with status {}, respawning",
+            as_str!(service.path),
+            status
+        );
+        start(service);
+    }
+}
+
+fn shutdown(services: &mut [Option<Service>], req: u8) -> ! {
+    let action = match req {
+        REQ_POWEROFF => "power off",
+        REQ_REBOOT => "reboot",
+        _ => "halt",
+    };
+    println!("init: shutting down to {}", action);
+
+    for service in services.iter_mut().rev().flatten() {
+        if let Some(pid) = service.pid.take() {
+            kill(pid);
+        }
+    }
+
+    let mut status = 0;
+    let mut waited = 0;
+    while waited < STOP_TIMEOUT_MS && wait(WAIT_ANY, &mut status, POLL_MS) >= 0 {
+        waited += POLL_MS;
+    }
+
+    reboot(req as u64);
+    println!("init: failed to {}", action);
+    loop {
+        core::hint::spin_loop();
+    }
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn _start() -> ! {
+    let ctl_fd = shm_open(INITCTL, O_RDWR | O_CREAT, 4096);
+    let ctl = if ctl_fd >= 0 {
+        let page = shm_map(ctl_fd as u64, 0);
+        close(ctl_fd as u64);
+        page
+    } else {
+        core::ptr::null_mut()
+    };
+    if ctl.is_null() {
+        println!("init: cannot create initctl, shutdown requests are unavailable");
+    }
+
+    let mut buf = [0u8; 4096];
+    let len = load_config(&mut buf);
+
+    let mut services: [Option<Service>; MAX_SERVICES] = [None; MAX_SERVICES];
+    let count = parse_config(&buf[..len], &mut services);
+    let services = &mut services[..count];
+
+    for service in services.iter_mut().flatten() {
+        start(service);
+    }
+
+    loop {
+        if !ctl.is_null() {
+            let req = unsafe { ctl.read_volatile() };
+            if matches!(req, REQ_POWEROFF | REQ_REBOOT | REQ_HALT) {
+                unsafe { ctl.write_volatile(REQ_NONE) };
+                shutdown(services, req);
+            }
+        }
+
+        let mut status = 0;
+        let pid = wait(WAIT_ANY, &mut status, POLL_MS);
+        if pid > 0 {
+            reap(services, pid as u64, status);
+        } else if pid < 0 {
+            // nothing left to wait on, so wait returns at once
+            sleep_ms(POLL_MS);
+        }
+    }
+}
+
+#[panic_handler]
+fn panic(_: &core::panic::PanicInfo) -> ! {
+    exit(1);
+}
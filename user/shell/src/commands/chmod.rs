@@ -0,0 +1,27 @@
+use vlib::{as_str, println, syscalls::chmod};
+
+fn parse_octal(s: &[u8]) -> Option<u32> {
+    if s.is_empty() || s.len() > 4 {
+        return None;
+    }
+    s.iter().try_fold(0, |mode, &c| match c {
+        b'0'..=b'7' => Some(mode * 8 + (c - b'0') as u32),
+        _ => None,
+    })
+}
+
+pub fn run(args: &[&[u8]]) {
+    if args.len() < 2 {
+        println!("usage: chmod <octal mode> <path>");
+        return;
+    }
+
+    let Some(mode) = parse_octal(args[0]) else {
+        println!("chmod: invalid mode '{}'", as_str!(args[0]));
+        return;
+    };
+
+    if chmod(args[1], mode) < 0 {
+        println!("chmod: cannot change mode of '{}'", as_str!(args[1]));
+    }
+}
@@ -0,0 +1,35 @@
+use vlib::{
+    as_str, println,
+    syscalls::{ID_UNCHANGED, chown},
+};
+
+fn parse_id(s: &[u8]) -> Option<u32> {
+    if s.is_empty() {
+        return Some(ID_UNCHANGED);
+    }
+    s.iter().try_fold(0u32, |id, &c| match c {
+        b'0'..=b'9' => id.checked_mul(10)?.checked_add((c - b'0') as u32),
+        _ => None,
+    })
+}
+
+pub fn run(args: &[&[u8]]) {
+    if args.len() < 2 {
+        println!("usage: chown <uid>[:<gid>] <path>");
+        return;
+    }
+
+    // either side may be left out to keep it
+    let (uid, gid) = match args[0].iter().position(|&c| c == b':') {
+        Some(i) => (&args[0][..i], &args[0][i + 1..]),
+        None => (args[0], &[][..]),
+    };
+    let (Some(uid), Some(gid)) = (parse_id(uid), parse_id(gid)) else {
+        println!("chown: invalid owner '{}'", as_str!(args[0]));
+        return;
+    };
+
+    if chown(args[1], uid, gid) < 0 {
+        println!("chown: cannot change owner of '{}'", as_str!(args[1]));
+    }
+}
@@ -0,0 +1,15 @@
+use vlib::{println, time::DateTime};
+
+pub fn run(_args: &[&[u8]]) {
+    let now = DateTime::now();
+    println!(
+        "{} {} {:>2} {:02}:{:02}:{:02} UTC {}",
+        now.weekday_name(),
+        now.month_name(),
+        now.day,
+        now.hour,
+        now.minute,
+        now.second,
+        now.year
+    );
+}
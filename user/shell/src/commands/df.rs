@@ -0,0 +1,62 @@
+use vlib::{
+    as_str, println,
+    syscalls::{O_RDONLY, StatFs, close, open, read, statfs},
+};
+
+fn show(source: &[u8], target: &[u8], all: bool) {
+    let mut stats = StatFs::empty();
+    if statfs(target, &mut stats) < 0 {
+        println!("df: cannot stat '{}'", as_str!(target));
+        return;
+    }
+    if stats.total_blocks == 0 && !all {
+        return;
+    }
+
+    let kib = |blocks: u64| blocks * stats.block_size / 1024;
+    let used = stats.total_blocks - stats.free_blocks;
+    let percent = (used * 100).checked_div(stats.total_blocks).unwrap_or(0);
+    println!(
+        "{:<12} {:<7} {:>9} {:>9} {:>9} {:>3}% {}",
+        as_str!(source),
+        as_str!(stats.fs_type()),
+        kib(stats.total_blocks),
+        kib(used),
+        kib(stats.free_blocks),
+        percent,
+        as_str!(target)
+    );
+}
+
+pub fn run(args: &[&[u8]]) {
+    let all = args.first() == Some(&&b"-a"[..]);
+
+    let fd = open(b"/live/mounts", O_RDONLY);
+    if fd < 0 {
+        println!("df: cannot read /live/mounts");
+        return;
+    }
+
+    let fd = fd as u64;
+    let mut buf = [0u8; 2048];
+    let mut len = 0;
+    while len < buf.len() {
+        let bytes_read = read(fd, &mut buf[len..]);
+        if bytes_read == 0 || bytes_read == u64::MAX {
+            break;
+        }
+        len += bytes_read as usize;
+    }
+    close(fd);
+
+    println!(
+        "{:<12} {:<7} {:>9} {:>9} {:>9} {:>4} {}",
+        "Filesystem", "Type", "1K-blocks", "Used", "Available", "Use%", "Mounted on"
+    );
+    for line in buf[..len].split(|&c| c == b'\n') {
+        let mut fields = line.split(|&c| c == b' ');
+        if let (Some(source), Some(target)) = (fields.next(), fields.next()) {
+            show(source, target, all);
+        }
+    }
+}
@@ -4,15 +4,30 @@ pub fn run(_args: &[&[u8]]) {
     println!("commands:");
     println!("  help          - what you're looking at");
     println!("  echo <text>   - echo some text");
-    println!("  ls <dir>      - list a directories contents");
+    println!("  ls [-l] <dir> - list a directories contents");
     println!("  touch <file>  - create a file");
+    println!("  truncate <size> <file> - cut a file short or pad it with zeros");
     println!("  mkdir <dir>   - create a directory");
     println!("  rm <file>     - delete a file");
     println!("  rmdir <dir>   - delete a directory");
+    println!("  mv <from> <to> - move or rename a file or directory");
+    println!("  ln [-s] <target> <name> - make a hard or symbolic link");
+    println!("  chmod <mode> <path> - change permission bits (octal)");
+    println!("  chown <uid>[:<gid>] <path> - change owner and group");
     println!("  write <file> <text> - write text to a file");
     println!("  cat <file>    - display a files content");
     println!("  cd <dir>      - change directory");
     println!("  pwd           - display current working directory");
     println!("  ps            - list running tasks in /live/tasks");
+    println!("  date          - show the current date and time");
+    println!("  uptime        - show how long the system has been up");
+    println!("  mount [-r] <type> <source|none> <target> - mount a filesystem");
+    println!("  umount <target> - unmount a filesystem");
+    println!("  df [-a]       - show free space on mounted filesystems");
+    println!("  swapon <path> - start swapping to a file or device");
+    println!("  swapoff <path> - stop swapping to a file or device");
+    println!("  sync          - write everything cached out to disk");
+    println!("  shutdown [-r|-h] - power off, reboot or halt");
+    println!("  reboot        - restart the machine");
     println!("  exit          - say byebye to the shell :c");
 }
@@ -0,0 +1,31 @@
+use vlib::{
+    as_str, println,
+    syscalls::{link, symlink},
+};
+
+pub fn run(args: &[&[u8]]) {
+    let (soft, args) = match args.first() {
+        Some(&b"-s") => (true, &args[1..]),
+        _ => (false, args),
+    };
+
+    if args.len() < 2 {
+        println!("usage: ln [-s] <target> <name>");
+        return;
+    }
+
+    let (target, name) = (args[0], args[1]);
+    let result = if soft {
+        symlink(target, name)
+    } else {
+        link(target, name)
+    };
+
+    if result < 0 {
+        println!(
+            "ln: cannot link '{}' to '{}'",
+            as_str!(name),
+            as_str!(target)
+        );
+    }
+}
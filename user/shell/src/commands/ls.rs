@@ -1,9 +1,14 @@
 use vlib::{
     as_str, println,
-    syscalls::{DirEntryIter, O_DIRECTORY, O_RDONLY, close, getdents, open},
+    syscalls::{DirEntry, DirEntryIter, O_DIRECTORY, O_RDONLY, Stat, close, getdents, lstat, open},
+    time::DateTime,
 };
 
 pub fn run(args: &[&[u8]]) {
+    let (long, args) = match args.first() {
+        Some(&b"-l") => (true, &args[1..]),
+        _ => (false, args),
+    };
     let path: &[u8] = if args.is_empty() { b"." } else { args[0] };
 
     let fd = open(path, O_RDONLY | O_DIRECTORY);
@@ -23,8 +28,12 @@ pub fn run(args: &[&[u8]]) {
         }
 
         for entry in DirEntryIter::new(&buf, bytes_read as usize) {
-            if entry.is_dir() {
+            if long {
+                print_long(path, &entry);
+            } else if entry.is_dir() {
                 println!("[DIR]  {}", as_str!(entry.name()));
+            } else if entry.is_symlink() {
+                println!("[LINK] {}", as_str!(entry.name()));
             } else {
                 println!("[FILE] {}", as_str!(entry.name()));
             }
@@ -33,3 +42,56 @@ pub fn run(args: &[&[u8]]) {
 
     close(fd);
 }
+
+fn mode_string(stat: &Stat) -> [u8; 10] {
+    let mut out = *b"----------";
+    out[0] = if stat.is_dir() {
+        b'd'
+    } else if stat.is_symlink() {
+        b'l'
+    } else if stat.is_device() {
+        b'b'
+    } else {
+        b'-'
+    };
+    for i in 0..9 {
+        if stat.mode & (0o400 >> i) != 0 {
+            out[1 + i] = b"rwx"[i % 3];
+        }
+    }
+    out
+}
+
+fn print_long(dir: &[u8], entry: &DirEntry) {
+    let name = entry.name();
+    let mut path = [0u8; 512];
+    let len = dir.len() + 1 + name.len();
+    if len > path.len() {
+        return;
+    }
+    path[..dir.len()].copy_from_slice(dir);
+    path[dir.len()] = b'/';
+    path[dir.len() + 1..len].copy_from_slice(name);
+
+    let mut stat = Stat::empty();
+    if lstat(&path[..len], &mut stat) < 0 {
+        println!("?????????? {}", as_str!(name));
+        return;
+    }
+
+    let mode = mode_string(&stat);
+    let time = DateTime::from_unix(stat.mtime / 1_000_000_000);
+    println!(
+        "{} {:>4} {:>4} {:>8} {} {:>2} {} {:02}:{:02} {}",
+        as_str!(&mode),
+        stat.uid,
+        stat.gid,
+        stat.size,
+        time.month_name(),
+        time.day,
+        time.year,
+        time.hour,
+        time.minute,
+        as_str!(name)
+    );
+}
@@ -4,15 +4,30 @@ use crate::input::parse_args;
 
 mod cat;
 mod cd;
+mod chmod;
+mod chown;
+mod date;
+mod df;
 mod echo;
 mod help;
+mod ln;
 mod ls;
 mod mkdir;
+mod mount;
+mod mv;
 mod ps;
 mod pwd;
+mod reboot;
 mod rm;
 mod rmdir;
+mod shutdown;
+mod swapoff;
+mod swapon;
+mod sync;
 mod touch;
+mod truncate;
+mod umount;
+mod uptime;
 mod write;
 
 pub fn execute(line: &[u8]) {
@@ -33,12 +48,27 @@ pub fn execute(line: &[u8]) {
         b"cat" => cat::run(args),
         b"ps" => ps::run(args),
         b"touch" => touch::run(args),
+        b"truncate" => truncate::run(args),
         b"mkdir" => mkdir::run(args),
         b"rm" => rm::run(args),
         b"rmdir" => rmdir::run(args),
+        b"mv" => mv::run(args),
+        b"ln" => ln::run(args),
+        b"chmod" => chmod::run(args),
+        b"chown" => chown::run(args),
         b"write" => write::run(args),
         b"pwd" => pwd::run(args),
         b"cd" => cd::run(args),
+        b"swapon" => swapon::run(args),
+        b"swapoff" => swapoff::run(args),
+        b"sync" => sync::run(args),
+        b"shutdown" => shutdown::run(args),
+        b"reboot" => reboot::run(args),
+        b"date" => date::run(args),
+        b"uptime" => uptime::run(args),
+        b"mount" => mount::run(args),
+        b"umount" => umount::run(args),
+        b"df" => df::run(args),
         b"exit" => {
             println!("byebye o7");
             vlib::syscalls::exit(0);
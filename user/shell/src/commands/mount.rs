@@ -0,0 +1,52 @@
+use vlib::{
+    as_str, print, println,
+    syscalls::{MOUNT_READ_ONLY, O_RDONLY, close, mount, open, read},
+};
+
+fn list() {
+    let fd = open(b"/live/mounts", O_RDONLY);
+    if fd < 0 {
+        println!("mount: cannot read /live/mounts");
+        return;
+    }
+
+    let fd = fd as u64;
+    let mut buf = [0u8; 512];
+    loop {
+        let bytes_read = read(fd, &mut buf);
+        if bytes_read == 0 || bytes_read == u64::MAX {
+            break;
+        }
+        print!("{}", as_str!(&buf[..bytes_read as usize]));
+    }
+
+    close(fd);
+}
+
+pub fn run(args: &[&[u8]]) {
+    if args.is_empty() {
+        list();
+        return;
+    }
+
+    let (flags, args) = match args[0] {
+        b"-r" => (MOUNT_READ_ONLY, &args[1..]),
+        _ => (0, args),
+    };
+
+    if args.len() != 3 {
+        println!("usage: mount [-r] <type> <source|none> <target>");
+        return;
+    }
+
+    let (fs_type, source, target) = (args[0], args[1], args[2]);
+    let source: &[u8] = if source == b"none" { &[] } else { source };
+
+    if mount(fs_type, source, target, flags) < 0 {
+        println!(
+            "mount: cannot mount {} on '{}'",
+            as_str!(fs_type),
+            as_str!(target)
+        );
+    }
+}
@@ -0,0 +1,80 @@
+use vlib::{
+    as_str, println,
+    syscalls::{
+        EXDEV, O_CREAT, O_RDONLY, O_TRUNC, O_WRONLY, Stat, chmod, close, lstat, open, read, rename,
+        unlink, write,
+    },
+};
+
+pub fn run(args: &[&[u8]]) {
+    if args.len() < 2 {
+        println!("usage: mv <from> <to>");
+        return;
+    }
+
+    let (from, to) = (args[0], args[1]);
+
+    match rename(from, to) {
+        0 => {}
+        EXDEV => move_across(from, to),
+        _ => println!("mv: cannot move '{}' to '{}'", as_str!(from), as_str!(to)),
+    }
+}
+
+/// rename cannot cross mounts, so a plain file is copied over and the
+/// original unlinked once the copy is complete
+fn move_across(from: &[u8], to: &[u8]) {
+    let mut stat = Stat::empty();
+    if lstat(from, &mut stat) < 0 || stat.is_dir() || stat.is_device() || stat.is_symlink() {
+        println!(
+            "mv: cannot move '{}' to '{}': different filesystem",
+            as_str!(from),
+            as_str!(to)
+        );
+        return;
+    }
+
+    if copy(from, to) != Some(stat.size) {
+        unlink(to);
+        println!("mv: cannot copy '{}' to '{}'", as_str!(from), as_str!(to));
+        return;
+    }
+
+    chmod(to, stat.mode);
+    if unlink(from) < 0 {
+        println!("mv: copied, but cannot remove '{}'", as_str!(from));
+    }
+}
+
+/// the number of bytes copied, which a failed read cuts short
+fn copy(from: &[u8], to: &[u8]) -> Option<u64> {
+    let src = open(from, O_RDONLY);
+    if src < 0 {
+        return None;
+    }
+    let dst = open(to, O_WRONLY | O_CREAT | O_TRUNC);
+    if dst < 0 {
+        close(src as u64);
+        return None;
+    }
+
+    let (src, dst) = (src as u64, dst as u64);
+    let mut buf = [0u8; 512];
+    let mut copied = Some(0);
+
+    loop {
+        let n = read(src, &mut buf) as usize;
+        if n == 0 {
+            break;
+        }
+        if write(dst, &buf[..n]) as usize != n {
+            copied = None;
+            break;
+        }
+        copied = copied.map(|total| total + n as u64);
+    }
+
+    close(src);
+    close(dst);
+    copied
+}
@@ -0,0 +1,7 @@
+use vlib::initctl::REQ_REBOOT;
+
+use super::shutdown::power;
+
+pub fn run(_args: &[&[u8]]) {
+    power(REQ_REBOOT);
+}
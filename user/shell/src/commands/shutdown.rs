@@ -0,0 +1,27 @@
+use vlib::{
+    initctl::{REQ_HALT, REQ_POWEROFF, REQ_REBOOT, request},
+    println,
+    syscalls::reboot,
+};
+
+// ask init first so services get stopped, go straight to the kernel without it
+pub fn power(req: u8) {
+    if request(req) == 0 {
+        return;
+    }
+    reboot(req as u64);
+    println!("shutdown: permission denied or not supported");
+}
+
+pub fn run(args: &[&[u8]]) {
+    let req = match args.first() {
+        None | Some(&b"-p") => REQ_POWEROFF,
+        Some(&b"-r") => REQ_REBOOT,
+        Some(&b"-h") => REQ_HALT,
+        Some(_) => {
+            println!("usage: shutdown [-p|-r|-h]");
+            return;
+        }
+    };
+    power(req);
+}
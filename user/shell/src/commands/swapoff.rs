@@ -0,0 +1,14 @@
+use vlib::{as_str, println, syscalls::swapoff};
+
+pub fn run(args: &[&[u8]]) {
+    if args.is_empty() {
+        println!("usage: swapoff <file|device>");
+        return;
+    }
+
+    let path = args[0];
+
+    if swapoff(path) < 0 {
+        println!("swapoff: failed for '{}'", as_str!(path));
+    }
+}
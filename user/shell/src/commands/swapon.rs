@@ -0,0 +1,14 @@
+use vlib::{as_str, println, syscalls::swapon};
+
+pub fn run(args: &[&[u8]]) {
+    if args.is_empty() {
+        println!("usage: swapon <file|device>");
+        return;
+    }
+
+    let path = args[0];
+
+    if swapon(path) < 0 {
+        println!("swapon: failed for '{}'", as_str!(path));
+    }
+}
@@ -0,0 +1,7 @@
+use vlib::{println, syscalls::sync};
+
+pub fn run(_args: &[&[u8]]) {
+    if sync() < 0 {
+        println!("sync: failed to write back some filesystems");
+    }
+}
@@ -1,4 +1,4 @@
-use vlib::{println, syscalls::touch};
+use vlib::{as_str, println, syscalls::touch};
 
 pub fn run(args: &[&[u8]]) {
     if args.is_empty() {
@@ -8,5 +8,7 @@ pub fn run(args: &[&[u8]]) {
 
     let path = args[0];
 
-    touch(path);
+    if touch(path) < 0 {
+        println!("touch: cannot touch '{}'", as_str!(path));
+    }
 }
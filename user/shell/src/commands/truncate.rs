@@ -0,0 +1,27 @@
+use vlib::{as_str, println, syscalls::truncate};
+
+fn parse_size(s: &[u8]) -> Option<u64> {
+    if s.is_empty() {
+        return None;
+    }
+    s.iter().try_fold(0u64, |size, &c| match c {
+        b'0'..=b'9' => size.checked_mul(10)?.checked_add((c - b'0') as u64),
+        _ => None,
+    })
+}
+
+pub fn run(args: &[&[u8]]) {
+    if args.len() < 2 {
+        println!("usage: truncate <size> <file>");
+        return;
+    }
+
+    let Some(size) = parse_size(args[0]) else {
+        println!("truncate: invalid size '{}'", as_str!(args[0]));
+        return;
+    };
+
+    if truncate(args[1], size) < 0 {
+        println!("truncate: cannot truncate '{}'", as_str!(args[1]));
+    }
+}
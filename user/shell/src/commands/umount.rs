@@ -0,0 +1,14 @@
+use vlib::{as_str, println, syscalls::umount};
+
+pub fn run(args: &[&[u8]]) {
+    if args.is_empty() {
+        println!("usage: umount <target>");
+        return;
+    }
+
+    let target = args[0];
+
+    if umount(target) < 0 {
+        println!("umount: cannot unmount '{}'", as_str!(target));
+    }
+}
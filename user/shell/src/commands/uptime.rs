@@ -0,0 +1,13 @@
+use vlib::{println, time};
+
+pub fn run(_args: &[&[u8]]) {
+    let secs = time::monotonic().as_secs();
+    let days = secs / 86400;
+    let (hours, minutes, seconds) = (secs / 3600 % 24, secs / 60 % 60, secs % 60);
+
+    match days {
+        0 => println!("up {}:{:02}:{:02}", hours, minutes, seconds),
+        1 => println!("up 1 day, {}:{:02}:{:02}", hours, minutes, seconds),
+        _ => println!("up {} days, {}:{:02}:{:02}", days, hours, minutes, seconds),
+    }
+}
@@ -25,5 +25,7 @@ pub fn run(args: &[&[u8]]) {
         write(fd, arg);
     }
     write(fd, b"\n");
-    close(fd);
+    if close(fd) < 0 {
+        println!("write: failed to write back '{}'", as_str!(path));
+    }
 }
@@ -0,0 +1,29 @@
+use crate::syscalls::{
+    O_RDWR, REBOOT_HALT, REBOOT_POWEROFF, REBOOT_REBOOT, close, shm_map, shm_open, shm_unmap,
+};
+
+/// shared memory object init polls for requests, the first byte holds the
+/// pending request
+pub const INITCTL: &[u8] = b"initctl";
+
+pub const REQ_NONE: u8 = 0;
+pub const REQ_POWEROFF: u8 = REBOOT_POWEROFF as u8;
+pub const REQ_REBOOT: u8 = REBOOT_REBOOT as u8;
+pub const REQ_HALT: u8 = REBOOT_HALT as u8;
+
+pub fn request(req: u8) -> i64 {
+    let fd = shm_open(INITCTL, O_RDWR, 0);
+    if fd < 0 {
+        return -1;
+    }
+
+    let page = shm_map(fd as u64, 0);
+    close(fd as u64);
+    if page.is_null() {
+        return -1;
+    }
+
+    unsafe { page.write_volatile(req) };
+    shm_unmap(page);
+    0
+}
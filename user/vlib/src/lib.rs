@@ -1,4 +1,6 @@
 #![no_std]
 
+pub mod initctl;
 pub mod io;
 pub mod syscalls;
+pub mod time;
@@ -11,14 +11,68 @@ pub const SYS_UNLINK: u64 = 7;
 pub const SYS_RMDIR: u64 = 8;
 pub const SYS_CHDIR: u64 = 9;
 pub const SYS_GETCWD: u64 = 10;
+pub const SYS_SHM_OPEN: u64 = 11;
+pub const SYS_SHM_UNLINK: u64 = 12;
+pub const SYS_SHM_MAP: u64 = 13;
+pub const SYS_SHM_UNMAP: u64 = 14;
+pub const SYS_SWAPON: u64 = 15;
+pub const SYS_SWAPOFF: u64 = 16;
+pub const SYS_SPAWN: u64 = 17;
+pub const SYS_WAIT: u64 = 18;
+pub const SYS_KILL: u64 = 19;
+pub const SYS_REBOOT: u64 = 20;
+pub const SYS_CLOCK_GETTIME: u64 = 21;
+pub const SYS_NANOSLEEP: u64 = 22;
+pub const SYS_MOUNT: u64 = 23;
+pub const SYS_UMOUNT: u64 = 24;
+pub const SYS_RENAME: u64 = 25;
+pub const SYS_SYMLINK: u64 = 26;
+pub const SYS_READLINK: u64 = 27;
+pub const SYS_LINK: u64 = 28;
+pub const SYS_CHMOD: u64 = 29;
+pub const SYS_CHOWN: u64 = 30;
+pub const SYS_GETUID: u64 = 31;
+pub const SYS_SETUID: u64 = 32;
+pub const SYS_UTIMENS: u64 = 33;
+pub const SYS_STAT: u64 = 34;
+pub const SYS_LSTAT: u64 = 35;
+pub const SYS_FTRUNCATE: u64 = 36;
+pub const SYS_TRUNCATE: u64 = 37;
+pub const SYS_FSYNC: u64 = 38;
+pub const SYS_SYNC: u64 = 39;
+pub const SYS_STATFS: u64 = 40;
+
+pub const WAIT_ANY: u64 = u64::MAX;
+pub const WAIT_FOREVER: u64 = u64::MAX;
+
+pub const REBOOT_POWEROFF: u64 = 1;
+pub const REBOOT_REBOOT: u64 = 2;
+pub const REBOOT_HALT: u64 = 3;
+
+pub const CLOCK_REALTIME: u64 = 0;
+pub const CLOCK_MONOTONIC: u64 = 1;
+
+pub const MOUNT_READ_ONLY: u64 = 1;
+
+/// passed to `chown` for an owner or group that stays as it is
+pub const ID_UNCHANGED: u32 = u32::MAX;
+
+/// passed to `utimens` in place of a time
+pub const UTIME_NOW: u64 = u64::MAX;
+pub const UTIME_OMIT: u64 = u64::MAX - 1;
+
+/// what `rename` returns when `from` and `to` are on different mounts
+pub const EXDEV: i64 = -2;
 
 pub const O_RDONLY: u64 = 0;
 pub const O_WRONLY: u64 = 1;
 pub const O_RDWR: u64 = 2;
 pub const O_CREAT: u64 = 64;
+pub const O_EXCL: u64 = 128;
 pub const O_TRUNC: u64 = 512;
 pub const O_APPEND: u64 = 1024;
 pub const O_DIRECTORY: u64 = 65536;
+pub const O_NOFOLLOW: u64 = 131072;
 
 pub fn exit(code: u64) -> ! {
     syscall1(SYS_EXIT, code);
@@ -53,14 +107,81 @@ pub fn close(fd: u64) -> i64 {
     if result == u64::MAX { -1 } else { 0 }
 }
 
+/// creates the file if it is missing and moves its times to now
 pub fn touch(path: &[u8]) -> i64 {
     let fd = open(path, O_CREAT);
-    if fd >= 0 {
-        close(fd as u64);
-        0
-    } else {
-        -1
+    if fd < 0 {
+        return -1;
     }
+    close(fd as u64);
+    utimens(path, UTIME_NOW, UTIME_NOW)
+}
+
+/// times are nanoseconds since the unix epoch
+pub fn utimens(path: &[u8], atime: u64, mtime: u64) -> i64 {
+    let result = syscall4(
+        SYS_UTIMENS,
+        path.as_ptr() as u64,
+        path.len() as u64,
+        atime,
+        mtime,
+    );
+    if result == u64::MAX { -1 } else { 0 }
+}
+
+pub fn stat(path: &[u8], stat: &mut Stat) -> i64 {
+    let result = syscall3(
+        SYS_STAT,
+        path.as_ptr() as u64,
+        path.len() as u64,
+        stat as *mut Stat as u64,
+    );
+    if result == u64::MAX { -1 } else { 0 }
+}
+
+/// like `stat`, but describes a symlink itself rather than its target
+pub fn lstat(path: &[u8], stat: &mut Stat) -> i64 {
+    let result = syscall3(
+        SYS_LSTAT,
+        path.as_ptr() as u64,
+        path.len() as u64,
+        stat as *mut Stat as u64,
+    );
+    if result == u64::MAX { -1 } else { 0 }
+}
+
+/// cuts the file short or pads it with zeros
+pub fn truncate(path: &[u8], len: u64) -> i64 {
+    let result = syscall3(SYS_TRUNCATE, path.as_ptr() as u64, path.len() as u64, len);
+    if result == u64::MAX { -1 } else { 0 }
+}
+
+pub fn ftruncate(fd: u64, len: u64) -> i64 {
+    let result = syscall2(SYS_FTRUNCATE, fd, len);
+    if result == u64::MAX { -1 } else { 0 }
+}
+
+/// waits until everything written through `fd` has reached the disk
+pub fn fsync(fd: u64) -> i64 {
+    let result = syscall1(SYS_FSYNC, fd);
+    if result == u64::MAX { -1 } else { 0 }
+}
+
+/// writes back every filesystem
+pub fn sync() -> i64 {
+    let result = syscall0(SYS_SYNC);
+    if result == u64::MAX { -1 } else { 0 }
+}
+
+/// describes the filesystem `path` is on
+pub fn statfs(path: &[u8], stats: &mut StatFs) -> i64 {
+    let result = syscall3(
+        SYS_STATFS,
+        path.as_ptr() as u64,
+        path.len() as u64,
+        stats as *mut StatFs as u64,
+    );
+    if result == u64::MAX { -1 } else { 0 }
 }
 
 pub fn unlink(path: &[u8]) -> i64 {
@@ -68,6 +189,59 @@ pub fn unlink(path: &[u8]) -> i64 {
     result as i64
 }
 
+pub fn rename(from: &[u8], to: &[u8]) -> i64 {
+    let result = syscall4(
+        SYS_RENAME,
+        from.as_ptr() as u64,
+        from.len() as u64,
+        to.as_ptr() as u64,
+        to.len() as u64,
+    );
+    match result {
+        0 => 0,
+        r if r == u64::MAX - 1 => EXDEV,
+        _ => -1,
+    }
+}
+
+pub fn symlink(target: &[u8], path: &[u8]) -> i64 {
+    let result = syscall4(
+        SYS_SYMLINK,
+        target.as_ptr() as u64,
+        target.len() as u64,
+        path.as_ptr() as u64,
+        path.len() as u64,
+    );
+    if result == u64::MAX { -1 } else { 0 }
+}
+
+pub fn link(from: &[u8], to: &[u8]) -> i64 {
+    let result = syscall4(
+        SYS_LINK,
+        from.as_ptr() as u64,
+        from.len() as u64,
+        to.as_ptr() as u64,
+        to.len() as u64,
+    );
+    if result == u64::MAX { -1 } else { 0 }
+}
+
+/// returns the length of the target, which is cut short if `buf` is
+pub fn readlink(path: &[u8], buf: &mut [u8]) -> i64 {
+    let result = syscall4(
+        SYS_READLINK,
+        path.as_ptr() as u64,
+        path.len() as u64,
+        buf.as_mut_ptr() as u64,
+        buf.len() as u64,
+    );
+    if result == u64::MAX {
+        -1
+    } else {
+        result as i64
+    }
+}
+
 pub fn mkdir(path: &[u8]) -> i64 {
     let result = syscall2(SYS_MKDIR, path.as_ptr() as u64, path.len() as u64);
     result as i64
@@ -97,6 +271,210 @@ pub fn getdents(fd: u64, buf: &mut [u8]) -> i64 {
     result as i64
 }
 
+pub fn shm_open(name: &[u8], flags: u64, size: u64) -> i64 {
+    let result = syscall4(
+        SYS_SHM_OPEN,
+        name.as_ptr() as u64,
+        name.len() as u64,
+        flags,
+        size,
+    );
+    if result == u64::MAX {
+        -1
+    } else {
+        result as i64
+    }
+}
+
+pub fn shm_unlink(name: &[u8]) -> i64 {
+    let result = syscall2(SYS_SHM_UNLINK, name.as_ptr() as u64, name.len() as u64);
+    if result == u64::MAX { -1 } else { 0 }
+}
+
+pub fn shm_map(fd: u64, addr: u64) -> *mut u8 {
+    let result = syscall2(SYS_SHM_MAP, fd, addr);
+    if result == u64::MAX {
+        core::ptr::null_mut()
+    } else {
+        result as *mut u8
+    }
+}
+
+pub fn shm_unmap(addr: *mut u8) -> i64 {
+    let result = syscall1(SYS_SHM_UNMAP, addr as u64);
+    if result == u64::MAX { -1 } else { 0 }
+}
+
+pub fn swapon(path: &[u8]) -> i64 {
+    let result = syscall2(SYS_SWAPON, path.as_ptr() as u64, path.len() as u64);
+    if result == u64::MAX { -1 } else { 0 }
+}
+
+pub fn swapoff(path: &[u8]) -> i64 {
+    let result = syscall2(SYS_SWAPOFF, path.as_ptr() as u64, path.len() as u64);
+    if result == u64::MAX { -1 } else { 0 }
+}
+
+pub fn spawn(path: &[u8]) -> i64 {
+    let result = syscall2(SYS_SPAWN, path.as_ptr() as u64, path.len() as u64);
+    if result == u64::MAX {
+        -1
+    } else {
+        result as i64
+    }
+}
+
+/// returns the pid of the exited child, 0 if the timeout passed first and -1
+/// if there is no matching child
+pub fn wait(pid: u64, status: &mut u64, timeout_ms: u64) -> i64 {
+    let result = syscall3(SYS_WAIT, pid, status as *mut u64 as u64, timeout_ms);
+    if result == u64::MAX {
+        -1
+    } else {
+        result as i64
+    }
+}
+
+pub fn kill(pid: u64) -> i64 {
+    let result = syscall1(SYS_KILL, pid);
+    if result == u64::MAX { -1 } else { 0 }
+}
+
+/// syncs filesystems and powers off, reboots or halts. only returns on error
+pub fn reboot(mode: u64) -> i64 {
+    syscall1(SYS_REBOOT, mode);
+    -1
+}
+
+/// fills `ts` with seconds and nanoseconds
+pub fn clock_gettime(clock: u64, ts: &mut [u64; 2]) -> i64 {
+    let result = syscall2(SYS_CLOCK_GETTIME, clock, ts as *mut [u64; 2] as u64);
+    if result == u64::MAX { -1 } else { 0 }
+}
+
+pub fn nanosleep(ns: u64) {
+    syscall1(SYS_NANOSLEEP, ns);
+}
+
+/// an empty `source` mounts a filesystem that does not need one
+pub fn mount(fs_type: &[u8], source: &[u8], target: &[u8], flags: u64) -> i64 {
+    let args: [[u64; 2]; 3] = [
+        [fs_type.as_ptr() as u64, fs_type.len() as u64],
+        [source.as_ptr() as u64, source.len() as u64],
+        [target.as_ptr() as u64, target.len() as u64],
+    ];
+    let result = syscall2(SYS_MOUNT, &args as *const [[u64; 2]; 3] as u64, flags);
+    if result == u64::MAX { -1 } else { 0 }
+}
+
+pub fn umount(target: &[u8]) -> i64 {
+    let result = syscall2(SYS_UMOUNT, target.as_ptr() as u64, target.len() as u64);
+    if result == u64::MAX { -1 } else { 0 }
+}
+
+pub fn chmod(path: &[u8], mode: u32) -> i64 {
+    let result = syscall3(
+        SYS_CHMOD,
+        path.as_ptr() as u64,
+        path.len() as u64,
+        mode as u64,
+    );
+    if result == u64::MAX { -1 } else { 0 }
+}
+
+pub fn chown(path: &[u8], uid: u32, gid: u32) -> i64 {
+    let result = syscall4(
+        SYS_CHOWN,
+        path.as_ptr() as u64,
+        path.len() as u64,
+        uid as u64,
+        gid as u64,
+    );
+    if result == u64::MAX { -1 } else { 0 }
+}
+
+/// (uid, gid) of the calling task
+pub fn getuid() -> (u32, u32) {
+    let result = syscall0(SYS_GETUID);
+    (result as u32, (result >> 32) as u32)
+}
+
+/// only root may switch, and the groups it had are dropped
+pub fn setuid(uid: u32, gid: u32) -> i64 {
+    let result = syscall2(SYS_SETUID, uid as u64, gid as u64);
+    if result == u64::MAX { -1 } else { 0 }
+}
+
+#[repr(C)]
+#[derive(Clone)]
+pub struct Stat {
+    pub file_type: u64,
+    pub size: u64,
+    pub uid: u32,
+    pub gid: u32,
+    pub mode: u32,
+    _pad: u32,
+    pub atime: u64,
+    pub mtime: u64,
+    pub ctime: u64,
+}
+
+impl Stat {
+    pub const fn empty() -> Self {
+        Self {
+            file_type: 0,
+            size: 0,
+            uid: 0,
+            gid: 0,
+            mode: 0,
+            _pad: 0,
+            atime: 0,
+            mtime: 0,
+            ctime: 0,
+        }
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.file_type == 2
+    }
+
+    pub fn is_device(&self) -> bool {
+        self.file_type == 3
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        self.file_type == 4
+    }
+}
+
+/// sizes are in blocks of `block_size` bytes
+#[repr(C)]
+#[derive(Clone)]
+pub struct StatFs {
+    pub fs_type: [u8; 16],
+    pub block_size: u64,
+    pub total_blocks: u64,
+    pub free_blocks: u64,
+    pub max_name_len: u64,
+}
+
+impl StatFs {
+    pub const fn empty() -> Self {
+        Self {
+            fs_type: [0; 16],
+            block_size: 0,
+            total_blocks: 0,
+            free_blocks: 0,
+            max_name_len: 0,
+        }
+    }
+
+    pub fn fs_type(&self) -> &[u8] {
+        let len = self.fs_type.iter().position(|&c| c == 0).unwrap_or(16);
+        &self.fs_type[..len]
+    }
+}
+
 #[derive(Clone)]
 pub struct DirEntry {
     pub file_type: u8,
@@ -124,6 +502,10 @@ impl DirEntry {
     pub fn is_file(&self) -> bool {
         self.file_type == 1
     }
+
+    pub fn is_symlink(&self) -> bool {
+        self.file_type == 4
+    }
 }
 
 pub struct DirEntryIter<'a> {
@@ -0,0 +1,82 @@
+use core::time::Duration;
+
+use crate::syscalls::{CLOCK_MONOTONIC, CLOCK_REALTIME, clock_gettime, nanosleep};
+
+fn now(clock: u64) -> Duration {
+    let mut ts = [0u64; 2];
+    if clock_gettime(clock, &mut ts) < 0 {
+        return Duration::ZERO;
+    }
+    Duration::new(ts[0], ts[1] as u32)
+}
+
+/// time since boot, never goes backwards
+pub fn monotonic() -> Duration {
+    now(CLOCK_MONOTONIC)
+}
+
+/// time since the unix epoch, in utc
+pub fn realtime() -> Duration {
+    now(CLOCK_REALTIME)
+}
+
+pub fn sleep(duration: Duration) {
+    nanosleep(duration.as_nanos().min(u64::MAX as u128) as u64);
+}
+
+pub fn sleep_ms(ms: u64) {
+    sleep(Duration::from_millis(ms));
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DateTime {
+    pub year: i64,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    // 0 is sunday
+    pub weekday: u8,
+}
+
+impl DateTime {
+    pub fn from_unix(secs: u64) -> Self {
+        let days = (secs / 86400) as i64;
+        let rem = secs % 86400;
+
+        let z = days + 719468;
+        let era = z.div_euclid(146097);
+        let doe = z - era * 146097;
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u8;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u8;
+        let year = yoe + era * 400 + (month <= 2) as i64;
+
+        Self {
+            year,
+            month,
+            day,
+            hour: (rem / 3600) as u8,
+            minute: (rem / 60 % 60) as u8,
+            second: (rem % 60) as u8,
+            weekday: ((days + 4) % 7) as u8,
+        }
+    }
+
+    pub fn now() -> Self {
+        Self::from_unix(realtime().as_secs())
+    }
+
+    pub fn weekday_name(&self) -> &'static str {
+        ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"][self.weekday as usize]
+    }
+
+    pub fn month_name(&self) -> &'static str {
+        [
+            "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+        ][self.month as usize - 1]
+    }
+}
@@ -0,0 +1,518 @@
+use alloc::{string::String, vec::Vec};
+use core::fmt::Write;
+use spin::Once;
+use x86_64::{PhysAddr, instructions::port::Port};
+
+use crate::mem::vmm;
+
+const SDT_HEADER_LEN: usize = 36;
+const FADT_TMR_VAL_EXT: u32 = 1 << 8;
+const FADT_RESET_REG_SUPPORTED: u32 = 1 << 10;
+const SCI_EN: u16 = 1 << 0;
+const SLP_EN: u16 = 1 << 13;
+
+const SPACE_MEMORY: u8 = 0;
+const SPACE_IO: u8 = 1;
+
+#[derive(Debug, Clone, Copy)]
+pub struct GenericAddress {
+    pub space: u8,
+    pub address: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Fadt {
+    pub sci_irq: u16,
+    pub smi_cmd: u32,
+    pub acpi_enable: u8,
+    pub pm1a_cnt: u16,
+    pub pm1b_cnt: u16,
+    pub pm_timer: Option<u16>,
+    pub pm_timer_32bit: bool,
+    pub century: u8,
+    pub reset: Option<(GenericAddress, u8)>,
+    pub s5: Option<(u16, u16)>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Cpu {
+    pub acpi_id: u32,
+    pub apic_id: u32,
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct IoApic {
+    pub id: u8,
+    pub address: u64,
+    pub gsi_base: u32,
+}
+
+// MPS INTI flags, polarity in bits 0-1 and trigger mode in bits 2-3
+#[derive(Debug, Clone, Copy)]
+pub struct IrqOverride {
+    pub source: u8,
+    pub gsi: u32,
+    pub flags: u16,
+}
+
+impl IrqOverride {
+    pub fn active_low(&self) -> bool {
+        self.flags & 0x3 == 0x3
+    }
+
+    pub fn level_triggered(&self) -> bool {
+        (self.flags >> 2) & 0x3 == 0x3
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Madt {
+    pub lapic_address: u64,
+    pub legacy_pics: bool,
+    pub cpus: Vec<Cpu>,
+    pub ioapics: Vec<IoApic>,
+    pub overrides: Vec<IrqOverride>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Hpet {
+    pub address: u64,
+    pub number: u8,
+    pub timers: u8,
+    pub counter_64bit: bool,
+    pub vendor: u16,
+    pub min_tick: u16,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct McfgEntry {
+    pub address: u64,
+    pub segment: u16,
+    pub start_bus: u8,
+    pub end_bus: u8,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TableInfo {
+    pub signature: [u8; 4],
+    pub address: u64,
+    pub length: u32,
+    pub revision: u8,
+    pub oem_id: [u8; 6],
+}
+
+#[derive(Debug, Default)]
+pub struct Acpi {
+    pub revision: u8,
+    pub oem_id: [u8; 6],
+    pub tables: Vec<TableInfo>,
+    pub fadt: Option<Fadt>,
+    pub madt: Option<Madt>,
+    pub hpet: Option<Hpet>,
+    pub mcfg: Vec<McfgEntry>,
+}
+
+impl Acpi {
+    // ISA irqs are identity mapped to GSIs unless the MADT says otherwise
+    pub fn isa_irq(&self, irq: u8) -> IrqOverride {
+        self.madt
+            .as_ref()
+            .and_then(|madt| madt.overrides.iter().find(|o| o.source == irq))
+            .copied()
+            .unwrap_or(IrqOverride {
+                source: irq,
+                gsi: irq as u32,
+                flags: 0,
+            })
+    }
+}
+
+static ACPI: Once<Acpi> = Once::new();
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(data: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+fn phys_slice(phys: u64, len: usize) -> Result<&'static [u8], &'static str> {
+    let virt = vmm::map_physical(PhysAddr::new(phys), len)?;
+    Ok(unsafe { core::slice::from_raw_parts(virt.as_ptr(), len) })
+}
+
+fn checksum_ok(data: &[u8]) -> bool {
+    data.iter().fold(0u8, |sum, b| sum.wrapping_add(*b)) == 0
+}
+
+fn table(phys: u64) -> Result<&'static [u8], &'static str> {
+    let header = phys_slice(phys, SDT_HEADER_LEN)?;
+    let len = read_u32(header, 4) as usize;
+    if len < SDT_HEADER_LEN {
+        return Err("truncated ACPI table");
+    }
+
+    let data = phys_slice(phys, len)?;
+    if !checksum_ok(data) {
+        return Err("bad ACPI table checksum");
+    }
+    Ok(data)
+}
+
+fn parse_gas(data: &[u8], offset: usize) -> GenericAddress {
+    GenericAddress {
+        space: data[offset],
+        address: read_u64(data, offset + 4),
+    }
+}
+
+// finds the \_S5 package in the dsdt and returns SLP_TYPa and SLP_TYPb
+fn find_s5(dsdt: &[u8]) -> Option<(u16, u16)> {
+    let body = &dsdt[SDT_HEADER_LEN..];
+    let pos = body.windows(4).position(|w| w == b"_S5_")?;
+
+    let named = (pos >= 1 && body[pos - 1] == 0x08)
+        || (pos >= 2 && body[pos - 2] == 0x08 && body[pos - 1] == b'\\');
+    if !named || body.get(pos + 4) != Some(&0x12) {
+        return None;
+    }
+
+    // PackageOp, PkgLength, NumElements
+    let mut i = pos + 5;
+    i += ((*body.get(i)? & 0xC0) >> 6) as usize + 1;
+    i += 1;
+
+    let mut element = || -> Option<u16> {
+        let mut value = *body.get(i)?;
+        if value == 0x0A {
+            i += 1;
+            value = *body.get(i)?;
+        }
+        i += 1;
+        Some(value as u16)
+    };
+
+    let a = element()?;
+    let b = element()?;
+    Some((a << 10, b << 10))
+}
+
+fn parse_fadt(data: &[u8]) -> Fadt {
+    let flags = if data.len() >= 116 {
+        read_u32(data, 112)
+    } else {
+        0
+    };
+
+    let mut pm_timer = read_u32(data, 76) as u16;
+    if data.len() >= 220 {
+        let x_pm_timer = parse_gas(data, 208);
+        if x_pm_timer.space == SPACE_IO && x_pm_timer.address != 0 {
+            pm_timer = x_pm_timer.address as u16;
+        }
+    }
+
+    let reset = (data.len() >= 129 && flags & FADT_RESET_REG_SUPPORTED != 0)
+        .then(|| (parse_gas(data, 116), data[128]));
+
+    let mut dsdt = read_u32(data, 40) as u64;
+    if data.len() >= 148 && read_u64(data, 140) != 0 {
+        dsdt = read_u64(data, 140);
+    }
+    let s5 = table(dsdt).ok().and_then(find_s5);
+
+    Fadt {
+        sci_irq: read_u16(data, 46),
+        smi_cmd: read_u32(data, 48),
+        acpi_enable: data[52],
+        pm1a_cnt: read_u32(data, 64) as u16,
+        pm1b_cnt: read_u32(data, 68) as u16,
+        pm_timer: (pm_timer != 0).then_some(pm_timer),
+        pm_timer_32bit: flags & FADT_TMR_VAL_EXT != 0,
+        century: data.get(108).copied().unwrap_or(0),
+        reset,
+        s5,
+    }
+}
+
+fn parse_madt(data: &[u8]) -> Madt {
+    let mut madt = Madt {
+        lapic_address: read_u32(data, 36) as u64,
+        legacy_pics: read_u32(data, 40) & 1 != 0,
+        ..Default::default()
+    };
+
+    let mut i = SDT_HEADER_LEN + 8;
+    while i + 2 <= data.len() {
+        let (kind, len) = (data[i], data[i + 1] as usize);
+        if len < 2 || i + len > data.len() {
+            break;
+        }
+        let entry = &data[i..i + len];
+
+        match kind {
+            0 if len >= 8 => madt.cpus.push(Cpu {
+                acpi_id: entry[2] as u32,
+                apic_id: entry[3] as u32,
+                enabled: read_u32(entry, 4) & 0x3 != 0,
+            }),
+            1 if len >= 12 => madt.ioapics.push(IoApic {
+                id: entry[2],
+                address: read_u32(entry, 4) as u64,
+                gsi_base: read_u32(entry, 8),
+            }),
+            2 if len >= 10 => madt.overrides.push(IrqOverride {
+                source: entry[3],
+                gsi: read_u32(entry, 4),
+                flags: read_u16(entry, 8),
+            }),
+            5 if len >= 12 => madt.lapic_address = read_u64(entry, 4),
+            9 if len >= 16 => madt.cpus.push(Cpu {
+                acpi_id: read_u32(entry, 12),
+                apic_id: read_u32(entry, 4),
+                enabled: read_u32(entry, 8) & 0x3 != 0,
+            }),
+            _ => {}
+        }
+
+        i += len;
+    }
+
+    madt
+}
+
+fn parse_hpet(data: &[u8]) -> Option<Hpet> {
+    if data.len() < 56 {
+        return None;
+    }
+    let id = read_u32(data, 36);
+    let base = parse_gas(data, 40);
+
+    Some(Hpet {
+        address: base.address,
+        number: data[52],
+        timers: ((id >> 8) & 0x1F) as u8 + 1,
+        counter_64bit: id & (1 << 13) != 0,
+        vendor: (id >> 16) as u16,
+        min_tick: read_u16(data, 53),
+    })
+}
+
+fn parse_mcfg(data: &[u8]) -> Vec<McfgEntry> {
+    data.get(SDT_HEADER_LEN + 8..)
+        .unwrap_or(&[])
+        .chunks_exact(16)
+        .map(|entry| McfgEntry {
+            address: read_u64(entry, 0),
+            segment: read_u16(entry, 8),
+            start_bus: entry[10],
+            end_bus: entry[11],
+        })
+        .collect()
+}
+
+fn parse(rsdp_phys: u64) -> Result<Acpi, &'static str> {
+    let rsdp = phys_slice(rsdp_phys, 20)?;
+    if &rsdp[..8] != b"RSD PTR " || !checksum_ok(rsdp) {
+        return Err("invalid RSDP");
+    }
+
+    let revision = rsdp[15];
+    let (root, entry_size) = if revision >= 2 {
+        let rsdp = phys_slice(rsdp_phys, 36)?;
+        (table(read_u64(rsdp, 24))?, 8)
+    } else {
+        (table(read_u32(rsdp, 16) as u64)?, 4)
+    };
+
+    let mut acpi = Acpi {
+        revision,
+        oem_id: rsdp[9..15].try_into().unwrap(),
+        ..Default::default()
+    };
+
+    for entry in root[SDT_HEADER_LEN..].chunks_exact(entry_size) {
+        let phys = if entry_size == 8 {
+            read_u64(entry, 0)
+        } else {
+            read_u32(entry, 0) as u64
+        };
+        let Ok(data) = table(phys) else { continue };
+
+        acpi.tables.push(TableInfo {
+            signature: data[..4].try_into().unwrap(),
+            address: phys,
+            length: data.len() as u32,
+            revision: data[8],
+            oem_id: data[10..16].try_into().unwrap(),
+        });
+
+        match &data[..4] {
+            b"FACP" => acpi.fadt = Some(parse_fadt(data)),
+            b"APIC" => acpi.madt = Some(parse_madt(data)),
+            b"HPET" => acpi.hpet = parse_hpet(data),
+            b"MCFG" => acpi.mcfg = parse_mcfg(data),
+            _ => {}
+        }
+    }
+
+    Ok(acpi)
+}
+
+pub fn init(rsdp: u64) -> Result<&'static Acpi, &'static str> {
+    let acpi = parse(rsdp)?;
+    Ok(ACPI.call_once(|| acpi))
+}
+
+pub fn get() -> Option<&'static Acpi> {
+    ACPI.get()
+}
+
+fn text(bytes: &[u8]) -> &str {
+    core::str::from_utf8(bytes).unwrap_or("?").trim_end()
+}
+
+pub fn render() -> String {
+    let Some(acpi) = get() else {
+        return String::from("acpi: unavailable\n");
+    };
+
+    let mut out = String::new();
+    let _ = writeln!(out, "revision: {}", acpi.revision);
+    let _ = writeln!(out, "oem:      {}", text(&acpi.oem_id));
+
+    for t in &acpi.tables {
+        let _ = writeln!(
+            out,
+            "table:    {} rev {} at {:#x} ({} bytes, {})",
+            text(&t.signature),
+            t.revision,
+            t.address,
+            t.length,
+            text(&t.oem_id)
+        );
+    }
+
+    if let Some(madt) = &acpi.madt {
+        let _ = writeln!(out, "lapic:    {:#x}", madt.lapic_address);
+        for cpu in &madt.cpus {
+            let _ = writeln!(
+                out,
+                "cpu:      acpi {} apic {}{}",
+                cpu.acpi_id,
+                cpu.apic_id,
+                if cpu.enabled { "" } else { " (disabled)" }
+            );
+        }
+        for io in &madt.ioapics {
+            let _ = writeln!(
+                out,
+                "ioapic:   id {} at {:#x} gsi base {}",
+                io.id, io.address, io.gsi_base
+            );
+        }
+        for o in &madt.overrides {
+            let _ = writeln!(
+                out,
+                "override: irq {} -> gsi {} ({}, {})",
+                o.source,
+                o.gsi,
+                if o.level_triggered() { "level" } else { "edge" },
+                if o.active_low() { "low" } else { "high" }
+            );
+        }
+    }
+
+    if let Some(fadt) = &acpi.fadt {
+        let _ = writeln!(out, "sci:      irq {}", fadt.sci_irq);
+        let _ = writeln!(out, "pm1a_cnt: {:#x}", fadt.pm1a_cnt);
+        if let Some(port) = fadt.pm_timer {
+            let bits = if fadt.pm_timer_32bit { 32 } else { 24 };
+            let _ = writeln!(out, "pm_timer: {:#x} ({}-bit)", port, bits);
+        }
+        if let Some((reg, value)) = &fadt.reset {
+            let space = if reg.space == SPACE_IO { "io" } else { "mem" };
+            let _ = writeln!(out, "reset:    {} {:#x} = {:#x}", space, reg.address, value);
+        }
+        if let Some((a, b)) = fadt.s5 {
+            let _ = writeln!(out, "s5:       {:#x} {:#x}", a >> 10, b >> 10);
+        }
+        if fadt.century != 0 {
+            let _ = writeln!(out, "century:  cmos {:#x}", fadt.century);
+        }
+    }
+
+    if let Some(hpet) = &acpi.hpet {
+        let _ = writeln!(
+            out,
+            "hpet:     {:#x} {} timers, {}-bit, vendor {:#06x}, min tick {}",
+            hpet.address,
+            hpet.timers,
+            if hpet.counter_64bit { 64 } else { 32 },
+            hpet.vendor,
+            hpet.min_tick
+        );
+    }
+
+    for m in &acpi.mcfg {
+        let _ = writeln!(
+            out,
+            "ecam:     segment {} buses {}-{} at {:#x}",
+            m.segment, m.start_bus, m.end_bus, m.address
+        );
+    }
+
+    out
+}
+
+fn write_gas(reg: &GenericAddress, value: u8) {
+    match reg.space {
+        SPACE_IO => unsafe { Port::<u8>::new(reg.address as u16).write(value) },
+        SPACE_MEMORY => {
+            if let Ok(virt) = vmm::map_physical(PhysAddr::new(reg.address), 1) {
+                unsafe { virt.as_mut_ptr::<u8>().write_volatile(value) };
+            }
+        }
+        _ => {}
+    }
+}
+
+pub fn reset() -> Result<(), &'static str> {
+    let fadt = get().and_then(|a| a.fadt).ok_or("no FADT")?;
+    let (reg, value) = fadt.reset.ok_or("no ACPI reset register")?;
+    write_gas(&reg, value);
+    Ok(())
+}
+
+pub fn enter_s5() -> Result<(), &'static str> {
+    let fadt = get().and_then(|a| a.fadt).ok_or("no FADT")?;
+    let (slp_typa, slp_typb) = fadt.s5.ok_or("no \\_S5 object")?;
+    if fadt.pm1a_cnt == 0 {
+        return Err("no PM1a control block");
+    }
+
+    unsafe {
+        let mut pm1a: Port<u16> = Port::new(fadt.pm1a_cnt);
+        if pm1a.read() & SCI_EN == 0 && fadt.smi_cmd != 0 {
+            Port::<u8>::new(fadt.smi_cmd as u16).write(fadt.acpi_enable);
+            for _ in 0..1_000_000 {
+                if pm1a.read() & SCI_EN != 0 {
+                    break;
+                }
+            }
+        }
+
+        pm1a.write(slp_typa | SLP_EN);
+        if fadt.pm1b_cnt != 0 {
+            Port::<u16>::new(fadt.pm1b_cnt).write(slp_typb | SLP_EN);
+        }
+    }
+
+    Ok(())
+}
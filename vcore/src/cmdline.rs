@@ -0,0 +1,81 @@
+use alloc::{format, string::String};
+use core::sync::atomic::{AtomicU8, Ordering};
+use spin::Once;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Error = 1,
+    Warn = 2,
+    Info = 3,
+}
+
+static CMDLINE: Once<&'static str> = Once::new();
+static LOG_LEVEL: AtomicU8 = AtomicU8::new(LogLevel::Info as u8);
+
+pub fn init(raw: &'static str) {
+    CMDLINE.call_once(|| raw);
+
+    let mut level = LogLevel::Info as u8;
+    if has("quiet") {
+        level = LogLevel::Warn as u8;
+    }
+    if let Some(value) = get("loglevel").and_then(|v| v.parse::<u8>().ok()) {
+        level = value.clamp(LogLevel::Error as u8, LogLevel::Info as u8);
+    }
+    LOG_LEVEL.store(level, Ordering::Relaxed);
+}
+
+pub fn raw() -> &'static str {
+    CMDLINE.get().copied().unwrap_or("")
+}
+
+pub fn options() -> impl Iterator<Item = (&'static str, Option<&'static str>)> {
+    raw()
+        .split_whitespace()
+        .map(|opt| match opt.split_once('=') {
+            Some((key, value)) => (key, Some(value)),
+            None => (opt, None),
+        })
+}
+
+pub fn get(key: &str) -> Option<&'static str> {
+    options()
+        .filter(|(k, _)| *k == key)
+        .filter_map(|(_, v)| v)
+        .last()
+}
+
+pub fn has(flag: &str) -> bool {
+    options().any(|(k, _)| k == flag)
+}
+
+pub fn root() -> Option<&'static str> {
+    get("root")
+}
+
+pub fn init_path() -> Option<&'static str> {
+    get("init")
+}
+
+pub fn serial_console() -> bool {
+    get("console") == Some("serial")
+}
+
+// `debug_exit` alone uses qemu's default isa-debug-exit port
+pub fn debug_exit_port() -> Option<u16> {
+    if !has("debug_exit") {
+        return None;
+    }
+    match get("debug_exit") {
+        Some(port) => u16::from_str_radix(port.trim_start_matches("0x"), 16).ok(),
+        None => Some(0xf4),
+    }
+}
+
+pub fn log_enabled(level: LogLevel) -> bool {
+    level as u8 <= LOG_LEVEL.load(Ordering::Relaxed)
+}
+
+pub fn render() -> String {
+    format!("{}\n", raw())
+}
@@ -1,10 +1,13 @@
+use alloc::vec::Vec;
+use spin::Once;
 use x86_64::{PhysAddr, VirtAddr, instructions::port::Port, structures::paging::PageTableFlags};
 
 const LAPIC_VIRT: u64 = 0xFFFF_FFFF_FEE0_0000;
-const LAPIC_PHYS: u64 = 0xFEE0_0000;
+const DEFAULT_LAPIC_PHYS: u64 = 0xFEE0_0000;
 
+// each ioapic gets its own page from here on
 const IOAPIC_VIRT: u64 = 0xFFFF_FFFF_FEC0_0000;
-const IOAPIC_PHYS: u64 = 0xFEC0_0000;
+const DEFAULT_IOAPIC_PHYS: u64 = 0xFEC0_0000;
 
 pub const LAPIC_ID: u64 = 0x020;
 pub const LAPIC_EOI: u64 = 0x0B0;
@@ -26,9 +29,20 @@ const CALIBRATE_MS: u32 = 10;
 
 static mut TICKS_PER_MS: u32 = 0;
 
-use crate::{cpu::interrupts::KEYBOARD_VECTOR, mem::vmm};
+use crate::{acpi, mem::vmm, warn};
 
-use super::interrupts::{SPURIOUS_VECTOR, TIMER_VECTOR};
+use super::{
+    interrupts::{SPURIOUS_VECTOR, keyboard_handler, timer_handler},
+    irq::{self, IrqSource},
+};
+
+struct IoApic {
+    virt: u64,
+    gsi_base: u32,
+    pins: u32,
+}
+
+static IOAPICS: Once<Vec<IoApic>> = Once::new();
 
 pub fn disable_pic() {
     unsafe {
@@ -64,29 +78,114 @@ pub unsafe fn lapic_write(offset: u64, value: u32) {
     unsafe { core::ptr::write_volatile(ptr, value) };
 }
 
-pub unsafe fn ioapic_read(reg: u32) -> u32 {
-    let select = IOAPIC_VIRT as *mut u32;
-    let data = (IOAPIC_VIRT + IOAPIC_REG_DATA) as *mut u32;
+pub unsafe fn ioapic_read(base: u64, reg: u32) -> u32 {
+    let select = (base + IOAPIC_REG_SELECT) as *mut u32;
+    let data = (base + IOAPIC_REG_DATA) as *mut u32;
     unsafe { core::ptr::write_volatile(select, reg) };
     unsafe { core::ptr::read_volatile(data) }
 }
 
-pub unsafe fn ioapic_write(reg: u32, value: u32) {
-    let select = IOAPIC_VIRT as *mut u32;
-    let data = (IOAPIC_VIRT + IOAPIC_REG_DATA) as *mut u32;
+pub unsafe fn ioapic_write(base: u64, reg: u32, value: u32) {
+    let select = (base + IOAPIC_REG_SELECT) as *mut u32;
+    let data = (base + IOAPIC_REG_DATA) as *mut u32;
     unsafe { core::ptr::write_volatile(select, reg) };
     unsafe { core::ptr::write_volatile(data, value) };
 }
 
-fn ioapic_set_irq(irq: u8, vector: u8) {
-    let redtbl_reg = IOAPIC_REDTBL_BASE + (irq as u32 * 2);
+pub fn lapic_id() -> u32 {
+    unsafe { lapic_read(LAPIC_ID) >> 24 }
+}
+
+fn ioapic_for(gsi: u32) -> Result<&'static IoApic, &'static str> {
+    IOAPICS
+        .get()
+        .into_iter()
+        .flatten()
+        .find(|io| gsi >= io.gsi_base && gsi < io.gsi_base + io.pins)
+        .ok_or("no ioapic handles this gsi")
+}
+
+pub fn route_gsi(gsi: u32, vector: u8, active_low: bool, level: bool) -> Result<(), &'static str> {
+    let ioapic = ioapic_for(gsi)?;
+
+    let redtbl_reg = IOAPIC_REDTBL_BASE + (gsi - ioapic.gsi_base) * 2;
+    let mut low = vector as u32;
+    if active_low {
+        low |= 1 << 13;
+    }
+    if level {
+        low |= 1 << 15;
+    }
 
     unsafe {
-        ioapic_write(redtbl_reg, vector as u32);
-        ioapic_write(redtbl_reg + 1, 0);
+        ioapic_write(ioapic.virt, redtbl_reg + 1, 0);
+        ioapic_write(ioapic.virt, redtbl_reg, low);
+    }
+    Ok(())
+}
+
+pub fn mask_gsi(gsi: u32) {
+    if let Ok(ioapic) = ioapic_for(gsi) {
+        let redtbl_reg = IOAPIC_REDTBL_BASE + (gsi - ioapic.gsi_base) * 2;
+        unsafe { ioapic_write(ioapic.virt, redtbl_reg, 1 << 16) };
     }
 }
 
+// routes a legacy ISA irq through any MADT interrupt source override
+pub fn route_isa_irq(irq: u8, vector: u8) -> Result<u32, &'static str> {
+    let route = match acpi::get() {
+        Some(acpi) => acpi.isa_irq(irq),
+        None => acpi::IrqOverride {
+            source: irq,
+            gsi: irq as u32,
+            flags: 0,
+        },
+    };
+
+    route_gsi(
+        route.gsi,
+        vector,
+        route.active_low(),
+        route.level_triggered(),
+    )?;
+    Ok(route.gsi)
+}
+
+fn init_ioapics(flags: PageTableFlags) {
+    let found: Vec<(u64, u32)> = acpi::get()
+        .and_then(|acpi| acpi.madt.as_ref())
+        .map(|madt| {
+            madt.ioapics
+                .iter()
+                .map(|io| (io.address, io.gsi_base))
+                .collect()
+        })
+        .filter(|found: &Vec<(u64, u32)>| !found.is_empty())
+        .unwrap_or_else(|| alloc::vec![(DEFAULT_IOAPIC_PHYS, 0)]);
+
+    let mut ioapics = Vec::new();
+    for (i, (phys, gsi_base)) in found.into_iter().enumerate() {
+        let virt = IOAPIC_VIRT + (i * 0x1000) as u64;
+        if let Err(e) = vmm::map_page(VirtAddr::new(virt), PhysAddr::new(phys), flags) {
+            warn!("ioapic at {:#x}: {}", phys, e);
+            continue;
+        }
+
+        let pins = unsafe { (ioapic_read(virt, IOAPIC_VER) >> 16) & 0xFF } + 1;
+        for pin in 0..pins {
+            // masked until someone asks for the line
+            unsafe { ioapic_write(virt, IOAPIC_REDTBL_BASE + pin * 2, 1 << 16) };
+        }
+        ioapics.push(IoApic {
+            virt,
+            gsi_base,
+            pins,
+        });
+    }
+
+    IOAPICS.call_once(|| ioapics);
+}
+
 fn calibrate_timer() {
     unsafe {
         let mut pit_cmd: Port<u8> = Port::new(0x43);
@@ -160,15 +259,14 @@ pub fn init() {
 
     let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_CACHE;
 
-    vmm::map_page(VirtAddr::new(LAPIC_VIRT), PhysAddr::new(LAPIC_PHYS), flags)
+    let lapic_phys = acpi::get()
+        .and_then(|acpi| acpi.madt.as_ref())
+        .map_or(DEFAULT_LAPIC_PHYS, |madt| madt.lapic_address);
+
+    vmm::map_page(VirtAddr::new(LAPIC_VIRT), PhysAddr::new(lapic_phys), flags)
         .expect("failed to map lapic");
 
-    vmm::map_page(
-        VirtAddr::new(IOAPIC_VIRT),
-        PhysAddr::new(IOAPIC_PHYS),
-        flags,
-    )
-    .expect("failed to map ioapic");
+    init_ioapics(flags);
 
     unsafe {
         lapic_write(LAPIC_SPURIOUS, 0x100 | SPURIOUS_VECTOR as u32);
@@ -176,13 +274,18 @@ pub fn init() {
 
     calibrate_timer();
 
-    let ticks_10ms = unsafe { TICKS_PER_MS * 10 };
+    let timer_vector =
+        irq::request_irq(IrqSource::Local, "timer", timer_handler).expect("no vector for timer");
+
+    let ticks_10ms = unsafe { TICKS_PER_MS * super::TICK_MS as u32 };
     unsafe {
         lapic_write(LAPIC_TIMER_DIV, 0x3);
-        lapic_write(LAPIC_TIMER_LVT, (1 << 17) | TIMER_VECTOR as u32);
+        lapic_write(LAPIC_TIMER_LVT, (1 << 17) | timer_vector as u32);
         lapic_write(LAPIC_TIMER_INIT, ticks_10ms);
     }
 
     init_keyboard_controller();
-    ioapic_set_irq(1, KEYBOARD_VECTOR);
+    if let Err(e) = irq::request_irq(IrqSource::Isa(1), "keyboard", keyboard_handler) {
+        warn!("keyboard irq: {}", e);
+    }
 }
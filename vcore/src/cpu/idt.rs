@@ -4,10 +4,8 @@ use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame, Pag
 use crate::{
     cpu::{
         gdt::DOUBLE_FAULT_IST_INDEX,
-        interrupts::{
-            KEYBOARD_VECTOR, SPURIOUS_VECTOR, TIMER_VECTOR, keyboard_handler, spurious_handler,
-            timer_handler,
-        },
+        interrupts::{SPURIOUS_VECTOR, spurious_handler},
+        irq,
     },
     info, println,
 };
@@ -23,8 +21,7 @@ static IDT: Lazy<InterruptDescriptorTable> = Lazy::new(|| {
     }
     idt.general_protection_fault.set_handler_fn(gpf_handler);
 
-    idt[TIMER_VECTOR].set_handler_fn(timer_handler);
-    idt[KEYBOARD_VECTOR].set_handler_fn(keyboard_handler);
+    irq::install(&mut idt);
     idt[SPURIOUS_VECTOR].set_handler_fn(spurious_handler);
 
     idt
@@ -60,9 +57,24 @@ extern "x86-interrupt" fn page_fault_handler(
     stack_frame: InterruptStackFrame,
     error_code: PageFaultErrorCode,
 ) -> () {
-    use x86_64::registers::control::Cr2;
+    use x86_64::{instructions::interrupts, registers::control::Cr2, registers::rflags::RFlags};
+    let addr = Cr2::read().unwrap();
+
+    // swapping in waits on locks and the disk, which is only safe where the
+    // faulting code could have been preempted anyway
+    if !error_code.contains(PageFaultErrorCode::PROTECTION_VIOLATION)
+        && stack_frame.cpu_flags.contains(RFlags::INTERRUPT_FLAG)
+    {
+        interrupts::enable();
+        let handled = crate::mem::swap::handle_fault(addr);
+        interrupts::disable();
+        if handled {
+            return;
+        }
+    }
+
     println!("PAGE FAULT");
-    println!("  TRIED TO ACCESS 0x{:016x}", Cr2::read().unwrap().as_u64());
+    println!("  TRIED TO ACCESS 0x{:016x}", addr.as_u64());
     println!("  ERR: {:?}", error_code);
     print_stack_frame(stack_frame);
     panic!();
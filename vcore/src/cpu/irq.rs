@@ -0,0 +1,228 @@
+use alloc::{string::String, vec::Vec};
+use core::{
+    fmt::Write,
+    sync::atomic::{AtomicU64, Ordering},
+};
+use spin::Mutex;
+use x86_64::{
+    PrivilegeLevel,
+    instructions::interrupts,
+    structures::idt::{InterruptDescriptorTable, InterruptStackFrame},
+};
+
+use crate::{
+    cpu::apic::{self, LAPIC_EOI},
+    drivers::pci::{self, PciAddress},
+    sched,
+};
+
+use super::interrupts::SPURIOUS_VECTOR;
+
+const FIRST_VECTOR: u8 = 32;
+const MSI_ADDRESS_BASE: u32 = 0xFEE0_0000;
+
+#[derive(Debug, Clone, Copy)]
+pub enum IrqSource {
+    // programmed by the caller, e.g. the lapic timer
+    Local,
+    Isa(u8),
+    Gsi {
+        gsi: u32,
+        active_low: bool,
+        level: bool,
+    },
+    Msi(PciAddress),
+    MsiX(PciAddress, u16),
+}
+
+#[derive(Clone, Copy)]
+struct Irq {
+    name: &'static str,
+    source: IrqSource,
+    gsi: Option<u32>,
+    handler: fn(),
+}
+
+static IRQS: Mutex<[Option<Irq>; 256]> = Mutex::new([None; 256]);
+static COUNTS: [AtomicU64; 256] = [const { AtomicU64::new(0) }; 256];
+
+extern "x86-interrupt" fn stub<const VECTOR: u8>(stack_frame: InterruptStackFrame) {
+    dispatch(VECTOR);
+
+    // from user mode nothing in the kernel is held, so a killed task can go
+    if stack_frame.code_segment.rpl() == PrivilegeLevel::Ring3 {
+        sched::exit_if_killed();
+    }
+}
+
+fn dispatch(vector: u8) {
+    COUNTS[vector as usize].fetch_add(1, Ordering::Relaxed);
+    let handler = IRQS.lock()[vector as usize].map(|irq| irq.handler);
+
+    if let Some(handler) = handler {
+        handler();
+    }
+
+    // only once the handler has quieted the device, or a level triggered
+    // line still asserted is delivered again straight away. a handler that
+    // wants another task asks for it, and the switch happens after
+    unsafe { apic::lapic_write(LAPIC_EOI, 0) };
+    sched::preempt();
+}
+
+macro_rules! install_row {
+    ($idt:expr, $high:literal, ($($low:literal)*)) => {
+        $( $idt[$high * 16 + $low].set_handler_fn(stub::<{ $high * 16 + $low }>); )*
+    };
+}
+
+pub fn install(idt: &mut InterruptDescriptorTable) {
+    install_row!(idt, 2, (0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15));
+    install_row!(idt, 3, (0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15));
+    install_row!(idt, 4, (0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15));
+    install_row!(idt, 5, (0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15));
+    install_row!(idt, 6, (0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15));
+    install_row!(idt, 7, (0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15));
+    install_row!(idt, 8, (0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15));
+    install_row!(idt, 9, (0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15));
+    install_row!(idt, 10, (0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15));
+    install_row!(idt, 11, (0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15));
+    install_row!(idt, 12, (0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15));
+    install_row!(idt, 13, (0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15));
+    install_row!(idt, 14, (0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15));
+    install_row!(idt, 15, (0 1 2 3 4 5 6 7 8 9 10 11 12 13 14));
+}
+
+fn msi_message(vector: u8) -> (u32, u16) {
+    let apic_id = apic::lapic_id();
+    (MSI_ADDRESS_BASE | (apic_id << 12), vector as u16)
+}
+
+fn program(source: IrqSource, vector: u8) -> Result<Option<u32>, &'static str> {
+    match source {
+        IrqSource::Local => Ok(None),
+        IrqSource::Isa(irq) => apic::route_isa_irq(irq, vector).map(Some),
+        IrqSource::Gsi {
+            gsi,
+            active_low,
+            level,
+        } => apic::route_gsi(gsi, vector, active_low, level).map(|()| Some(gsi)),
+        IrqSource::Msi(address) => {
+            let dev = pci::find(address).ok_or("no such PCI device")?;
+            let (addr, data) = msi_message(vector);
+            pci::enable_msi(&dev, addr, data).map(|()| None)
+        }
+        IrqSource::MsiX(address, entry) => {
+            let dev = pci::find(address).ok_or("no such PCI device")?;
+            let (addr, data) = msi_message(vector);
+            pci::enable_msix(&dev, entry, addr, data).map(|()| None)
+        }
+    }
+}
+
+/// allocates a vector, installs `handler` on it and unmasks the source.
+/// returns the vector. the handler runs before the interrupt is acknowledged,
+/// so it asks for a task switch with `sched::request_schedule` instead of
+/// making one
+pub fn request_irq(
+    source: IrqSource,
+    name: &'static str,
+    handler: fn(),
+) -> Result<u8, &'static str> {
+    let vector = interrupts::without_interrupts(|| {
+        let mut irqs = IRQS.lock();
+        let vector = (FIRST_VECTOR..SPURIOUS_VECTOR)
+            .find(|v| irqs[*v as usize].is_none())
+            .ok_or("out of interrupt vectors")?;
+
+        irqs[vector as usize] = Some(Irq {
+            name,
+            source,
+            gsi: None,
+            handler,
+        });
+        Ok::<u8, &'static str>(vector)
+    })?;
+
+    match program(source, vector) {
+        Ok(gsi) => {
+            interrupts::without_interrupts(|| {
+                if let Some(irq) = IRQS.lock()[vector as usize].as_mut() {
+                    irq.gsi = gsi;
+                }
+            });
+            Ok(vector)
+        }
+        Err(e) => {
+            interrupts::without_interrupts(|| IRQS.lock()[vector as usize] = None);
+            Err(e)
+        }
+    }
+}
+
+pub fn free_irq(vector: u8) {
+    let irq = interrupts::without_interrupts(|| IRQS.lock()[vector as usize].take());
+    let Some(irq) = irq else { return };
+
+    match irq.source {
+        IrqSource::Isa(_) | IrqSource::Gsi { .. } => {
+            if let Some(gsi) = irq.gsi {
+                apic::mask_gsi(gsi);
+            }
+        }
+        IrqSource::Msi(address) | IrqSource::MsiX(address, _) => {
+            if let Some(dev) = pci::find(address) {
+                pci::disable_msi(&dev);
+            }
+        }
+        IrqSource::Local => {}
+    }
+    COUNTS[vector as usize].store(0, Ordering::Relaxed);
+}
+
+pub fn count(vector: u8) -> u64 {
+    COUNTS[vector as usize].load(Ordering::Relaxed)
+}
+
+pub fn render() -> String {
+    let irqs: Vec<(usize, Irq)> = interrupts::without_interrupts(|| {
+        IRQS.lock()
+            .iter()
+            .enumerate()
+            .filter_map(|(vector, irq)| irq.map(|irq| (vector, irq)))
+            .collect()
+    });
+    let mut out = String::new();
+
+    for (vector, irq) in irqs {
+        let mut source = String::new();
+        let _ = match irq.source {
+            IrqSource::Local => write!(source, "lapic"),
+            IrqSource::Isa(n) => write!(source, "isa {} gsi {}", n, irq.gsi.unwrap_or(n as u32)),
+            IrqSource::Gsi {
+                gsi,
+                active_low,
+                level,
+            } => write!(
+                source,
+                "gsi {} {} {}",
+                gsi,
+                if level { "level" } else { "edge" },
+                if active_low { "low" } else { "high" }
+            ),
+            IrqSource::Msi(address) => write!(source, "msi {}", address),
+            IrqSource::MsiX(address, entry) => write!(source, "msix {} #{}", address, entry),
+        };
+
+        let _ = writeln!(
+            out,
+            "{:>3} {:>10}  {:<24} {}",
+            vector,
+            count(vector as u8),
+            source,
+            irq.name
+        );
+    }
+
+    out
+}
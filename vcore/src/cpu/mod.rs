@@ -4,7 +4,9 @@ pub mod apic;
 pub mod gdt;
 pub mod idt;
 pub mod interrupts;
+pub mod irq;
 pub mod syscall;
+pub mod time;
 
 pub fn init() {
     gdt::init();
@@ -15,6 +17,8 @@ pub fn init() {
     info!("Syscalls loaded");
 }
 
+pub const TICK_MS: u64 = 10;
+
 pub fn ticks() -> u64 {
     interrupts::ticks()
 }
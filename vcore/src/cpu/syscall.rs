@@ -9,7 +9,14 @@ use x86_64::{
     },
 };
 
-use crate::{cpu::gdt, drivers::keyboard, error, info, print, sched, vfs};
+use crate::{
+    cpu::{self, gdt},
+    drivers::keyboard,
+    error, info,
+    mem::{shm, swap},
+    power::{self, PowerAction},
+    print, sched, vfs,
+};
 
 #[repr(C, align(16))]
 struct CpuLocal {
@@ -37,7 +44,7 @@ pub fn init() {
         .expect("failed to set STAR");
 
         LStar::write(VirtAddr::new(syscall_entry as *const () as u64));
-        SFMask::write(RFlags::empty());
+        SFMask::write(RFlags::INTERRUPT_FLAG);
 
         let efer = Efer::read();
         Efer::write(efer | EferFlags::SYSTEM_CALL_EXTENSIONS);
@@ -55,6 +62,9 @@ unsafe extern "C" fn syscall_entry() {
         "swapgs",
         "mov gs:[0], rsp",
         "mov rsp, gs:[8]",
+        "push qword ptr gs:[0]",
+        "swapgs",
+        "sti",
 
         "push rcx",
         "push r11",
@@ -99,14 +109,20 @@ unsafe extern "C" fn syscall_entry() {
         "pop r11",
         "pop rcx",
 
-        "mov rsp, gs:[0]",
-        "swapgs",
+        "cli",
+        "pop rsp",
 
         "sysretq",
         handler = sym syscall_handler,
     );
 }
 
+// the user rsp is saved on the task's own kernel stack, so a task can block
+// inside a syscall while others enter the kernel
+pub fn set_kernel_stack(top: u64) {
+    unsafe { CPU_LOCAL.kernel_rsp = top };
+}
+
 pub const SYS_EXIT: u64 = 0;
 pub const SYS_WRITE: u64 = 1;
 pub const SYS_READ: u64 = 2;
@@ -118,19 +134,97 @@ pub const SYS_UNLINK: u64 = 7;
 pub const SYS_RMDIR: u64 = 8;
 pub const SYS_CHDIR: u64 = 9;
 pub const SYS_GETCWD: u64 = 10;
+pub const SYS_SHM_OPEN: u64 = 11;
+pub const SYS_SHM_UNLINK: u64 = 12;
+pub const SYS_SHM_MAP: u64 = 13;
+pub const SYS_SHM_UNMAP: u64 = 14;
+pub const SYS_SWAPON: u64 = 15;
+pub const SYS_SWAPOFF: u64 = 16;
+pub const SYS_SPAWN: u64 = 17;
+pub const SYS_WAIT: u64 = 18;
+pub const SYS_KILL: u64 = 19;
+pub const SYS_REBOOT: u64 = 20;
+pub const SYS_CLOCK_GETTIME: u64 = 21;
+pub const SYS_NANOSLEEP: u64 = 22;
+pub const SYS_MOUNT: u64 = 23;
+pub const SYS_UMOUNT: u64 = 24;
+pub const SYS_RENAME: u64 = 25;
+pub const SYS_SYMLINK: u64 = 26;
+pub const SYS_READLINK: u64 = 27;
+pub const SYS_LINK: u64 = 28;
+pub const SYS_CHMOD: u64 = 29;
+pub const SYS_CHOWN: u64 = 30;
+pub const SYS_GETUID: u64 = 31;
+pub const SYS_SETUID: u64 = 32;
+pub const SYS_UTIMENS: u64 = 33;
+pub const SYS_STAT: u64 = 34;
+pub const SYS_LSTAT: u64 = 35;
+pub const SYS_FTRUNCATE: u64 = 36;
+pub const SYS_TRUNCATE: u64 = 37;
+pub const SYS_FSYNC: u64 = 38;
+pub const SYS_SYNC: u64 = 39;
+pub const SYS_STATFS: u64 = 40;
+
+// for SYS_UTIMENS, in place of a time
+pub const UTIME_NOW: u64 = u64::MAX;
+pub const UTIME_OMIT: u64 = u64::MAX - 1;
+
+// what SYS_RENAME returns when `from` and `to` are on different mounts
+pub const EXDEV: u64 = u64::MAX - 1;
+
+/// what SYS_STAT fills in. times are nanoseconds since the unix epoch
+#[repr(C)]
+pub struct Stat {
+    pub file_type: u64,
+    pub size: u64,
+    pub uid: u32,
+    pub gid: u32,
+    pub mode: u32,
+    _pad: u32,
+    pub atime: u64,
+    pub mtime: u64,
+    pub ctime: u64,
+}
+
+/// what SYS_STATFS fills in. sizes are in blocks of `block_size` bytes
+#[repr(C)]
+pub struct StatFs {
+    /// nul padded
+    pub fs_type: [u8; 16],
+    pub block_size: u64,
+    pub total_blocks: u64,
+    pub free_blocks: u64,
+    pub max_name_len: u64,
+}
+
+fn file_type_code(file_type: vfs::FileType) -> u8 {
+    match file_type {
+        vfs::FileType::File => 1,
+        vfs::FileType::Directory => 2,
+        vfs::FileType::Device => 3,
+        vfs::FileType::Symlink => 4,
+    }
+}
 
+// a killed task leaves here, with whatever it locked on the way in let go
 extern "C" fn syscall_handler(
     num: u64,
     arg1: u64,
     arg2: u64,
     arg3: u64,
-    _arg4: u64,
-    _arg5: u64,
+    arg4: u64,
+    arg5: u64,
 ) -> u64 {
+    let ret = dispatch(num, arg1, arg2, arg3, arg4, arg5);
+    sched::exit_if_killed();
+    ret
+}
+
+fn dispatch(num: u64, arg1: u64, arg2: u64, arg3: u64, arg4: u64, _arg5: u64) -> u64 {
     match num {
         SYS_EXIT => {
             info!("task exited with code {}", arg1);
-            sched::exit();
+            sched::exit(arg1);
             0
         }
 
@@ -170,8 +264,15 @@ extern "C" fn syscall_handler(
             let buf = arg2 as *mut u8;
             let len = arg3 as usize;
 
-            if fd == 0 {
+            // waited on outside the fd table, which holds the scheduler
+            let stdin = fd == 0
+                || sched::with_fd_table(|table| Ok(matches!(table.get(fd)?, vfs::FdKind::Stdin)))
+                    .unwrap_or(false);
+            if stdin {
                 while !keyboard::has_input() {
+                    if sched::kill_pending() {
+                        return 0;
+                    }
                     x86_64::instructions::hlt();
                 }
 
@@ -195,24 +296,6 @@ extern "C" fn syscall_handler(
                     let slice = unsafe { core::slice::from_raw_parts_mut(buf, len) };
                     handle.read(slice)
                 }
-                vfs::FdKind::Stdin => {
-                    while !keyboard::has_input() {
-                        x86_64::instructions::hlt();
-                    }
-                    let mut count = 0;
-                    while count < len {
-                        if let Some(c) = keyboard::read_char() {
-                            unsafe { *buf.add(count) = c };
-                            count += 1;
-                            if c == b'\n' {
-                                break;
-                            }
-                        } else {
-                            break;
-                        }
-                    }
-                    Ok(count)
-                }
                 _ => Err(vfs::VfsError::PermissionDenied),
             });
 
@@ -287,12 +370,7 @@ extern "C" fn syscall_handler(
                         }
 
                         unsafe {
-                            let file_type: u8 = match entry.file_type {
-                                vfs::FileType::File => 1,
-                                vfs::FileType::Directory => 2,
-                                vfs::FileType::Device => 3,
-                            };
-                            *buf_ptr.add(offset) = file_type;
+                            *buf_ptr.add(offset) = file_type_code(entry.file_type);
                             offset += 1;
 
                             let name_len = name_bytes.len() as u16;
@@ -387,6 +465,9 @@ extern "C" fn syscall_handler(
 
             match vfs::metadata(&path) {
                 Ok(meta) if meta.file_type == vfs::FileType::Directory => {
+                    let Ok(path) = vfs::canonicalize(&path) else {
+                        return u64::MAX;
+                    };
                     if sched::set_cwd(path).is_ok() {
                         0
                     } else {
@@ -414,6 +495,479 @@ extern "C" fn syscall_handler(
             }
         }
 
+        SYS_SHM_OPEN => {
+            let name_ptr = arg1 as *const u8;
+            let name_len = arg2 as usize;
+            let flags = vfs::OpenFlags::from_bits(arg3 as u32);
+            let size = arg4 as usize;
+
+            let name = unsafe {
+                let slice = core::slice::from_raw_parts(name_ptr, name_len);
+                core::str::from_utf8_unchecked(slice)
+            };
+
+            let object = match shm::open(
+                name,
+                flags.contains(vfs::OpenFlags::O_CREAT),
+                flags.contains(vfs::OpenFlags::O_EXCL),
+            ) {
+                Ok(object) => object,
+                Err(_) => return u64::MAX,
+            };
+
+            if size > object.size() && object.resize(size).is_err() {
+                return u64::MAX;
+            }
+
+            let result = sched::with_fd_table(|table| table.alloc(vfs::FdKind::Shm(object)));
+            result.map(|fd| fd as u64).unwrap_or(u64::MAX)
+        }
+
+        SYS_SHM_UNLINK => {
+            let name_ptr = arg1 as *const u8;
+            let name_len = arg2 as usize;
+
+            let name = unsafe {
+                let slice = core::slice::from_raw_parts(name_ptr, name_len);
+                core::str::from_utf8_unchecked(slice)
+            };
+
+            match shm::unlink(name) {
+                Ok(()) => 0,
+                Err(_) => u64::MAX,
+            }
+        }
+
+        SYS_SHM_MAP => {
+            let fd = arg1 as usize;
+            let hint = arg2;
+
+            let object = sched::with_fd_table(|table| match table.get(fd)? {
+                vfs::FdKind::Shm(object) => Ok(object.clone()),
+                _ => Err(vfs::VfsError::InvalidFd),
+            });
+
+            let Ok(object) = object else {
+                return u64::MAX;
+            };
+
+            let result = sched::with_current_task(|task| {
+                let address_space = task.address_space.as_ref().ok_or("no address space")?;
+                shm::map(address_space, &mut task.shm_maps, object, hint)
+            });
+
+            match result {
+                Some(Ok(addr)) => addr,
+                _ => u64::MAX,
+            }
+        }
+
+        SYS_SHM_UNMAP => {
+            let addr = arg1;
+
+            let result = sched::with_current_task(|task| {
+                let address_space = task.address_space.as_ref().ok_or("no address space")?;
+                shm::unmap(address_space, &mut task.shm_maps, addr)
+            });
+
+            match result {
+                Some(Ok(())) => 0,
+                _ => u64::MAX,
+            }
+        }
+
+        SYS_SWAPON | SYS_SWAPOFF if !sched::credentials().is_root() => u64::MAX,
+
+        SYS_SWAPON | SYS_SWAPOFF => {
+            let path_ptr = arg1 as *const u8;
+            let path_len = arg2 as usize;
+
+            let path = unsafe {
+                let slice = core::slice::from_raw_parts(path_ptr, path_len);
+                core::str::from_utf8_unchecked(slice)
+            };
+
+            let cwd = sched::get_cwd().unwrap_or_else(|| "/".into());
+            let path = vfs::resolve_path(path, &cwd);
+
+            let result = if num == SYS_SWAPON {
+                swap::swapon(&path)
+            } else {
+                swap::swapoff(&path)
+            };
+
+            match result {
+                Ok(()) => 0,
+                Err(e) => {
+                    error!("{}: {}", path, e);
+                    u64::MAX
+                }
+            }
+        }
+
+        SYS_SPAWN => {
+            let path_ptr = arg1 as *const u8;
+            let path_len = arg2 as usize;
+
+            let path = unsafe {
+                let slice = core::slice::from_raw_parts(path_ptr, path_len);
+                core::str::from_utf8_unchecked(slice)
+            };
+
+            let cwd = sched::get_cwd().unwrap_or_else(|| "/".into());
+            let path = vfs::resolve_path(path, &cwd);
+
+            match sched::spawn_path(&path) {
+                Ok(pid) => pid,
+                Err(e) => {
+                    error!("{}: {}", path, e);
+                    u64::MAX
+                }
+            }
+        }
+
+        SYS_WAIT => {
+            let pid = (arg1 != u64::MAX).then_some(arg1);
+            let status_ptr = arg2 as *mut u64;
+            let timeout = (arg3 != u64::MAX).then(|| arg3.div_ceil(cpu::TICK_MS));
+
+            match sched::wait(pid, timeout) {
+                Ok(Some(status)) => {
+                    if !status_ptr.is_null() {
+                        unsafe { *status_ptr = status.code };
+                    }
+                    status.pid
+                }
+                Ok(None) => 0,
+                Err(_) => u64::MAX,
+            }
+        }
+
+        SYS_KILL => match sched::kill(arg1) {
+            Ok(()) => 0,
+            Err(_) => u64::MAX,
+        },
+
+        SYS_REBOOT if !sched::credentials().is_root() => u64::MAX,
+
+        SYS_REBOOT => match PowerAction::from_u64(arg1) {
+            Some(action) => power::shutdown(action),
+            None => u64::MAX,
+        },
+
+        SYS_CLOCK_GETTIME => {
+            let ts_ptr = arg2 as *mut [u64; 2];
+            match cpu::time::clock_gettime(arg1) {
+                Some(ns) if !ts_ptr.is_null() => {
+                    unsafe { *ts_ptr = [ns / 1_000_000_000, ns % 1_000_000_000] };
+                    0
+                }
+                _ => u64::MAX,
+            }
+        }
+
+        SYS_NANOSLEEP => {
+            cpu::time::nanosleep(arg1);
+            0
+        }
+
+        SYS_MOUNT | SYS_UMOUNT if !sched::credentials().is_root() => u64::MAX,
+
+        SYS_MOUNT => {
+            // fstype, source and target as (pointer, length) pairs
+            let [fs_type, source, target] = unsafe {
+                let args = &*(arg1 as *const [[u64; 2]; 3]);
+                args.map(|[ptr, len]| {
+                    let slice = core::slice::from_raw_parts(ptr as *const u8, len as usize);
+                    core::str::from_utf8_unchecked(slice)
+                })
+            };
+            let flags = vfs::MountFlags::from_bits(arg2 as u32);
+
+            let cwd = sched::get_cwd().unwrap_or_else(|| "/".into());
+            let target = vfs::resolve_path(target, &cwd);
+            let source = (!source.is_empty()).then(|| vfs::resolve_path(source, &cwd));
+
+            let fs = match vfs::fstype::create(fs_type, source.as_deref(), flags) {
+                Ok(fs) => fs,
+                Err(e) => {
+                    error!("mount {}: {}", target, e);
+                    return u64::MAX;
+                }
+            };
+
+            match vfs::mount_from(&target, fs, source.as_deref().unwrap_or("none"), flags) {
+                Ok(()) => 0,
+                Err(_) => u64::MAX,
+            }
+        }
+
+        SYS_RENAME => {
+            let (from, to) = unsafe {
+                let from = core::slice::from_raw_parts(arg1 as *const u8, arg2 as usize);
+                let to = core::slice::from_raw_parts(arg3 as *const u8, arg4 as usize);
+                (
+                    core::str::from_utf8_unchecked(from),
+                    core::str::from_utf8_unchecked(to),
+                )
+            };
+
+            let cwd = sched::get_cwd().unwrap_or_else(|| "/".into());
+            let from = vfs::resolve_path(from, &cwd);
+            let to = vfs::resolve_path(to, &cwd);
+
+            match vfs::rename(&from, &to) {
+                Ok(()) => 0,
+                Err(vfs::VfsError::CrossDevice) => EXDEV,
+                Err(_) => u64::MAX,
+            }
+        }
+
+        SYS_SYMLINK | SYS_LINK => {
+            let (target, path) = unsafe {
+                let target = core::slice::from_raw_parts(arg1 as *const u8, arg2 as usize);
+                let path = core::slice::from_raw_parts(arg3 as *const u8, arg4 as usize);
+                (
+                    core::str::from_utf8_unchecked(target),
+                    core::str::from_utf8_unchecked(path),
+                )
+            };
+
+            let cwd = sched::get_cwd().unwrap_or_else(|| "/".into());
+            let path = vfs::resolve_path(path, &cwd);
+
+            // a symlink's target is stored as given and resolved when followed
+            let result = if num == SYS_SYMLINK {
+                vfs::symlink(target, &path)
+            } else {
+                vfs::link(&vfs::resolve_path(target, &cwd), &path)
+            };
+
+            match result {
+                Ok(()) => 0,
+                Err(_) => u64::MAX,
+            }
+        }
+
+        SYS_READLINK => {
+            let path_ptr = arg1 as *const u8;
+            let path_len = arg2 as usize;
+            let buf_ptr = arg3 as *mut u8;
+            let buf_len = arg4 as usize;
+
+            let path = unsafe {
+                let slice = core::slice::from_raw_parts(path_ptr, path_len);
+                core::str::from_utf8_unchecked(slice)
+            };
+
+            let cwd = sched::get_cwd().unwrap_or_else(|| "/".into());
+            let path = vfs::resolve_path(path, &cwd);
+
+            match vfs::readlink(&path) {
+                Ok(target) => {
+                    let len = target.len().min(buf_len);
+                    unsafe {
+                        core::ptr::copy_nonoverlapping(target.as_ptr(), buf_ptr, len);
+                    }
+                    len as u64
+                }
+                Err(_) => u64::MAX,
+            }
+        }
+
+        SYS_UMOUNT => {
+            let path_ptr = arg1 as *const u8;
+            let path_len = arg2 as usize;
+
+            let path = unsafe {
+                let slice = core::slice::from_raw_parts(path_ptr, path_len);
+                core::str::from_utf8_unchecked(slice)
+            };
+
+            let cwd = sched::get_cwd().unwrap_or_else(|| "/".into());
+            let path = vfs::resolve_path(path, &cwd);
+
+            match vfs::unmount(&path) {
+                Ok(()) => 0,
+                Err(_) => u64::MAX,
+            }
+        }
+
+        SYS_CHMOD | SYS_CHOWN => {
+            let path = unsafe {
+                let slice = core::slice::from_raw_parts(arg1 as *const u8, arg2 as usize);
+                core::str::from_utf8_unchecked(slice)
+            };
+
+            let cwd = sched::get_cwd().unwrap_or_else(|| "/".into());
+            let path = vfs::resolve_path(path, &cwd);
+
+            let result = if num == SYS_CHMOD {
+                vfs::chmod(&path, arg3 as u32)
+            } else {
+                vfs::chown(&path, arg3 as u32, arg4 as u32)
+            };
+
+            match result {
+                Ok(()) => 0,
+                Err(_) => u64::MAX,
+            }
+        }
+
+        SYS_UTIMENS => {
+            let path = unsafe {
+                let slice = core::slice::from_raw_parts(arg1 as *const u8, arg2 as usize);
+                core::str::from_utf8_unchecked(slice)
+            };
+
+            let cwd = sched::get_cwd().unwrap_or_else(|| "/".into());
+            let path = vfs::resolve_path(path, &cwd);
+
+            let time = |t| match t {
+                UTIME_NOW => vfs::SetTime::Now,
+                UTIME_OMIT => vfs::SetTime::Omit,
+                ns => vfs::SetTime::At(ns),
+            };
+
+            match vfs::utimens(&path, time(arg3), time(arg4)) {
+                Ok(()) => 0,
+                Err(_) => u64::MAX,
+            }
+        }
+
+        SYS_STAT | SYS_LSTAT => {
+            let path = unsafe {
+                let slice = core::slice::from_raw_parts(arg1 as *const u8, arg2 as usize);
+                core::str::from_utf8_unchecked(slice)
+            };
+            let stat_ptr = arg3 as *mut Stat;
+
+            let cwd = sched::get_cwd().unwrap_or_else(|| "/".into());
+            let path = vfs::resolve_path(path, &cwd);
+
+            let meta = if num == SYS_STAT {
+                vfs::metadata(&path)
+            } else {
+                vfs::symlink_metadata(&path)
+            };
+
+            match meta {
+                Ok(meta) if !stat_ptr.is_null() => {
+                    unsafe {
+                        *stat_ptr = Stat {
+                            file_type: file_type_code(meta.file_type) as u64,
+                            size: meta.size as u64,
+                            uid: meta.uid,
+                            gid: meta.gid,
+                            mode: meta.mode,
+                            _pad: 0,
+                            atime: meta.atime,
+                            mtime: meta.mtime,
+                            ctime: meta.ctime,
+                        };
+                    }
+                    0
+                }
+                _ => u64::MAX,
+            }
+        }
+
+        SYS_FTRUNCATE => {
+            let fd = arg1 as usize;
+            let result = sched::with_fd_table(|table| match table.get_mut(fd)? {
+                vfs::FdKind::File(handle) => handle.set_len(arg2 as usize),
+                _ => Err(vfs::VfsError::InvalidFd),
+            });
+            match result {
+                Ok(()) => 0,
+                Err(_) => u64::MAX,
+            }
+        }
+
+        SYS_TRUNCATE => {
+            let path = unsafe {
+                let slice = core::slice::from_raw_parts(arg1 as *const u8, arg2 as usize);
+                core::str::from_utf8_unchecked(slice)
+            };
+
+            let cwd = sched::get_cwd().unwrap_or_else(|| "/".into());
+            let path = vfs::resolve_path(path, &cwd);
+
+            match vfs::truncate(&path, arg3 as usize) {
+                Ok(()) => 0,
+                Err(_) => u64::MAX,
+            }
+        }
+
+        SYS_FSYNC => {
+            let fd = arg1 as usize;
+            let result = sched::with_fd_table(|table| match table.get_mut(fd)? {
+                vfs::FdKind::File(handle) => handle.sync(),
+                _ => Err(vfs::VfsError::InvalidFd),
+            });
+            match result {
+                Ok(()) => 0,
+                Err(_) => u64::MAX,
+            }
+        }
+
+        SYS_SYNC => match vfs::sync() {
+            Ok(()) => 0,
+            Err(_) => u64::MAX,
+        },
+
+        SYS_STATFS => {
+            let path = unsafe {
+                let slice = core::slice::from_raw_parts(arg1 as *const u8, arg2 as usize);
+                core::str::from_utf8_unchecked(slice)
+            };
+            let statfs_ptr = arg3 as *mut StatFs;
+
+            let cwd = sched::get_cwd().unwrap_or_else(|| "/".into());
+            let path = vfs::resolve_path(path, &cwd);
+
+            match vfs::statfs(&path) {
+                Ok(stats) if !statfs_ptr.is_null() => {
+                    let mut fs_type = [0u8; 16];
+                    let name = stats.fs_type.as_bytes();
+                    let len = name.len().min(fs_type.len());
+                    fs_type[..len].copy_from_slice(&name[..len]);
+                    unsafe {
+                        *statfs_ptr = StatFs {
+                            fs_type,
+                            block_size: stats.block_size as u64,
+                            total_blocks: stats.total_blocks,
+                            free_blocks: stats.free_blocks,
+                            max_name_len: stats.max_name_len as u64,
+                        };
+                    }
+                    0
+                }
+                _ => u64::MAX,
+            }
+        }
+
+        SYS_GETUID => {
+            let creds = sched::credentials();
+            (creds.gid as u64) << 32 | creds.uid as u64
+        }
+
+        // only root changes who it is, and gives up its groups doing so
+        SYS_SETUID => {
+            if !sched::credentials().is_root() {
+                return u64::MAX;
+            }
+            let creds = sched::task::Credentials {
+                uid: arg1 as u32,
+                gid: arg2 as u32,
+                groups: alloc::vec::Vec::new(),
+            };
+            match sched::set_credentials(creds) {
+                Ok(()) => 0,
+                Err(()) => u64::MAX,
+            }
+        }
+
         _ => {
             error!("unknown syscall: {}", num);
             u64::MAX
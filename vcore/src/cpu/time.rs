@@ -0,0 +1,218 @@
+use core::{
+    arch::x86_64::{__cpuid, _rdtsc},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use spin::Once;
+use x86_64::{PhysAddr, instructions::port::Port};
+
+use crate::{acpi, drivers::rtc, info, mem::vmm, warn};
+
+const NS_PER_SEC: u64 = 1_000_000_000;
+const NS_PER_MS: u64 = 1_000_000;
+const FS_PER_NS: u64 = 1_000_000;
+
+const PIT_FREQ: u64 = 1193182;
+// the pit's 16-bit counter tops out at ~54ms
+const CALIBRATE_MS: u64 = 50;
+
+const HPET_CAPABILITIES: usize = 0x000;
+const HPET_CONFIG: usize = 0x010;
+const HPET_COUNTER: usize = 0x0F0;
+const HPET_ENABLE: u64 = 1;
+
+pub const CLOCK_REALTIME: u64 = 0;
+pub const CLOCK_MONOTONIC: u64 = 1;
+
+#[derive(Debug, Clone, Copy)]
+enum ClockSource {
+    Tsc { hz: u64, base: u64 },
+    Hpet { hpet: Hpet, base: u64 },
+    Ticks,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Hpet {
+    virt: u64,
+    period_fs: u64,
+    counter_64bit: bool,
+}
+
+impl Hpet {
+    fn map() -> Option<Self> {
+        let info = acpi::get()?.hpet.as_ref()?;
+        let virt = vmm::map_mmio(PhysAddr::new(info.address), 0x400)
+            .ok()?
+            .as_u64();
+        let mut hpet = Self {
+            virt,
+            period_fs: 0,
+            counter_64bit: info.counter_64bit,
+        };
+
+        let period_fs = hpet.read(HPET_CAPABILITIES) >> 32;
+        if period_fs == 0 || period_fs > 100_000_000 {
+            return None;
+        }
+        let config = hpet.read(HPET_CONFIG);
+        hpet.write(HPET_CONFIG, config | HPET_ENABLE);
+
+        hpet.period_fs = period_fs;
+        Some(hpet)
+    }
+
+    fn read(&self, reg: usize) -> u64 {
+        unsafe { ((self.virt as usize + reg) as *const u64).read_volatile() }
+    }
+
+    fn write(&self, reg: usize, value: u64) {
+        unsafe { ((self.virt as usize + reg) as *mut u64).write_volatile(value) }
+    }
+
+    fn counter(&self) -> u64 {
+        self.read(HPET_COUNTER)
+    }
+}
+
+static CLOCK: Once<ClockSource> = Once::new();
+static BOOT_REALTIME_NS: AtomicU64 = AtomicU64::new(0);
+
+fn rdtsc() -> u64 {
+    unsafe { _rdtsc() }
+}
+
+fn invariant_tsc() -> bool {
+    let max_ext = __cpuid(0x8000_0000).eax;
+    max_ext >= 0x8000_0007 && __cpuid(0x8000_0007).edx & (1 << 8) != 0
+}
+
+fn calibrate_with_hpet(hpet: &Hpet) -> u64 {
+    // 32-bit counters wrap, but not within a calibration window
+    let wait = CALIBRATE_MS * NS_PER_MS * FS_PER_NS / hpet.period_fs;
+    let start = hpet.counter() as u32;
+    let tsc_start = rdtsc();
+    while (hpet.counter() as u32).wrapping_sub(start) < wait as u32 {
+        core::hint::spin_loop();
+    }
+    let tsc_end = rdtsc();
+    (tsc_end - tsc_start) * 1000 / CALIBRATE_MS
+}
+
+fn calibrate_with_pit() -> u64 {
+    unsafe {
+        let mut pit_cmd: Port<u8> = Port::new(0x43);
+        let mut pit_ch2: Port<u8> = Port::new(0x42);
+        let mut pit_gate: Port<u8> = Port::new(0x61);
+
+        let divisor = PIT_FREQ * CALIBRATE_MS / 1000;
+
+        // gate low, speaker off
+        let gate = pit_gate.read() & !0x02;
+        pit_gate.write(gate & !0x01);
+
+        pit_cmd.write(0b10110000);
+        pit_ch2.write((divisor & 0xFF) as u8);
+        pit_ch2.write((divisor >> 8) as u8);
+
+        pit_gate.write(gate | 0x01);
+        let tsc_start = rdtsc();
+        while pit_gate.read() & 0x20 == 0 {
+            core::hint::spin_loop();
+        }
+        let tsc_end = rdtsc();
+        pit_gate.write(gate & !0x01);
+
+        (tsc_end - tsc_start) * 1000 / CALIBRATE_MS
+    }
+}
+
+fn ticks_to_ns(ticks: u64, hz: u64) -> u64 {
+    (ticks as u128 * NS_PER_SEC as u128 / hz as u128) as u64
+}
+
+pub fn init() {
+    let hpet = Hpet::map();
+
+    let source = if invariant_tsc() {
+        let hz = match &hpet {
+            Some(hpet) => calibrate_with_hpet(hpet),
+            None => calibrate_with_pit(),
+        };
+        ClockSource::Tsc { hz, base: rdtsc() }
+    } else if let Some(hpet) = hpet.filter(|h| h.counter_64bit) {
+        warn!("TSC is not invariant, using the HPET as clock source");
+        ClockSource::Hpet {
+            hpet,
+            base: hpet.counter(),
+        }
+    } else {
+        warn!(
+            "no usable TSC or HPET, clock resolution is {}ms",
+            super::TICK_MS
+        );
+        ClockSource::Ticks
+    };
+
+    match source {
+        ClockSource::Tsc { hz, .. } => info!("clock source: tsc at {} MHz", hz / 1_000_000),
+        ClockSource::Hpet { hpet, .. } => {
+            info!(
+                "clock source: hpet at {} kHz",
+                NS_PER_SEC * FS_PER_NS / hpet.period_fs / 1000
+            )
+        }
+        ClockSource::Ticks => {}
+    }
+    CLOCK.call_once(|| source);
+
+    match rtc::read() {
+        Ok(time) => {
+            let now = time.to_unix() * NS_PER_SEC;
+            BOOT_REALTIME_NS.store(now.saturating_sub(monotonic_now()), Ordering::Relaxed);
+            info!(
+                "RTC {:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+                time.year, time.month, time.day, time.hour, time.minute, time.second
+            );
+        }
+        Err(e) => warn!("RTC: {}", e),
+    }
+}
+
+/// nanoseconds since the clock was initialised
+pub fn monotonic_now() -> u64 {
+    match CLOCK.get() {
+        Some(ClockSource::Tsc { hz, base }) => ticks_to_ns(rdtsc().wrapping_sub(*base), *hz),
+        Some(ClockSource::Hpet { hpet, base }) => {
+            let elapsed = hpet.counter().wrapping_sub(*base);
+            (elapsed as u128 * hpet.period_fs as u128 / FS_PER_NS as u128) as u64
+        }
+        Some(ClockSource::Ticks) | None => super::ticks() * super::TICK_MS * NS_PER_MS,
+    }
+}
+
+/// nanoseconds since the unix epoch, as far as the rtc knows
+pub fn realtime_now() -> u64 {
+    BOOT_REALTIME_NS.load(Ordering::Relaxed) + monotonic_now()
+}
+
+pub fn clock_gettime(clock: u64) -> Option<u64> {
+    match clock {
+        CLOCK_REALTIME => Some(realtime_now()),
+        CLOCK_MONOTONIC => Some(monotonic_now()),
+        _ => None,
+    }
+}
+
+pub fn nanosleep(ns: u64) {
+    let deadline = monotonic_now().saturating_add(ns);
+
+    // whole ticks go to the scheduler, the remainder is yielded away
+    let tick_ns = super::TICK_MS * NS_PER_MS;
+    let ticks = ns / tick_ns;
+    if ticks > 0 {
+        crate::sched::sleep(ticks);
+    }
+    while monotonic_now() < deadline && !crate::sched::kill_pending() {
+        crate::sched::yield_now();
+    }
+}
@@ -1,6 +1,8 @@
 use spin::Mutex;
 use x86_64::instructions::port::{Port, PortReadOnly, PortWriteOnly};
 
+use super::pci::{self, PciDevice, PciDriver, PciMatch};
+
 const ATA_PRIMARY_DATA: u16 = 0x1F0;
 const ATA_PRIMARY_ERROR: u16 = 0x1F1;
 const ATA_PRIMARY_SECTOR_COUNT: u16 = 0x1F2;
@@ -14,6 +16,7 @@ const ATA_PRIMARY_STATUS: u16 = 0x1F7;
 const ATA_CMD_READ_SECTORS: u8 = 0x20;
 const ATA_CMD_WRITE_SECTORS: u8 = 0x30;
 const ATA_CMD_IDENTIFY: u8 = 0xEC;
+const ATA_CMD_FLUSH_CACHE: u8 = 0xE7;
 
 const ATA_SR_BSY: u8 = 0x80;
 const ATA_SR_DRDY: u8 = 0x40;
@@ -120,6 +123,21 @@ impl AtaDrive {
         Ok(())
     }
 
+    pub fn flush(&mut self) -> Result<(), &'static str> {
+        self.wait_ready()?;
+
+        unsafe {
+            self.drive_select.write(0xE0);
+            self.command.write(ATA_CMD_FLUSH_CACHE);
+        }
+
+        self.wait_ready()?;
+        if unsafe { self.status.read() } & ATA_SR_ERR != 0 {
+            return Err("ATA cache flush failed");
+        }
+        Ok(())
+    }
+
     fn identify(&mut self) -> Result<[u16; 256], &'static str> {
         self.wait_ready()?;
 
@@ -177,3 +195,30 @@ pub fn write_sector(lba: u32, buffer: &[u8; 512]) -> Result<(), &'static str> {
         .ok_or("ATA not initialized")?
         .write_sector(lba, buffer)
 }
+
+pub fn flush() -> Result<(), &'static str> {
+    ATA.lock().as_mut().ok_or("ATA not initialized")?.flush()
+}
+
+pub fn is_present() -> bool {
+    ATA.lock().is_some()
+}
+
+static PCI_DRIVER: PciDriver = PciDriver {
+    name: "ata",
+    matches: &[PciMatch::class(0x01, 0x01)],
+    probe,
+};
+
+fn probe(dev: &PciDevice) -> Result<(), &'static str> {
+    // prog_if bit 0 means the primary channel moved off the legacy ports
+    if dev.prog_if & 0x01 != 0 {
+        return Err("native mode IDE is not supported");
+    }
+    dev.set_command(pci::COMMAND_IO, 0);
+    init()
+}
+
+pub fn register() {
+    pci::register_driver(&PCI_DRIVER);
+}
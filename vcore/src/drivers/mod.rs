@@ -1,2 +1,5 @@
 pub mod ata;
 pub mod keyboard;
+pub mod pci;
+pub mod rtc;
+pub mod serial;
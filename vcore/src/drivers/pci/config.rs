@@ -0,0 +1,79 @@
+use spin::Mutex;
+use x86_64::{PhysAddr, instructions::port::Port};
+
+use crate::{acpi, mem::vmm};
+
+use super::PciAddress;
+
+const CONFIG_ADDRESS: u16 = 0xCF8;
+const CONFIG_DATA: u16 = 0xCFC;
+
+// the two legacy ports are one shared register pair
+static LEGACY: Mutex<()> = Mutex::new(());
+
+fn ecam_base(addr: PciAddress) -> Option<u64> {
+    let entry = acpi::get()?
+        .mcfg
+        .iter()
+        .find(|m| m.segment == addr.segment && (m.start_bus..=m.end_bus).contains(&addr.bus))?;
+
+    let offset = ((addr.bus - entry.start_bus) as u64) << 20
+        | (addr.device as u64) << 15
+        | (addr.function as u64) << 12;
+    vmm::map_mmio(PhysAddr::new(entry.address + offset), 4096)
+        .ok()
+        .map(|virt| virt.as_u64())
+}
+
+fn legacy_address(addr: PciAddress, offset: u16) -> u32 {
+    0x8000_0000
+        | (addr.bus as u32) << 16
+        | (addr.device as u32) << 11
+        | (addr.function as u32) << 8
+        | (offset as u32 & 0xFC)
+}
+
+pub fn read32(addr: PciAddress, offset: u16) -> u32 {
+    if let Some(base) = ecam_base(addr) {
+        return unsafe { ((base + offset as u64) as *const u32).read_volatile() };
+    }
+    if addr.segment != 0 || offset >= 256 {
+        return u32::MAX;
+    }
+
+    let _guard = LEGACY.lock();
+    unsafe {
+        Port::<u32>::new(CONFIG_ADDRESS).write(legacy_address(addr, offset));
+        Port::<u32>::new(CONFIG_DATA).read()
+    }
+}
+
+pub fn write32(addr: PciAddress, offset: u16, value: u32) {
+    if let Some(base) = ecam_base(addr) {
+        unsafe { ((base + offset as u64) as *mut u32).write_volatile(value) };
+        return;
+    }
+    if addr.segment != 0 || offset >= 256 {
+        return;
+    }
+
+    let _guard = LEGACY.lock();
+    unsafe {
+        Port::<u32>::new(CONFIG_ADDRESS).write(legacy_address(addr, offset));
+        Port::<u32>::new(CONFIG_DATA).write(value);
+    }
+}
+
+pub fn read16(addr: PciAddress, offset: u16) -> u16 {
+    (read32(addr, offset & !3) >> ((offset & 2) * 8)) as u16
+}
+
+pub fn read8(addr: PciAddress, offset: u16) -> u8 {
+    (read32(addr, offset & !3) >> ((offset & 3) * 8)) as u8
+}
+
+pub fn write16(addr: PciAddress, offset: u16, value: u16) {
+    let shift = (offset & 2) * 8;
+    let old = read32(addr, offset & !3) & !(0xFFFF << shift);
+    write32(addr, offset & !3, old | (value as u32) << shift);
+}
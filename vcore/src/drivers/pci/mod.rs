@@ -0,0 +1,604 @@
+use alloc::{string::String, vec::Vec};
+use core::fmt::{self, Write};
+use spin::Mutex;
+use x86_64::PhysAddr;
+
+use crate::{info, mem::vmm, warn};
+
+pub mod config;
+
+const VENDOR_NONE: u16 = 0xFFFF;
+
+const REG_VENDOR: u16 = 0x00;
+const REG_COMMAND: u16 = 0x04;
+const REG_STATUS: u16 = 0x06;
+const REG_CLASS: u16 = 0x08;
+const REG_HEADER_TYPE: u16 = 0x0E;
+const REG_BAR0: u16 = 0x10;
+const REG_SECONDARY_BUS: u16 = 0x19;
+const REG_CAPABILITIES: u16 = 0x34;
+const REG_INTERRUPT_LINE: u16 = 0x3C;
+const REG_INTERRUPT_PIN: u16 = 0x3D;
+
+pub const COMMAND_IO: u16 = 1 << 0;
+pub const COMMAND_MEMORY: u16 = 1 << 1;
+pub const COMMAND_BUS_MASTER: u16 = 1 << 2;
+pub const COMMAND_INTX_DISABLE: u16 = 1 << 10;
+
+const STATUS_CAPABILITIES: u16 = 1 << 4;
+
+pub const CAP_MSI: u8 = 0x05;
+pub const CAP_MSIX: u8 = 0x11;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PciAddress {
+    pub segment: u16,
+    pub bus: u8,
+    pub device: u8,
+    pub function: u8,
+}
+
+impl fmt::Display for PciAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:04x}:{:02x}:{:02x}.{}",
+            self.segment, self.bus, self.device, self.function
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Bar {
+    Memory {
+        address: u64,
+        size: u64,
+        prefetchable: bool,
+        wide: bool,
+    },
+    Io {
+        port: u16,
+        size: u32,
+    },
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct MsiInfo {
+    pub offset: u16,
+    pub wide: bool,
+    pub maskable: bool,
+    pub max_vectors: u8,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct MsixInfo {
+    pub offset: u16,
+    pub table_size: u16,
+    pub table_bar: u8,
+    pub table_offset: u32,
+    pub pba_bar: u8,
+    pub pba_offset: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct PciDevice {
+    pub address: PciAddress,
+    pub vendor: u16,
+    pub device: u16,
+    pub class: u8,
+    pub subclass: u8,
+    pub prog_if: u8,
+    pub revision: u8,
+    pub header_type: u8,
+    pub interrupt_line: u8,
+    pub interrupt_pin: u8,
+    pub bars: [Option<Bar>; 6],
+    pub capabilities: Vec<(u8, u16)>,
+    pub msi: Option<MsiInfo>,
+    pub msix: Option<MsixInfo>,
+    pub driver: Option<&'static str>,
+}
+
+impl PciDevice {
+    pub fn command(&self) -> u16 {
+        config::read16(self.address, REG_COMMAND)
+    }
+
+    pub fn set_command(&self, set: u16, clear: u16) {
+        let command = (self.command() & !clear) | set;
+        config::write16(self.address, REG_COMMAND, command);
+    }
+
+    pub fn enable(&self) {
+        self.set_command(COMMAND_IO | COMMAND_MEMORY | COMMAND_BUS_MASTER, 0);
+    }
+
+    pub fn capability(&self, id: u8) -> Option<u16> {
+        self.capabilities
+            .iter()
+            .find(|(cap, _)| *cap == id)
+            .map(|(_, offset)| *offset)
+    }
+}
+
+/// matches when every field that is set agrees with the device
+#[derive(Debug, Clone, Copy)]
+pub struct PciMatch {
+    pub vendor: Option<u16>,
+    pub device: Option<u16>,
+    pub class: Option<u8>,
+    pub subclass: Option<u8>,
+    pub prog_if: Option<u8>,
+}
+
+impl PciMatch {
+    pub const fn id(vendor: u16, device: u16) -> Self {
+        Self {
+            vendor: Some(vendor),
+            device: Some(device),
+            class: None,
+            subclass: None,
+            prog_if: None,
+        }
+    }
+
+    pub const fn class(class: u8, subclass: u8) -> Self {
+        Self {
+            vendor: None,
+            device: None,
+            class: Some(class),
+            subclass: Some(subclass),
+            prog_if: None,
+        }
+    }
+
+    fn matches(&self, dev: &PciDevice) -> bool {
+        self.vendor.is_none_or(|v| v == dev.vendor)
+            && self.device.is_none_or(|d| d == dev.device)
+            && self.class.is_none_or(|c| c == dev.class)
+            && self.subclass.is_none_or(|s| s == dev.subclass)
+            && self.prog_if.is_none_or(|p| p == dev.prog_if)
+    }
+}
+
+pub struct PciDriver {
+    pub name: &'static str,
+    pub matches: &'static [PciMatch],
+    pub probe: fn(&PciDevice) -> Result<(), &'static str>,
+}
+
+static DEVICES: Mutex<Vec<PciDevice>> = Mutex::new(Vec::new());
+static DRIVERS: Mutex<Vec<&'static PciDriver>> = Mutex::new(Vec::new());
+
+fn size_bar(addr: PciAddress, offset: u16) -> (u32, u32) {
+    let original = config::read32(addr, offset);
+    config::write32(addr, offset, u32::MAX);
+    let mask = config::read32(addr, offset);
+    config::write32(addr, offset, original);
+    (original, mask)
+}
+
+fn read_bars(addr: PciAddress, count: usize) -> [Option<Bar>; 6] {
+    let mut bars = [None; 6];
+
+    // decoding stays off while the bars hold the sizing pattern
+    let command = config::read16(addr, REG_COMMAND);
+    config::write16(addr, REG_COMMAND, command & !(COMMAND_IO | COMMAND_MEMORY));
+
+    let mut i = 0;
+    while i < count {
+        let offset = REG_BAR0 + i as u16 * 4;
+        let (low, mask) = size_bar(addr, offset);
+
+        if low & 1 == 1 {
+            let bits = mask & 0xFFFC;
+            if bits != 0 {
+                bars[i] = Some(Bar::Io {
+                    port: (low & !0x3) as u16,
+                    size: (!bits & 0xFFFF) + 1,
+                });
+            }
+            i += 1;
+            continue;
+        }
+
+        let wide = (low >> 1) & 0x3 == 0x2;
+        let prefetchable = low & 0x8 != 0;
+        let mut address = (low & !0xF) as u64;
+        let mut size_mask = (mask & !0xF) as u64;
+
+        if wide && i + 1 < count {
+            let (high, high_mask) = size_bar(addr, offset + 4);
+            address |= (high as u64) << 32;
+            size_mask |= (high_mask as u64) << 32;
+        } else {
+            size_mask |= 0xFFFF_FFFF_0000_0000;
+        }
+
+        if size_mask != 0 && size_mask != 0xFFFF_FFFF_0000_0000 {
+            bars[i] = Some(Bar::Memory {
+                address,
+                size: (!size_mask).wrapping_add(1),
+                prefetchable,
+                wide,
+            });
+        }
+        i += if wide { 2 } else { 1 };
+    }
+
+    config::write16(addr, REG_COMMAND, command);
+    bars
+}
+
+fn read_capabilities(addr: PciAddress) -> Vec<(u8, u16)> {
+    let mut caps = Vec::new();
+    if config::read16(addr, REG_STATUS) & STATUS_CAPABILITIES == 0 {
+        return caps;
+    }
+
+    let mut offset = (config::read8(addr, REG_CAPABILITIES) & 0xFC) as u16;
+    // bounded in case a broken device links the list into a loop
+    while offset != 0 && caps.len() < 48 {
+        let id = config::read8(addr, offset);
+        caps.push((id, offset));
+        offset = (config::read8(addr, offset + 1) & 0xFC) as u16;
+    }
+    caps
+}
+
+fn read_msi(addr: PciAddress, offset: u16) -> MsiInfo {
+    let control = config::read16(addr, offset + 2);
+    MsiInfo {
+        offset,
+        wide: control & (1 << 7) != 0,
+        maskable: control & (1 << 8) != 0,
+        max_vectors: 1 << ((control >> 1) & 0x7),
+    }
+}
+
+fn read_msix(addr: PciAddress, offset: u16) -> MsixInfo {
+    let control = config::read16(addr, offset + 2);
+    let table = config::read32(addr, offset + 4);
+    let pba = config::read32(addr, offset + 8);
+    MsixInfo {
+        offset,
+        table_size: (control & 0x7FF) + 1,
+        table_bar: (table & 0x7) as u8,
+        table_offset: table & !0x7,
+        pba_bar: (pba & 0x7) as u8,
+        pba_offset: pba & !0x7,
+    }
+}
+
+fn read_device(addr: PciAddress) -> Option<PciDevice> {
+    let id = config::read32(addr, REG_VENDOR);
+    let vendor = id as u16;
+    if vendor == VENDOR_NONE || vendor == 0 {
+        return None;
+    }
+
+    let class = config::read32(addr, REG_CLASS);
+    let header_type = config::read8(addr, REG_HEADER_TYPE) & 0x7F;
+    let bar_count = match header_type {
+        0 => 6,
+        1 => 2,
+        _ => 0,
+    };
+
+    let capabilities = read_capabilities(addr);
+    let find = |id| capabilities.iter().find(|(c, _)| *c == id).map(|(_, o)| *o);
+    let msi = find(CAP_MSI).map(|offset| read_msi(addr, offset));
+    let msix = find(CAP_MSIX).map(|offset| read_msix(addr, offset));
+
+    Some(PciDevice {
+        address: addr,
+        vendor,
+        device: (id >> 16) as u16,
+        class: (class >> 24) as u8,
+        subclass: (class >> 16) as u8,
+        prog_if: (class >> 8) as u8,
+        revision: class as u8,
+        header_type,
+        interrupt_line: config::read8(addr, REG_INTERRUPT_LINE),
+        interrupt_pin: config::read8(addr, REG_INTERRUPT_PIN),
+        bars: read_bars(addr, bar_count),
+        capabilities,
+        msi,
+        msix,
+        driver: None,
+    })
+}
+
+fn scan_bus(segment: u16, bus: u8, devices: &mut Vec<PciDevice>, depth: usize) {
+    for device in 0..32 {
+        let addr = PciAddress {
+            segment,
+            bus,
+            device,
+            function: 0,
+        };
+        if config::read16(addr, REG_VENDOR) == VENDOR_NONE {
+            continue;
+        }
+
+        let multifunction = config::read8(addr, REG_HEADER_TYPE) & 0x80 != 0;
+        let functions = if multifunction { 8 } else { 1 };
+
+        for function in 0..functions {
+            let addr = PciAddress { function, ..addr };
+            let Some(dev) = read_device(addr) else {
+                continue;
+            };
+
+            let bridge = dev.header_type == 1;
+            devices.push(dev);
+
+            if bridge && depth < 32 {
+                let secondary = config::read8(addr, REG_SECONDARY_BUS);
+                if secondary > bus {
+                    scan_bus(segment, secondary, devices, depth + 1);
+                }
+            }
+        }
+    }
+}
+
+pub fn init() {
+    let mut segments: Vec<(u16, u8)> = crate::acpi::get()
+        .map(|acpi| acpi.mcfg.iter().map(|m| (m.segment, m.start_bus)).collect())
+        .unwrap_or_default();
+    if segments.is_empty() {
+        segments.push((0, 0));
+    }
+
+    let mut devices = Vec::new();
+    for (segment, bus) in segments {
+        scan_bus(segment, bus, &mut devices, 0);
+    }
+
+    info!("PCI {} devices", devices.len());
+    *DEVICES.lock() = devices;
+
+    let drivers: Vec<&'static PciDriver> = DRIVERS.lock().clone();
+    for driver in drivers {
+        bind(driver);
+    }
+}
+
+fn bind(driver: &'static PciDriver) {
+    let candidates: Vec<PciDevice> = DEVICES
+        .lock()
+        .iter()
+        .filter(|dev| dev.driver.is_none() && driver.matches.iter().any(|m| m.matches(dev)))
+        .cloned()
+        .collect();
+
+    for dev in candidates {
+        match (driver.probe)(&dev) {
+            Ok(()) => {
+                if let Some(d) = DEVICES.lock().iter_mut().find(|d| d.address == dev.address) {
+                    d.driver = Some(driver.name);
+                }
+                info!("pci {}: bound to {}", dev.address, driver.name);
+            }
+            Err(e) => warn!("pci {}: {} probe failed: {}", dev.address, driver.name, e),
+        }
+    }
+}
+
+/// registers a driver and probes it against every unclaimed device
+pub fn register_driver(driver: &'static PciDriver) {
+    DRIVERS.lock().push(driver);
+    bind(driver);
+}
+
+pub fn devices() -> Vec<PciDevice> {
+    DEVICES.lock().clone()
+}
+
+pub fn find(address: PciAddress) -> Option<PciDevice> {
+    DEVICES
+        .lock()
+        .iter()
+        .find(|d| d.address == address)
+        .cloned()
+}
+
+pub fn enable_msi(dev: &PciDevice, address: u32, data: u16) -> Result<(), &'static str> {
+    let msi = dev.msi.ok_or("device has no MSI capability")?;
+    let addr = dev.address;
+    let control = config::read16(addr, msi.offset + 2);
+
+    config::write32(addr, msi.offset + 4, address);
+    if msi.wide {
+        config::write32(addr, msi.offset + 8, 0);
+        config::write16(addr, msi.offset + 12, data);
+    } else {
+        config::write16(addr, msi.offset + 8, data);
+    }
+
+    // a single vector, multiple message enable stays at zero
+    config::write16(addr, msi.offset + 2, (control & !(0x7 << 4)) | 1);
+    dev.set_command(COMMAND_INTX_DISABLE | COMMAND_BUS_MASTER, 0);
+    Ok(())
+}
+
+fn msix_table(dev: &PciDevice, msix: &MsixInfo) -> Result<*mut u32, &'static str> {
+    let Some(Bar::Memory { address, .. }) = dev.bars[msix.table_bar as usize] else {
+        return Err("MSI-X table is not in a memory BAR");
+    };
+    let table = address + msix.table_offset as u64;
+    let virt = vmm::map_mmio(PhysAddr::new(table), msix.table_size as usize * 16)?;
+    Ok(virt.as_mut_ptr())
+}
+
+pub fn enable_msix(
+    dev: &PciDevice,
+    entry: u16,
+    address: u32,
+    data: u16,
+) -> Result<(), &'static str> {
+    let msix = dev.msix.ok_or("device has no MSI-X capability")?;
+    if entry >= msix.table_size {
+        return Err("MSI-X entry out of range");
+    }
+
+    let table = msix_table(dev, &msix)?;
+    let control = config::read16(dev.address, msix.offset + 2);
+    dev.set_command(
+        COMMAND_MEMORY | COMMAND_BUS_MASTER | COMMAND_INTX_DISABLE,
+        0,
+    );
+
+    // function masked while the entry is rewritten
+    config::write16(
+        dev.address,
+        msix.offset + 2,
+        control | (1 << 15) | (1 << 14),
+    );
+    unsafe {
+        let slot = table.add(entry as usize * 4);
+        slot.write_volatile(address);
+        slot.add(1).write_volatile(0);
+        slot.add(2).write_volatile(data as u32);
+        slot.add(3).write_volatile(0);
+    }
+    config::write16(
+        dev.address,
+        msix.offset + 2,
+        (control | (1 << 15)) & !(1 << 14),
+    );
+    Ok(())
+}
+
+pub fn disable_msi(dev: &PciDevice) {
+    if let Some(msi) = dev.msi {
+        let control = config::read16(dev.address, msi.offset + 2);
+        config::write16(dev.address, msi.offset + 2, control & !1);
+    }
+    if let Some(msix) = dev.msix {
+        let control = config::read16(dev.address, msix.offset + 2);
+        config::write16(dev.address, msix.offset + 2, control & !(1 << 15));
+    }
+}
+
+fn class_name(class: u8, subclass: u8) -> &'static str {
+    match (class, subclass) {
+        (0x01, 0x01) => "IDE controller",
+        (0x01, 0x06) => "SATA controller",
+        (0x01, 0x08) => "NVMe controller",
+        (0x01, _) => "storage controller",
+        (0x02, 0x00) => "ethernet controller",
+        (0x02, _) => "network controller",
+        (0x03, 0x00) => "VGA controller",
+        (0x03, _) => "display controller",
+        (0x04, _) => "multimedia controller",
+        (0x05, _) => "memory controller",
+        (0x06, 0x00) => "host bridge",
+        (0x06, 0x01) => "ISA bridge",
+        (0x06, 0x04) => "PCI bridge",
+        (0x06, _) => "bridge",
+        (0x07, _) => "communication controller",
+        (0x08, _) => "system peripheral",
+        (0x0C, 0x03) => "USB controller",
+        (0x0C, 0x05) => "SMBus controller",
+        (0x0C, _) => "serial bus controller",
+        _ => "device",
+    }
+}
+
+fn cap_name(id: u8) -> Option<&'static str> {
+    match id {
+        0x01 => Some("pm"),
+        0x05 => Some("msi"),
+        0x09 => Some("vendor"),
+        0x10 => Some("pcie"),
+        0x11 => Some("msix"),
+        0x12 => Some("sata"),
+        _ => None,
+    }
+}
+
+pub fn render() -> String {
+    let mut out = String::new();
+
+    for dev in DEVICES.lock().iter() {
+        let _ = write!(
+            out,
+            "{} {:04x}:{:04x} class {:02x}.{:02x}.{:02x} {}",
+            dev.address,
+            dev.vendor,
+            dev.device,
+            dev.class,
+            dev.subclass,
+            dev.prog_if,
+            class_name(dev.class, dev.subclass)
+        );
+        if let Some(driver) = dev.driver {
+            let _ = write!(out, " [{}]", driver);
+        }
+        if dev.interrupt_pin != 0 {
+            let _ = write!(
+                out,
+                " pin {} irq {}",
+                (b'A' + dev.interrupt_pin - 1) as char,
+                dev.interrupt_line
+            );
+        }
+        out.push('\n');
+
+        for (i, bar) in dev.bars.iter().enumerate() {
+            match bar {
+                Some(Bar::Memory {
+                    address,
+                    size,
+                    prefetchable,
+                    wide,
+                }) => {
+                    let _ = writeln!(
+                        out,
+                        "  bar{} mem {:#x} size {:#x}{}{}",
+                        i,
+                        address,
+                        size,
+                        if *wide { " 64-bit" } else { "" },
+                        if *prefetchable { " prefetchable" } else { "" }
+                    );
+                }
+                Some(Bar::Io { port, size }) => {
+                    let _ = writeln!(out, "  bar{} io {:#x} size {:#x}", i, port, size);
+                }
+                None => {}
+            }
+        }
+
+        if !dev.capabilities.is_empty() {
+            out.push_str("  caps:");
+            for (id, _) in &dev.capabilities {
+                let _ = match cap_name(*id) {
+                    Some(name) => write!(out, " {}", name),
+                    None => write!(out, " {:#04x}", id),
+                };
+            }
+            out.push('\n');
+        }
+        if let Some(msi) = &dev.msi {
+            let _ = writeln!(
+                out,
+                "  msi: {} vectors{}{}",
+                msi.max_vectors,
+                if msi.wide { ", 64-bit" } else { "" },
+                if msi.maskable { ", maskable" } else { "" }
+            );
+        }
+        if let Some(msix) = &dev.msix {
+            let _ = writeln!(
+                out,
+                "  msix: {} vectors, table bar{} +{:#x}, pba bar{} +{:#x}",
+                msix.table_size, msix.table_bar, msix.table_offset, msix.pba_bar, msix.pba_offset
+            );
+        }
+    }
+
+    out
+}
@@ -0,0 +1,169 @@
+use x86_64::instructions::{interrupts, port::Port};
+
+use crate::acpi;
+
+const CMOS_INDEX: u16 = 0x70;
+const CMOS_DATA: u16 = 0x71;
+
+const REG_SECONDS: u8 = 0x00;
+const REG_MINUTES: u8 = 0x02;
+const REG_HOURS: u8 = 0x04;
+const REG_DAY: u8 = 0x07;
+const REG_MONTH: u8 = 0x08;
+const REG_YEAR: u8 = 0x09;
+const REG_STATUS_A: u8 = 0x0A;
+const REG_STATUS_B: u8 = 0x0B;
+
+const STATUS_A_UPDATING: u8 = 0x80;
+const STATUS_B_24H: u8 = 0x02;
+const STATUS_B_BINARY: u8 = 0x04;
+const HOUR_PM: u8 = 0x80;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DateTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl DateTime {
+    /// seconds since 1970-01-01 00:00:00 utc
+    pub fn to_unix(self) -> u64 {
+        let (y, m) = if self.month <= 2 {
+            (self.year as i64 - 1, self.month as i64 + 9)
+        } else {
+            (self.year as i64, self.month as i64 - 3)
+        };
+        let era = y.div_euclid(400);
+        let yoe = y - era * 400;
+        let doy = (153 * m + 2) / 5 + self.day as i64 - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        let days = era * 146097 + doe - 719468;
+
+        (days * 86400 + self.hour as i64 * 3600 + self.minute as i64 * 60 + self.second as i64)
+            .max(0) as u64
+    }
+
+    pub fn from_unix(secs: u64) -> Self {
+        let days = (secs / 86400) as i64;
+        let rem = secs % 86400;
+
+        let z = days + 719468;
+        let era = z.div_euclid(146097);
+        let doe = z - era * 146097;
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u8;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u8;
+        let year = yoe + era * 400 + (month <= 2) as i64;
+
+        Self {
+            year: year as u16,
+            month,
+            day,
+            hour: (rem / 3600) as u8,
+            minute: (rem / 60 % 60) as u8,
+            second: (rem % 60) as u8,
+        }
+    }
+}
+
+fn read_reg(reg: u8) -> u8 {
+    // bit 7 of the index is nmi disable, leave it clear
+    unsafe {
+        Port::<u8>::new(CMOS_INDEX).write(reg);
+        Port::<u8>::new(CMOS_DATA).read()
+    }
+}
+
+fn updating() -> bool {
+    read_reg(REG_STATUS_A) & STATUS_A_UPDATING != 0
+}
+
+fn read_raw(century_reg: u8) -> [u8; 7] {
+    while updating() {
+        core::hint::spin_loop();
+    }
+    [
+        read_reg(REG_SECONDS),
+        read_reg(REG_MINUTES),
+        read_reg(REG_HOURS),
+        read_reg(REG_DAY),
+        read_reg(REG_MONTH),
+        read_reg(REG_YEAR),
+        if century_reg != 0 {
+            read_reg(century_reg)
+        } else {
+            0
+        },
+    ]
+}
+
+fn from_bcd(value: u8) -> u8 {
+    (value & 0x0F) + (value >> 4) * 10
+}
+
+pub fn read() -> Result<DateTime, &'static str> {
+    let century_reg = acpi::get()
+        .and_then(|acpi| acpi.fadt.as_ref())
+        .map_or(0, |fadt| fadt.century);
+
+    let raw = interrupts::without_interrupts(|| {
+        // an update can land between two reads, so read until two agree
+        let mut last = read_raw(century_reg);
+        for _ in 0..8 {
+            let next = read_raw(century_reg);
+            if next == last {
+                return Ok(next);
+            }
+            last = next;
+        }
+        Err("rtc never settled")
+    })?;
+
+    let status_b = read_reg(REG_STATUS_B);
+    let binary = status_b & STATUS_B_BINARY != 0;
+    let decode = |v: u8| if binary { v } else { from_bcd(v) };
+
+    let [second, minute, hour, day, month, year, century] = raw;
+
+    let pm = hour & HOUR_PM != 0;
+    let mut hour = decode(hour & !HOUR_PM);
+    if status_b & STATUS_B_24H == 0 {
+        hour %= 12;
+        if pm {
+            hour += 12;
+        }
+    }
+
+    let year = decode(year) as u16;
+    let year = if century_reg != 0 && century != 0 {
+        decode(century) as u16 * 100 + year
+    } else {
+        2000 + year
+    };
+
+    let time = DateTime {
+        year,
+        month: decode(month),
+        day: decode(day),
+        hour,
+        minute: decode(minute),
+        second: decode(second),
+    };
+
+    if !(1..=12).contains(&time.month)
+        || !(1..=31).contains(&time.day)
+        || time.hour > 23
+        || time.minute > 59
+        || time.second > 60
+    {
+        return Err("rtc returned an invalid date");
+    }
+
+    Ok(time)
+}
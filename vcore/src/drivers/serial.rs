@@ -0,0 +1,86 @@
+use core::fmt::{self, Write};
+use spin::Mutex;
+use x86_64::instructions::port::{Port, PortReadOnly};
+
+const COM1: u16 = 0x3F8;
+
+const LSR_TX_EMPTY: u8 = 0x20;
+
+pub struct SerialPort {
+    data: Port<u8>,
+    int_enable: Port<u8>,
+    fifo_ctrl: Port<u8>,
+    line_ctrl: Port<u8>,
+    modem_ctrl: Port<u8>,
+    line_status: PortReadOnly<u8>,
+}
+
+impl SerialPort {
+    fn new(base: u16) -> Self {
+        Self {
+            data: Port::new(base),
+            int_enable: Port::new(base + 1),
+            fifo_ctrl: Port::new(base + 2),
+            line_ctrl: Port::new(base + 3),
+            modem_ctrl: Port::new(base + 4),
+            line_status: PortReadOnly::new(base + 5),
+        }
+    }
+
+    fn init(&mut self) -> Result<(), &'static str> {
+        unsafe {
+            self.int_enable.write(0x00);
+            // 38400 baud
+            self.line_ctrl.write(0x80);
+            self.data.write(0x03);
+            self.int_enable.write(0x00);
+            self.line_ctrl.write(0x03);
+            self.fifo_ctrl.write(0xC7);
+
+            self.modem_ctrl.write(0x1E);
+            self.data.write(0xAE);
+            if self.data.read() != 0xAE {
+                return Err("serial loopback test failed");
+            }
+
+            self.modem_ctrl.write(0x0F);
+        }
+        Ok(())
+    }
+
+    fn send(&mut self, byte: u8) {
+        unsafe {
+            while self.line_status.read() & LSR_TX_EMPTY == 0 {
+                core::hint::spin_loop();
+            }
+            self.data.write(byte);
+        }
+    }
+}
+
+impl Write for SerialPort {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            if byte == b'\n' {
+                self.send(b'\r');
+            }
+            self.send(byte);
+        }
+        Ok(())
+    }
+}
+
+static SERIAL: Mutex<Option<SerialPort>> = Mutex::new(None);
+
+pub fn init() -> Result<(), &'static str> {
+    let mut port = SerialPort::new(COM1);
+    port.init()?;
+    *SERIAL.lock() = Some(port);
+    Ok(())
+}
+
+pub fn write_fmt(args: fmt::Arguments) {
+    if let Some(ref mut port) = *SERIAL.lock() {
+        let _ = port.write_fmt(args);
+    }
+}
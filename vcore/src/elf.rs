@@ -90,17 +90,7 @@ pub fn load_into(elf_data: &[u8], address_space: &AddressSpace) -> Result<Loaded
             flags |= PageTableFlags::NO_EXECUTE;
         }
 
-        let start_page = ph.vaddr & !0xFFF;
-        let end_addr = ph.vaddr + ph.memsz;
-        let end_page = (end_addr + 0xFFF) & !0xFFF;
-
-        let mut addr = start_page;
-        while addr < end_page {
-            if !address_space.is_mapped(VirtAddr::new(addr)) {
-                address_space.map_page_alloc(VirtAddr::new(addr), flags)?;
-            }
-            addr += 4096;
-        }
+        address_space.map_anonymous(VirtAddr::new(ph.vaddr), ph.memsz as usize, flags)?;
 
         let file_start = ph.offset as usize;
         let file_end = file_start + ph.filesz as usize;
@@ -1,6 +1,11 @@
 pub mod terminal;
 
 use limine::request::FramebufferRequest;
+use x86_64::{VirtAddr, structures::paging::PageTableFlags};
+
+use crate::mem::vmm;
+
+const FB_VIRT: u64 = 0xFFFF_9000_0000_0000;
 
 #[repr(C)]
 pub struct Framebuffer {
@@ -29,6 +34,32 @@ impl DrawTarget for Framebuffer {
 }
 
 impl Framebuffer {
+    pub fn size(&self) -> usize {
+        self.pitch * self.height
+    }
+
+    pub fn remap(&mut self) -> Result<(), &'static str> {
+        let phys = vmm::virt_to_phys(VirtAddr::new(self.address as u64))
+            .ok_or("framebuffer not mapped")?;
+        let offset = phys.as_u64() & 0xFFF;
+        let phys = phys.align_down(4096u64);
+
+        let flags = PageTableFlags::PRESENT
+            | PageTableFlags::WRITABLE
+            | PageTableFlags::WRITE_THROUGH
+            | PageTableFlags::NO_EXECUTE;
+
+        vmm::map_range(
+            VirtAddr::new(FB_VIRT),
+            phys,
+            (self.size() + offset as usize).next_multiple_of(4096),
+            flags,
+        )?;
+
+        self.address = (FB_VIRT + offset) as *mut u8;
+        Ok(())
+    }
+
     pub fn from_limine(request: &FramebufferRequest) -> Self {
         if let Some(framebuffer_response) = request.get_response() {
             if let Some(framebuffer) = framebuffer_response.framebuffers().next() {
@@ -153,6 +153,13 @@ pub fn init(fb: Framebuffer, font: &'static Font) {
     *TERMINAL.lock() = Some(Terminal::new(fb, font));
 }
 
+pub fn remap_framebuffer() -> Result<(), &'static str> {
+    match *TERMINAL.lock() {
+        Some(ref mut term) => term.fb.remap(),
+        None => Err("terminal not initialized"),
+    }
+}
+
 pub fn set_fg(color: u32) {
     if let Some(ref mut term) = *TERMINAL.lock() {
         term.set_fg(color);
@@ -172,6 +179,7 @@ macro_rules! print {
         if let Some(ref mut term) = *$crate::terminal::TERMINAL.lock() {
             let _ = write!(term, $($arg)*);
         }
+        $crate::drivers::serial::write_fmt(format_args!($($arg)*));
     }};
 }
 
@@ -184,41 +192,47 @@ macro_rules! println {
 #[macro_export]
 macro_rules! info {
     ($($arg:tt)*) => {{
-        $crate::fb::terminal::set_fg(0x5555ff);
-        $crate::print!("[");
-        $crate::fb::terminal::set_fg(0x00ff00);
-        $crate::print!("*");
-        $crate::fb::terminal::set_fg(0x5555ff);
-        $crate::print!("] ");
-        $crate::fb::terminal::set_fg(0xffffff);
-        $crate::println!($($arg)*);
+        if $crate::cmdline::log_enabled($crate::cmdline::LogLevel::Info) {
+            $crate::fb::terminal::set_fg(0x5555ff);
+            $crate::print!("[");
+            $crate::fb::terminal::set_fg(0x00ff00);
+            $crate::print!("*");
+            $crate::fb::terminal::set_fg(0x5555ff);
+            $crate::print!("] ");
+            $crate::fb::terminal::set_fg(0xffffff);
+            $crate::println!($($arg)*);
+        }
     }};
 }
 
 #[macro_export]
 macro_rules! warn {
     ($($arg:tt)*) => {{
-        $crate::fb::terminal::set_fg(0x5555ff);
-        $crate::print!("[");
-        $crate::fb::terminal::set_fg(0xFFA500);
-        $crate::print!("W");
-        $crate::fb::terminal::set_fg(0x5555ff);
-        $crate::print!("] ");
-        $crate::fb::terminal::set_fg(0xffffff);
-        $crate::println!($($arg)*);
+        if $crate::cmdline::log_enabled($crate::cmdline::LogLevel::Warn) {
+            $crate::fb::terminal::set_fg(0x5555ff);
+            $crate::print!("[");
+            $crate::fb::terminal::set_fg(0xFFA500);
+            $crate::print!("W");
+            $crate::fb::terminal::set_fg(0x5555ff);
+            $crate::print!("] ");
+            $crate::fb::terminal::set_fg(0xffffff);
+            $crate::println!($($arg)*);
+        }
     }};
 }
 
 #[macro_export]
 macro_rules! error {
     ($($arg:tt)*) => {{
-        $crate::fb::terminal::set_fg(0x5555ff);
-        $crate::print!("[");
-        $crate::fb::terminal::set_fg(0xff0000);
-        $crate::print!("E");
-        $crate::fb::terminal::set_fg(0x5555ff);
-        $crate::print!("] ");
-        $crate::fb::terminal::set_fg(0xffffff);
-        $crate::println!($($arg)*);
+        if $crate::cmdline::log_enabled($crate::cmdline::LogLevel::Error) {
+            $crate::fb::terminal::set_fg(0x5555ff);
+            $crate::print!("[");
+            $crate::fb::terminal::set_fg(0xff0000);
+            $crate::print!("E");
+            $crate::fb::terminal::set_fg(0x5555ff);
+            $crate::print!("] ");
+            $crate::fb::terminal::set_fg(0xffffff);
+            $crate::println!($($arg)*);
+        }
     }};
 }
@@ -3,16 +3,24 @@
 #![feature(abi_x86_interrupt)]
 #![allow(dead_code)]
 #![allow(rust_2024_compatibility)]
+#![cfg_attr(test, feature(custom_test_frameworks))]
+#![cfg_attr(test, test_runner(crate::testing::run))]
+#![cfg_attr(test, reexport_test_harness_main = "test_main")]
 
 extern crate alloc;
 
+mod acpi;
+mod cmdline;
 mod cpu;
 mod drivers;
 mod elf;
 mod fb;
 mod font;
 mod mem;
+mod power;
 mod sched;
+#[cfg(test)]
+mod testing;
 mod vfs;
 
 use core::arch::asm;
@@ -20,13 +28,14 @@ use core::arch::asm;
 use alloc::boxed::Box;
 use limine::BaseRevision;
 use limine::request::{
-    FramebufferRequest, HhdmRequest, MemoryMapRequest, RequestsEndMarker, RequestsStartMarker,
+    ExecutableCmdlineRequest, FramebufferRequest, HhdmRequest, MemoryMapRequest, ModuleRequest,
+    RequestsEndMarker, RequestsStartMarker, RsdpRequest,
 };
 use x86_64::VirtAddr;
 
 use crate::fb::{Framebuffer, terminal};
 use crate::vfs::block::AtaDisk;
-use crate::vfs::{DevFs, Fat32Fs, Partition, TasksFs, first_partition};
+use crate::vfs::{DevFs, LiveFs, Partition, TasksFs, parse_partitions};
 
 #[used]
 #[unsafe(link_section = ".requests")]
@@ -44,6 +53,18 @@ static HHDM_REQUEST: HhdmRequest = HhdmRequest::new();
 #[unsafe(link_section = ".requests")]
 static MEMORY_MAP_REQUEST: MemoryMapRequest = MemoryMapRequest::new();
 
+#[used]
+#[unsafe(link_section = ".requests")]
+static MODULE_REQUEST: ModuleRequest = ModuleRequest::new();
+
+#[used]
+#[unsafe(link_section = ".requests")]
+static CMDLINE_REQUEST: ExecutableCmdlineRequest = ExecutableCmdlineRequest::new();
+
+#[used]
+#[unsafe(link_section = ".requests")]
+static RSDP_REQUEST: RsdpRequest = RsdpRequest::new();
+
 #[used]
 #[unsafe(link_section = ".requests_start_marker")]
 static _START_MARKER: RequestsStartMarker = RequestsStartMarker::new();
@@ -51,48 +72,161 @@ static _START_MARKER: RequestsStartMarker = RequestsStartMarker::new();
 #[unsafe(link_section = ".requests_end_marker")]
 static _END_MARKER: RequestsEndMarker = RequestsEndMarker::new();
 
-static INIT_ELF: &[u8] = include_bytes!("../../target/x86_64-unknown-none/release/shell");
-static HELLO_ELF: &[u8] = include_bytes!("../../target/x86_64-unknown-none/release/hello_world");
+const INIT_PATHS: &[&str] = &[
+    "/system/cmd/init",
+    "/initrd/bin/init",
+    "/initrd/bin/shell",
+    "/system/cmd/shell",
+];
+
+fn initrd() -> Option<&'static [u8]> {
+    let modules = MODULE_REQUEST.get_response()?.modules();
+    let module = modules
+        .iter()
+        .find(|m| m.path().to_bytes().ends_with(b"initrd.tar"))
+        .or(modules.first())?;
+
+    Some(unsafe { core::slice::from_raw_parts(module.addr(), module.size() as usize) })
+}
 
-fn mount_fat32() -> Result<(), &'static str> {
-    let disk = AtaDisk::new()?;
-    let part_info = first_partition(&disk)?.ok_or("no partitions found")?;
-    let partition = Partition::new(disk, part_info.start_lba, part_info.sector_count);
-    let fat = Fat32Fs::new(partition)?;
+fn mount_initrd() -> Result<(), &'static str> {
+    let data = initrd().ok_or("no initrd module")?;
+    let fs = vfs::ArchiveFs::new(data)?;
 
-    vfs::mount("/", Box::new(fat)).expect("failed to mount root");
-    info!("mounted root at /");
+    vfs::mount_from(
+        "/initrd",
+        Box::new(fs),
+        "initrd",
+        vfs::MountFlags::READ_ONLY,
+    )
+    .map_err(|_| "failed to mount initrd")?;
+    info!("mounted initrd at /initrd ({} KB)", data.len() / 1024);
 
     Ok(())
 }
 
-fn setup_fs() {
-    if let Err(e) = mount_fat32() {
-        error!("failed to mount fat32, falling back to tmpfs, {}", e);
-        vfs::mount("/", Box::new(vfs::TmpFs::new())).expect("failed to mount root");
+const RESCUE_SHELL: &str = "/initrd/bin/shell";
+
+fn spawn_init(rescue: bool) {
+    // the regular init paths live on the root that is missing
+    if rescue {
+        error!("root filesystem unavailable, starting rescue shell");
+        match sched::spawn_path(RESCUE_SHELL) {
+            Ok(pid) => return sched::set_init(pid),
+            Err(e) => panic!(
+                "root unavailable and rescue shell missing ({}: {})",
+                RESCUE_SHELL, e
+            ),
+        }
+    }
+
+    if let Some(path) = cmdline::init_path() {
+        match sched::spawn_path(path) {
+            Ok(pid) => return sched::set_init(pid),
+            Err(e) => error!("failed to start init from {}: {}", path, e),
+        }
+    }
+
+    for path in INIT_PATHS {
+        match sched::spawn_path(path) {
+            Ok(pid) => return sched::set_init(pid),
+            Err(e) => warn!("failed to start init from {}: {}", path, e),
+        }
     }
+    panic!("no init found");
+}
+
+// the system tree is shared between images, so it is mounted over itself
+// read-only to keep user tools off it
+fn protect_system() -> Result<(), &'static str> {
+    let fs = vfs::BindFs::new("/system").map_err(|_| "not a directory")?;
+    vfs::mount_from(
+        "/system",
+        Box::new(fs),
+        "/system",
+        vfs::MountFlags::READ_ONLY,
+    )
+    .map_err(|_| "failed to mount /system read-only")?;
+    info!("mounted /system read-only");
+
+    Ok(())
+}
+
+fn setup_fs() -> bool {
+    let root = cmdline::root();
+    let rescue = match vfs::root::mount_root(root) {
+        Ok(desc) => {
+            info!("mounted root {} at /", desc);
+            false
+        }
+        Err(e) => {
+            error!(
+                "cannot mount root {}: {}",
+                root.unwrap_or("(first partition)"),
+                e
+            );
+            vfs::mount("/", Box::new(vfs::TmpFs::new())).expect("failed to mount rescue root");
+            true
+        }
+    };
 
     let _ = vfs::mkdir("/live");
-    let _ = vfs::mkdir("/live/tasks");
-    let _ = vfs::mkdir("/live/mem");
     let _ = vfs::mkdir("/dev");
 
     vfs::mount("/live/tasks", Box::new(TasksFs::new())).expect("failed to mount tasksfs");
-    vfs::mount("/live/mem", Box::new(vfs::MemFs::new())).expect("failed to mount memfs");
+    vfs::mount("/live/mem", Box::new(LiveFs::new(vfs::memfs::render)))
+        .expect("failed to mount memfs");
+    vfs::mount("/live/cmdline", Box::new(LiveFs::new(cmdline::render)))
+        .expect("failed to mount cmdline");
+    vfs::mount("/live/acpi", Box::new(LiveFs::new(acpi::render))).expect("failed to mount acpi");
+    vfs::mount("/live/pci", Box::new(LiveFs::new(drivers::pci::render)))
+        .expect("failed to mount pci");
+    vfs::mount("/live/interrupts", Box::new(LiveFs::new(cpu::irq::render)))
+        .expect("failed to mount interrupts");
+    vfs::mount("/live/mounts", Box::new(LiveFs::new(vfs::render_mounts)))
+        .expect("failed to mount mounts");
 
     let devfs = DevFs::new();
 
     if let Ok(ata) = AtaDisk::new() {
+        if let Ok(partitions) = parse_partitions(&ata) {
+            for part in partitions {
+                let Ok(disk) = AtaDisk::new() else { continue };
+                let device = Partition::new(disk, part.start_lba, part.sector_count);
+                devfs.register_device(&vfs::root::partition_name(&part), Box::new(device));
+            }
+        }
         devfs.register_device("ata0", Box::new(ata));
     }
 
     vfs::mount("/dev", Box::new(devfs)).expect("failed to mount devfs");
+    vfs::mount("/dev/shm", Box::new(vfs::ShmFs::new())).expect("failed to mount shmfs");
+
+    if let Err(e) = mount_initrd() {
+        warn!("initrd: {}", e);
+    }
+
+    if vfs::exists("/system")
+        && let Err(e) = protect_system()
+    {
+        warn!("system: {}", e);
+    }
+
+    rescue
 }
 
 #[unsafe(no_mangle)]
 unsafe extern "C" fn kmain() -> ! {
     assert!(BASE_REVISION.is_supported());
 
+    let raw_cmdline = CMDLINE_REQUEST
+        .get_response()
+        .and_then(|r| r.cmdline().to_str().ok())
+        .unwrap_or("");
+    cmdline::init(raw_cmdline);
+
+    let serial = cmdline::serial_console().then(drivers::serial::init);
+
     let hhdm = VirtAddr::new(HHDM_REQUEST.get_response().unwrap().offset());
 
     let framebuffer = Framebuffer::from_limine(&FRAMEBUFFER_REQUEST);
@@ -107,6 +241,10 @@ unsafe extern "C" fn kmain() -> ! {
     println!("       `-.-`     '.____.'       `.____.'\n");
     terminal::set_fg(0xffffff);
 
+    if let Some(Err(e)) = serial {
+        warn!("serial console unavailable: {}", e);
+    }
+
     info!("Beginning BOOT");
     cpu::init();
 
@@ -124,21 +262,49 @@ unsafe extern "C" fn kmain() -> ! {
     mem::heap::init().expect("heap init failed");
     info!("HEAP {}KB", mem::heap::size());
 
+    let rsdp = RSDP_REQUEST.get_response().map(|r| r.address() as u64);
+    match rsdp.ok_or("no RSDP from bootloader").and_then(|rsdp| {
+        // older base revisions hand out the rsdp as an hhdm pointer
+        acpi::init(rsdp.checked_sub(hhdm.as_u64()).unwrap_or(rsdp))
+    }) {
+        Ok(acpi) => info!(
+            "ACPI revision {}, {} tables",
+            acpi.revision,
+            acpi.tables.len()
+        ),
+        Err(e) => warn!("ACPI unavailable: {}", e),
+    }
+
+    match terminal::remap_framebuffer() {
+        Ok(()) => info!("framebuffer remapped with huge pages"),
+        Err(e) => warn!("framebuffer remap failed: {}", e),
+    }
+
     cpu::apic::init();
     info!("APIC loaded");
 
-    match drivers::ata::init() {
-        Ok(()) => info!("ATA drive detected"),
-        Err(e) => warn!("ATA init failed: {}", e),
+    cpu::time::init();
+
+    #[cfg(test)]
+    test_main();
+
+    drivers::pci::init();
+    drivers::ata::register();
+
+    // machines without a PCI IDE function can still have the ISA ports
+    if !drivers::ata::is_present() {
+        match drivers::ata::init() {
+            Ok(()) => info!("ATA drive detected"),
+            Err(e) => warn!("ATA init failed: {}", e),
+        }
     }
 
-    setup_fs();
+    let rescue = setup_fs();
 
     sched::init();
+    sched::spawn("writeback", vfs::cache::writeback_task);
 
-    sched::spawn_elf("shell", INIT_ELF).expect("failed to spawn init");
-    // sched::spawn_elf(HELLO_ELF).expect("failed to spawn init");
-    // sched::spawn_elf(HELLO_ELF).expect("failed to spawn init");
+    spawn_init(rescue);
 
     x86_64::instructions::interrupts::enable();
 
@@ -149,6 +315,8 @@ unsafe extern "C" fn kmain() -> ! {
 fn rust_panic(info: &core::panic::PanicInfo) -> ! {
     error!("{}", info.message());
     error!("at {}", info.location().unwrap());
+    #[cfg(test)]
+    power::test_exit(false);
     hcf()
 }
 
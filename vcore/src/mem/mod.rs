@@ -1,5 +1,12 @@
 pub mod heap;
+pub mod oom;
 pub mod pmm;
+pub mod shm;
+pub mod swap;
 pub mod vmm;
 
 pub const PAGE_SIZE: usize = 4096;
+pub const HUGE_PAGE_SIZE: usize = 2 * 1024 * 1024;
+pub const GIANT_PAGE_SIZE: usize = 1024 * 1024 * 1024;
+// first address past the lower, user half
+pub const USER_END: u64 = 0x0000_8000_0000_0000;
@@ -0,0 +1,80 @@
+use x86_64::instructions::interrupts;
+
+use crate::{
+    mem::{pmm, swap},
+    sched::{
+        SCHEDULER,
+        task::{Task, TaskMode, TaskState},
+    },
+    vfs::cache,
+};
+
+const RECLAIM_BATCH: usize = 64;
+
+pub fn alloc_frame() -> Option<u64> {
+    if let Some(frame) = pmm::alloc() {
+        return Some(frame);
+    }
+
+    if cache::shrink(RECLAIM_BATCH) > 0
+        && let Some(frame) = pmm::alloc()
+    {
+        return Some(frame);
+    }
+
+    if swap::swap_out(RECLAIM_BATCH) > 0
+        && let Some(frame) = pmm::alloc()
+    {
+        return Some(frame);
+    }
+
+    // the victim's memory only comes back once it has exited, which this
+    // allocation may be in the way of, so it fails either way
+    match interrupts::without_interrupts(kill_largest_task) {
+        Kill::Pending => None,
+        Kill::NoVictim => panic!("out of memory: nothing left to reclaim"),
+    }
+}
+
+enum Kill {
+    Pending,
+    NoVictim,
+}
+
+fn kill_largest_task() -> Kill {
+    // the allocation may come from inside a scheduler critical section
+    let Some(mut guard) = SCHEDULER.try_lock() else {
+        return Kill::Pending;
+    };
+    let Some(sched) = guard.as_mut() else {
+        return Kill::NoVictim;
+    };
+
+    let live = |t: &&Task| t.mode == TaskMode::User && t.state != TaskState::Dead;
+    // one victim at a time, the last one has yet to give its memory back
+    if sched.tasks.iter().filter(live).any(|t| t.kill_pending) {
+        return Kill::Pending;
+    }
+
+    let victim = sched
+        .tasks
+        .iter()
+        .enumerate()
+        .filter(|(_, t)| live(t))
+        .max_by_key(|(_, t)| t.resident_pages())
+        .map(|(i, _)| i);
+
+    let Some(idx) = victim else {
+        return Kill::NoVictim;
+    };
+
+    let task = &mut sched.tasks[idx];
+    crate::warn!(
+        "out of memory: killed task {} ({}) with {} resident pages",
+        task.id,
+        task.name,
+        task.resident_pages()
+    );
+    sched.mark_killed(idx);
+    Kill::Pending
+}
@@ -56,6 +56,25 @@ impl BitmapAllocator {
         None
     }
 
+    fn alloc_contiguous(&mut self, count: usize, align: usize) -> Option<u64> {
+        let mut start = 0;
+
+        while start + count <= self.total_pages {
+            match (start..start + count).find(|&i| self.test_bit(i)) {
+                Some(used) => start = (used + 1).next_multiple_of(align),
+                None => {
+                    for i in start..start + count {
+                        self.set_bit(i);
+                    }
+                    self.free_pages -= count;
+                    return Some((start * PAGE_SIZE) as u64);
+                }
+            }
+        }
+
+        None
+    }
+
     fn free_page(&mut self, addr: u64) {
         let page = addr as usize / PAGE_SIZE;
         if page < self.total_pages && self.test_bit(page) {
@@ -131,6 +150,18 @@ pub fn alloc() -> Option<u64> {
     PMM.lock().as_mut()?.alloc_page()
 }
 
+pub fn alloc_contiguous(count: usize, align: usize) -> Option<u64> {
+    PMM.lock().as_mut()?.alloc_contiguous(count, align.max(1))
+}
+
+pub fn free_contiguous(addr: u64, count: usize) {
+    if let Some(pmm) = PMM.lock().as_mut() {
+        for i in 0..count {
+            pmm.free_page(addr + (i * PAGE_SIZE) as u64);
+        }
+    }
+}
+
 pub fn free(addr: u64) {
     if let Some(pmm) = PMM.lock().as_mut() {
         pmm.free_page(addr);
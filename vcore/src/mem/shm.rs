@@ -0,0 +1,231 @@
+use alloc::{collections::BTreeMap, string::String, sync::Arc, vec::Vec};
+use spin::Mutex;
+use x86_64::{PhysAddr, VirtAddr, structures::paging::PageTableFlags};
+
+use crate::mem::{PAGE_SIZE, USER_END, oom, pmm, vmm};
+
+pub const SHM_BASE: u64 = 0x0000_2000_0000_0000;
+const MAX_SHM_SIZE: usize = 64 * 1024 * 1024;
+
+static OBJECTS: Mutex<BTreeMap<String, Arc<ShmObject>>> = Mutex::new(BTreeMap::new());
+
+pub struct ShmObject {
+    pub name: String,
+    inner: Mutex<ShmInner>,
+}
+
+struct ShmInner {
+    size: usize,
+    frames: Vec<u64>,
+}
+
+impl ShmObject {
+    fn new(name: &str) -> Self {
+        Self {
+            name: String::from(name),
+            inner: Mutex::new(ShmInner {
+                size: 0,
+                frames: Vec::new(),
+            }),
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.inner.lock().size
+    }
+
+    pub fn resize(&self, size: usize) -> Result<(), &'static str> {
+        if size > MAX_SHM_SIZE {
+            return Err("shared memory object too large");
+        }
+
+        let mut inner = self.inner.lock();
+        let pages = size.div_ceil(PAGE_SIZE);
+
+        while inner.frames.len() < pages {
+            let frame = oom::alloc_frame().ok_or("out of memory")?;
+            unsafe {
+                let ptr = vmm::phys_to_virt(PhysAddr::new(frame)).as_mut_ptr::<u8>();
+                core::ptr::write_bytes(ptr, 0, PAGE_SIZE);
+            }
+            inner.frames.push(frame);
+        }
+
+        inner.size = inner.size.max(size);
+        Ok(())
+    }
+
+    pub fn frames(&self) -> Vec<u64> {
+        self.inner.lock().frames.clone()
+    }
+
+    pub fn read(&self, offset: usize, buf: &mut [u8]) -> usize {
+        let inner = self.inner.lock();
+        let to_read = buf.len().min(inner.size.saturating_sub(offset));
+
+        let mut done = 0;
+        while done < to_read {
+            let pos = offset + done;
+            let page_offset = pos % PAGE_SIZE;
+            let chunk = (PAGE_SIZE - page_offset).min(to_read - done);
+            let frame = inner.frames[pos / PAGE_SIZE];
+
+            unsafe {
+                let src = vmm::phys_to_virt(PhysAddr::new(frame)).as_ptr::<u8>();
+                core::ptr::copy_nonoverlapping(
+                    src.add(page_offset),
+                    buf.as_mut_ptr().add(done),
+                    chunk,
+                );
+            }
+            done += chunk;
+        }
+
+        done
+    }
+
+    pub fn write(&self, offset: usize, buf: &[u8]) -> usize {
+        let inner = self.inner.lock();
+        let to_write = buf.len().min(inner.size.saturating_sub(offset));
+
+        let mut done = 0;
+        while done < to_write {
+            let pos = offset + done;
+            let page_offset = pos % PAGE_SIZE;
+            let chunk = (PAGE_SIZE - page_offset).min(to_write - done);
+            let frame = inner.frames[pos / PAGE_SIZE];
+
+            unsafe {
+                let dest = vmm::phys_to_virt(PhysAddr::new(frame)).as_mut_ptr::<u8>();
+                core::ptr::copy_nonoverlapping(
+                    buf.as_ptr().add(done),
+                    dest.add(page_offset),
+                    chunk,
+                );
+            }
+            done += chunk;
+        }
+
+        done
+    }
+}
+
+impl Drop for ShmObject {
+    fn drop(&mut self) {
+        for &frame in self.inner.lock().frames.iter() {
+            pmm::free(frame);
+        }
+    }
+}
+
+pub struct ShmMapping {
+    pub addr: u64,
+    pub pages: usize,
+    pub object: Arc<ShmObject>,
+}
+
+pub fn open(name: &str, create: bool, exclusive: bool) -> Result<Arc<ShmObject>, &'static str> {
+    if name.is_empty() || name.contains('/') {
+        return Err("invalid shared memory name");
+    }
+
+    let mut objects = OBJECTS.lock();
+
+    match objects.get(name) {
+        Some(_) if create && exclusive => Err("shared memory object exists"),
+        Some(object) => Ok(object.clone()),
+        None if create => {
+            let object = Arc::new(ShmObject::new(name));
+            objects.insert(String::from(name), object.clone());
+            Ok(object)
+        }
+        None => Err("no such shared memory object"),
+    }
+}
+
+pub fn unlink(name: &str) -> Result<(), &'static str> {
+    OBJECTS
+        .lock()
+        .remove(name)
+        .map(|_| ())
+        .ok_or("no such shared memory object")
+}
+
+pub fn lookup(name: &str) -> Option<Arc<ShmObject>> {
+    OBJECTS.lock().get(name).cloned()
+}
+
+pub fn names() -> Vec<String> {
+    OBJECTS.lock().keys().cloned().collect()
+}
+
+pub fn map(
+    address_space: &vmm::AddressSpace,
+    mappings: &mut Vec<ShmMapping>,
+    object: Arc<ShmObject>,
+    hint: u64,
+) -> Result<u64, &'static str> {
+    let frames = object.frames();
+    if frames.is_empty() {
+        return Err("shared memory object has no size");
+    }
+
+    let pages = frames.len();
+    let addr = if hint != 0 {
+        hint & !(PAGE_SIZE as u64 - 1)
+    } else {
+        mappings
+            .iter()
+            .map(|m| m.addr + (m.pages * PAGE_SIZE) as u64)
+            .max()
+            .unwrap_or(SHM_BASE)
+    };
+
+    let end = addr.checked_add((pages * PAGE_SIZE) as u64);
+    if addr == 0 || end.is_none_or(|end| end > USER_END) {
+        return Err("address outside user space");
+    }
+    let page_addr =
+        |i: usize| VirtAddr::try_new(addr + (i * PAGE_SIZE) as u64).map_err(|_| "bad address");
+
+    for i in 0..pages {
+        if address_space.is_mapped(page_addr(i)?) {
+            return Err("address range already mapped");
+        }
+    }
+
+    let flags = PageTableFlags::PRESENT
+        | PageTableFlags::WRITABLE
+        | PageTableFlags::USER_ACCESSIBLE
+        | PageTableFlags::NO_EXECUTE;
+
+    for (i, &frame) in frames.iter().enumerate() {
+        address_space.map_page(page_addr(i)?, PhysAddr::new(frame), flags)?;
+    }
+
+    mappings.push(ShmMapping {
+        addr,
+        pages,
+        object,
+    });
+
+    Ok(addr)
+}
+
+pub fn unmap(
+    address_space: &vmm::AddressSpace,
+    mappings: &mut Vec<ShmMapping>,
+    addr: u64,
+) -> Result<(), &'static str> {
+    let idx = mappings
+        .iter()
+        .position(|m| m.addr == addr)
+        .ok_or("no shared memory mapped at address")?;
+    let mapping = mappings.remove(idx);
+
+    for i in 0..mapping.pages {
+        address_space.unmap_page(VirtAddr::new(addr + (i * PAGE_SIZE) as u64))?;
+    }
+
+    Ok(())
+}
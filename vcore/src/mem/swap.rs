@@ -0,0 +1,469 @@
+use alloc::{boxed::Box, string::String, sync::Arc, vec, vec::Vec};
+use core::sync::atomic::{AtomicU64, Ordering};
+use spin::Mutex;
+use x86_64::{
+    PhysAddr, VirtAddr,
+    instructions::{interrupts, tlb},
+    structures::paging::{PageTableFlags, page_table::PageTableEntry},
+};
+
+use crate::{
+    mem::{PAGE_SIZE, USER_END, oom, pmm, vmm},
+    sched::{
+        SCHEDULER,
+        task::{TaskMode, TaskState},
+    },
+    vfs::{self, FileHandle, FileType, OpenFlags},
+};
+
+const SWAP_ENTRY: PageTableFlags = PageTableFlags::BIT_9;
+
+// only taken with interrupts off and never across I/O, so whoever holds it
+// cannot have been preempted
+static AREAS: Mutex<Vec<SwapArea>> = Mutex::new(Vec::new());
+static SWAPPED_OUT: AtomicU64 = AtomicU64::new(0);
+static SWAPPED_IN: AtomicU64 = AtomicU64::new(0);
+
+type SwapHandle = Arc<Mutex<Box<dyn FileHandle>>>;
+
+struct SwapArea {
+    path: String,
+    // used without AREAS, so the I/O runs with nothing else held
+    handle: SwapHandle,
+    base: u64,
+    slots: Vec<bool>,
+    used: usize,
+    active: bool,
+}
+
+impl SwapArea {
+    fn contains(&self, slot: u64) -> bool {
+        slot >= self.base && slot < self.base + self.slots.len() as u64
+    }
+
+    fn alloc_slot(&mut self) -> Option<u64> {
+        if !self.active || self.used == self.slots.len() {
+            return None;
+        }
+
+        let index = self.slots.iter().position(|used| !used)?;
+        self.slots[index] = true;
+        self.used += 1;
+        Some(self.base + index as u64)
+    }
+
+    fn free_slot(&mut self, slot: u64) {
+        let index = (slot - self.base) as usize;
+        if self.slots[index] {
+            self.slots[index] = false;
+            self.used -= 1;
+        }
+    }
+}
+
+pub struct SwapStats {
+    pub total_pages: usize,
+    pub used_pages: usize,
+    pub swapped_out: u64,
+    pub swapped_in: u64,
+}
+
+fn page_mut(frame: u64) -> &'static mut [u8] {
+    let ptr = vmm::phys_to_virt(PhysAddr::new(frame)).as_mut_ptr::<u8>();
+    unsafe { core::slice::from_raw_parts_mut(ptr, PAGE_SIZE) }
+}
+
+fn is_swap_entry(entry: &PageTableEntry) -> bool {
+    let flags = entry.flags();
+    !flags.contains(PageTableFlags::PRESENT) && flags.contains(SWAP_ENTRY)
+}
+
+fn entry_slot(entry: &PageTableEntry) -> u64 {
+    entry.addr().as_u64() / PAGE_SIZE as u64
+}
+
+// the handle behind `slot` and the page it is in there
+fn backing(slot: u64) -> Option<(SwapHandle, u64)> {
+    interrupts::without_interrupts(|| {
+        let areas = AREAS.lock();
+        let area = areas.iter().find(|area| area.contains(slot))?;
+        Some((area.handle.clone(), slot - area.base))
+    })
+}
+
+fn free_slot(slot: u64) {
+    interrupts::without_interrupts(|| {
+        if let Some(area) = AREAS.lock().iter_mut().find(|area| area.contains(slot)) {
+            area.free_slot(slot);
+        }
+    });
+}
+
+// reads `slot` into a new frame. nothing may be held, the read waits on
+// filesystem locks and reclaim may need to write pages out
+fn read_slot(slot: u64) -> Result<u64, &'static str> {
+    let frame = oom::alloc_frame().ok_or("out of memory")?;
+    let Some((handle, index)) = backing(slot) else {
+        pmm::free(frame);
+        return Err("swap slot has no backing area");
+    };
+
+    if handle
+        .lock()
+        .read_page_direct(index, page_mut(frame))
+        .is_err()
+    {
+        pmm::free(frame);
+        return Err("swap read failed");
+    }
+    Ok(frame)
+}
+
+// points `entry` at the frame `slot` was read into, unless something else
+// swapped it in while the read ran
+fn install(entry: &mut PageTableEntry, slot: u64, frame: u64) {
+    if !is_swap_entry(entry) || entry_slot(entry) != slot {
+        pmm::free(frame);
+        return;
+    }
+
+    free_slot(slot);
+    let flags = (entry.flags() - SWAP_ENTRY) | PageTableFlags::PRESENT;
+    entry.set_addr(PhysAddr::new(frame), flags);
+    SWAPPED_IN.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn swapon(path: &str) -> Result<(), &'static str> {
+    let mut handle = vfs::open(path, OpenFlags::O_RDWR).map_err(|_| "cannot open swap area")?;
+    let meta = handle.metadata().map_err(|_| "cannot stat swap area")?;
+
+    if !matches!(meta.file_type, FileType::File | FileType::Device) {
+        return Err("swap area must be a file or block device");
+    }
+
+    let pages = meta.size / PAGE_SIZE;
+    if pages == 0 {
+        return Err("swap area too small");
+    }
+
+    // swap writes beneath the page cache, so whatever it holds of the file
+    // has to go, and stay gone while swap is on
+    handle
+        .claim_direct()
+        .map_err(|_| "cannot take swap area out of the page cache")?;
+
+    let mut probe = vec![0u8; PAGE_SIZE];
+    handle
+        .read_page_direct(0, &mut probe)
+        .map_err(|_| "swap area does not support direct I/O")?;
+
+    interrupts::without_interrupts(|| {
+        let mut areas = AREAS.lock();
+        if areas.iter().any(|area| area.path == path) {
+            return Err("swap area already active");
+        }
+
+        // slot 0 is never handed out so a swap entry always has a nonzero address
+        let base = areas
+            .iter()
+            .map(|area| area.base + area.slots.len() as u64)
+            .max()
+            .unwrap_or(1);
+
+        areas.push(SwapArea {
+            path: String::from(path),
+            handle: Arc::new(Mutex::new(handle)),
+            base,
+            slots: vec![false; pages],
+            used: 0,
+            active: true,
+        });
+        Ok(())
+    })?;
+
+    crate::info!("swap: enabled {} ({} KB)", path, pages * PAGE_SIZE / 1024);
+    Ok(())
+}
+
+pub fn swapoff(path: &str) -> Result<(), &'static str> {
+    let range = interrupts::without_interrupts(|| {
+        let mut areas = AREAS.lock();
+        let area = areas
+            .iter_mut()
+            .find(|area| area.path == path)
+            .ok_or("swap area not active")?;
+        area.active = false;
+        Ok(area.base..area.base + area.slots.len() as u64)
+    })?;
+
+    let result = swap_in_range(range);
+
+    let area = interrupts::without_interrupts(|| {
+        let mut areas = AREAS.lock();
+        let idx = areas
+            .iter()
+            .position(|area| area.path == path)
+            .ok_or("swap area not active")?;
+
+        if result.is_err() || areas[idx].used != 0 {
+            areas[idx].active = true;
+            return Err("not enough memory to swap pages back in");
+        }
+
+        Ok(areas.remove(idx))
+    })?;
+    // closing the file may write to it, which is not done under AREAS
+    drop(area);
+
+    crate::info!("swap: disabled {}", path);
+    Ok(())
+}
+
+// the pages are gathered under the scheduler, then read in one at a time
+// with it let go and put back under it again
+fn swap_in_range(range: core::ops::Range<u64>) -> Result<(), &'static str> {
+    let pages = interrupts::without_interrupts(|| {
+        let guard = SCHEDULER.lock();
+        let mut pages = Vec::new();
+        for task in guard.iter().flat_map(|sched| sched.tasks.iter()) {
+            let Some(space) = &task.address_space else {
+                continue;
+            };
+            space.for_each_user_entry(&mut |virt, entry, _| {
+                if is_swap_entry(entry) && range.contains(&entry_slot(entry)) {
+                    pages.push((task.id, virt, entry_slot(entry)));
+                }
+            });
+        }
+        pages
+    });
+
+    for (pid, virt, slot) in pages {
+        let frame = read_slot(slot)?;
+
+        interrupts::without_interrupts(|| {
+            let guard = SCHEDULER.lock();
+            let space = guard
+                .iter()
+                .flat_map(|sched| sched.tasks.iter())
+                .find(|task| task.id == pid && task.state != TaskState::Dead)
+                .and_then(|task| task.address_space.as_ref());
+            match space.and_then(|space| Some((space, space.leaf_entry(virt)?))) {
+                Some((space, entry)) => {
+                    install(entry, slot, frame);
+                    if space.is_active() {
+                        tlb::flush(virt);
+                    }
+                }
+                // exited, and its slots went with it
+                None => pmm::free(frame),
+            }
+        });
+    }
+    Ok(())
+}
+
+// called with interrupts on, from code that could have been preempted
+pub fn handle_fault(addr: VirtAddr) -> bool {
+    if addr.as_u64() >= USER_END {
+        return false;
+    }
+
+    let Some(entry) = vmm::current_leaf_entry(addr) else {
+        return false;
+    };
+    if !is_swap_entry(entry) {
+        return false;
+    }
+
+    // the faulting task is blocked here, so its tables stay put meanwhile
+    let slot = entry_slot(entry);
+    match read_slot(slot) {
+        Ok(frame) => {
+            interrupts::without_interrupts(|| install(entry, slot, frame));
+            tlb::flush(addr.align_down(PAGE_SIZE as u64));
+            true
+        }
+        Err(e) => {
+            crate::error!("swap: failed to swap in {:#x}: {}", addr.as_u64(), e);
+            false
+        }
+    }
+}
+
+pub fn release_entry(entry: &mut PageTableEntry) {
+    if !is_swap_entry(entry) {
+        return;
+    }
+
+    free_slot(entry_slot(entry));
+    entry.set_unused();
+}
+
+struct Victim {
+    pid: u64,
+    virt: VirtAddr,
+    frame: u64,
+    slot: u64,
+}
+
+// victims are picked under the locks, written out with none held, and only
+// let go if nothing wrote to them meanwhile
+pub fn swap_out(target: usize) -> usize {
+    // the writes wait on filesystem locks, which is only safe when this can
+    // be preempted in turn
+    if !interrupts::are_enabled() {
+        return 0;
+    }
+
+    let victims = interrupts::without_interrupts(|| pick_victims(target));
+    let mut swapped = 0;
+    for victim in victims {
+        let written = match backing(victim.slot) {
+            Some((handle, index)) => handle
+                .lock()
+                .write_page_direct(index, page_mut(victim.frame))
+                .is_ok(),
+            None => false,
+        };
+        if interrupts::without_interrupts(|| finish_swap_out(&victim, written)) {
+            swapped += 1;
+        }
+    }
+    swapped
+}
+
+fn pick_victims(target: usize) -> Vec<Victim> {
+    let mut victims = Vec::new();
+
+    // called from the allocator, which may already hold either lock
+    let Some(guard) = SCHEDULER.try_lock() else {
+        return victims;
+    };
+    let Some(sched) = guard.as_ref() else {
+        return victims;
+    };
+    let Some(mut areas) = AREAS.try_lock() else {
+        return victims;
+    };
+    if !areas.iter().any(|area| area.active) {
+        return victims;
+    }
+
+    // two passes give recently used pages a second chance
+    for _ in 0..2 {
+        for task in sched.tasks.iter() {
+            if task.mode != TaskMode::User || task.state == TaskState::Dead {
+                continue;
+            }
+            let Some(space) = &task.address_space else {
+                continue;
+            };
+
+            space.for_each_user_entry(&mut |virt, entry, pages| {
+                if victims.len() >= target || pages != 1 {
+                    return;
+                }
+
+                let flags = entry.flags();
+                if !flags.contains(PageTableFlags::PRESENT) {
+                    return;
+                }
+
+                let shared = task.shm_maps.iter().any(|m| {
+                    virt.as_u64() >= m.addr && virt.as_u64() < m.addr + (m.pages * PAGE_SIZE) as u64
+                });
+                if shared
+                    || victims
+                        .iter()
+                        .any(|v: &Victim| v.pid == task.id && v.virt == virt)
+                {
+                    return;
+                }
+
+                if flags.contains(PageTableFlags::ACCESSED) {
+                    entry.set_flags(flags - PageTableFlags::ACCESSED);
+                    if space.is_active() {
+                        tlb::flush(virt);
+                    }
+                    return;
+                }
+
+                let Some(slot) = areas.iter_mut().find_map(|area| area.alloc_slot()) else {
+                    return;
+                };
+                // a write while the page is on its way out sets this again
+                entry.set_flags(flags - PageTableFlags::DIRTY);
+                if space.is_active() {
+                    tlb::flush(virt);
+                }
+                victims.push(Victim {
+                    pid: task.id,
+                    virt,
+                    frame: entry.addr().as_u64(),
+                    slot,
+                });
+            });
+        }
+
+        if victims.len() >= target {
+            break;
+        }
+    }
+
+    victims
+}
+
+fn finish_swap_out(victim: &Victim, written: bool) -> bool {
+    let swapped = written
+        && (|| {
+            let guard = SCHEDULER.try_lock()?;
+            let task = guard
+                .as_ref()?
+                .tasks
+                .iter()
+                .find(|task| task.id == victim.pid && task.state != TaskState::Dead)?;
+            let space = task.address_space.as_ref()?;
+            let entry = space.leaf_entry(victim.virt)?;
+
+            let flags = entry.flags();
+            if !flags.contains(PageTableFlags::PRESENT)
+                || flags.contains(PageTableFlags::DIRTY)
+                || entry.addr().as_u64() != victim.frame
+            {
+                return None;
+            }
+
+            let flags = (flags - PageTableFlags::PRESENT - PageTableFlags::ACCESSED) | SWAP_ENTRY;
+            entry.set_addr(PhysAddr::new(victim.slot * PAGE_SIZE as u64), flags);
+            if space.is_active() {
+                tlb::flush(victim.virt);
+            }
+            Some(())
+        })()
+        .is_some();
+
+    if swapped {
+        pmm::free(victim.frame);
+        SWAPPED_OUT.fetch_add(1, Ordering::Relaxed);
+    } else {
+        free_slot(victim.slot);
+    }
+    swapped
+}
+
+pub fn stats() -> SwapStats {
+    let (total_pages, used_pages) = interrupts::without_interrupts(|| {
+        let areas = AREAS.lock();
+        (
+            areas.iter().map(|area| area.slots.len()).sum(),
+            areas.iter().map(|area| area.used).sum(),
+        )
+    });
+    SwapStats {
+        total_pages,
+        used_pages,
+        swapped_out: SWAPPED_OUT.load(Ordering::Relaxed),
+        swapped_in: SWAPPED_IN.load(Ordering::Relaxed),
+    }
+}
@@ -3,12 +3,13 @@ use x86_64::{
     PhysAddr, VirtAddr,
     registers::control::Cr3,
     structures::paging::{
-        FrameAllocator, Mapper, OffsetPageTable, Page, PageTable, PageTableFlags, PhysFrame,
-        Size4KiB, Translate,
+        FrameAllocator, Mapper, OffsetPageTable, Page, PageSize, PageTable, PageTableFlags,
+        PhysFrame, Size1GiB, Size2MiB, Size4KiB, Translate, mapper::MapperFlush,
+        page_table::PageTableEntry,
     },
 };
 
-use crate::mem::pmm;
+use crate::mem::{GIANT_PAGE_SIZE, HUGE_PAGE_SIZE, PAGE_SIZE, oom, pmm, swap};
 
 static HHDM_OFFSET: Mutex<Option<u64>> = Mutex::new(None);
 static KERNEL_PML4_PHYS: Mutex<Option<PhysAddr>> = Mutex::new(None);
@@ -17,7 +18,7 @@ pub struct PmmFrameAllocator;
 
 unsafe impl FrameAllocator<Size4KiB> for PmmFrameAllocator {
     fn allocate_frame(&mut self) -> Option<PhysFrame<Size4KiB>> {
-        let addr = pmm::alloc()?;
+        let addr = oom::alloc_frame()?;
         Some(PhysFrame::containing_address(PhysAddr::new(addr)))
     }
 }
@@ -62,6 +63,48 @@ unsafe fn get_page_table_at(pml4_phys: PhysAddr) -> OffsetPageTable<'static> {
     unsafe { OffsetPageTable::new(pml4, VirtAddr::new(hhdm())) }
 }
 
+fn map_sized<S: PageSize>(
+    mapper: &mut OffsetPageTable<'static>,
+    virt: VirtAddr,
+    phys: PhysAddr,
+    flags: PageTableFlags,
+) -> Result<MapperFlush<S>, &'static str>
+where
+    OffsetPageTable<'static>: Mapper<S>,
+{
+    if !virt.is_aligned(S::SIZE) || !phys.is_aligned(S::SIZE) {
+        return Err("unaligned huge page mapping");
+    }
+
+    let page: Page<S> = Page::containing_address(virt);
+    let frame: PhysFrame<S> = PhysFrame::containing_address(phys);
+    let mut allocator = PmmFrameAllocator;
+
+    unsafe {
+        mapper
+            .map_to(page, frame, flags, &mut allocator)
+            .map_err(|_| "failed to map huge page")
+    }
+}
+
+fn alloc_zeroed(size: usize) -> Result<PhysAddr, &'static str> {
+    let pages = size / PAGE_SIZE;
+    let phys_addr = pmm::alloc_contiguous(pages, pages).ok_or("out of contiguous memory")?;
+    let phys = PhysAddr::new(phys_addr);
+
+    unsafe {
+        let virt_ptr = phys_to_virt(phys).as_mut_ptr::<u8>();
+        core::ptr::write_bytes(virt_ptr, 0, size);
+    }
+
+    Ok(phys)
+}
+
+pub fn supports_giant_pages() -> bool {
+    let result = core::arch::x86_64::__cpuid(0x8000_0001);
+    result.edx & (1 << 26) != 0
+}
+
 pub fn map_page(virt: VirtAddr, phys: PhysAddr, flags: PageTableFlags) -> Result<(), &'static str> {
     let page: Page<Size4KiB> = Page::containing_address(virt);
     let frame = PhysFrame::containing_address(phys);
@@ -79,8 +122,42 @@ pub fn map_page(virt: VirtAddr, phys: PhysAddr, flags: PageTableFlags) -> Result
     Ok(())
 }
 
+// firmware tables and mmio can sit outside the memory map limine covers with
+// the hhdm, map whatever is missing there
+pub fn map_physical(phys: PhysAddr, len: usize) -> Result<VirtAddr, &'static str> {
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE;
+    map_physical_with(phys, len, flags)
+}
+
+pub fn map_mmio(phys: PhysAddr, len: usize) -> Result<VirtAddr, &'static str> {
+    let flags = PageTableFlags::PRESENT
+        | PageTableFlags::WRITABLE
+        | PageTableFlags::NO_EXECUTE
+        | PageTableFlags::NO_CACHE;
+    map_physical_with(phys, len, flags)
+}
+
+fn map_physical_with(
+    phys: PhysAddr,
+    len: usize,
+    flags: PageTableFlags,
+) -> Result<VirtAddr, &'static str> {
+    let start = phys.align_down(PAGE_SIZE as u64).as_u64();
+    let end = (phys.as_u64() + len.max(1) as u64).next_multiple_of(PAGE_SIZE as u64);
+
+    for page in (start..end).step_by(PAGE_SIZE) {
+        let virt = phys_to_virt(PhysAddr::new(page));
+        let mapped = unsafe { get_current_page_table().translate_addr(virt).is_some() };
+        if !mapped {
+            map_page(virt, PhysAddr::new(page), flags)?;
+        }
+    }
+
+    Ok(phys_to_virt(phys))
+}
+
 pub fn map_page_alloc(virt: VirtAddr, flags: PageTableFlags) -> Result<PhysAddr, &'static str> {
-    let phys_addr = pmm::alloc().ok_or("out of memory")?;
+    let phys_addr = oom::alloc_frame().ok_or("out of memory")?;
     let phys = PhysAddr::new(phys_addr);
 
     unsafe {
@@ -92,6 +169,85 @@ pub fn map_page_alloc(virt: VirtAddr, flags: PageTableFlags) -> Result<PhysAddr,
     Ok(phys)
 }
 
+pub fn map_huge_page(
+    virt: VirtAddr,
+    phys: PhysAddr,
+    flags: PageTableFlags,
+) -> Result<(), &'static str> {
+    unsafe {
+        let mut mapper = get_current_page_table();
+        map_sized::<Size2MiB>(&mut mapper, virt, phys, flags)?.flush();
+    }
+
+    Ok(())
+}
+
+pub fn map_giant_page(
+    virt: VirtAddr,
+    phys: PhysAddr,
+    flags: PageTableFlags,
+) -> Result<(), &'static str> {
+    if !supports_giant_pages() {
+        return Err("1 GiB pages not supported");
+    }
+
+    unsafe {
+        let mut mapper = get_current_page_table();
+        map_sized::<Size1GiB>(&mut mapper, virt, phys, flags)?.flush();
+    }
+
+    Ok(())
+}
+
+pub fn map_huge_page_alloc(
+    virt: VirtAddr,
+    flags: PageTableFlags,
+) -> Result<PhysAddr, &'static str> {
+    let phys = alloc_zeroed(HUGE_PAGE_SIZE)?;
+
+    if let Err(e) = map_huge_page(virt, phys, flags) {
+        pmm::free_contiguous(phys.as_u64(), HUGE_PAGE_SIZE / PAGE_SIZE);
+        return Err(e);
+    }
+
+    Ok(phys)
+}
+
+pub fn map_range(
+    virt: VirtAddr,
+    phys: PhysAddr,
+    len: usize,
+    flags: PageTableFlags,
+) -> Result<(), &'static str> {
+    let mut offset = 0;
+
+    while offset < len {
+        let v = virt + offset as u64;
+        let p = phys + offset as u64;
+        let remaining = len - offset;
+
+        if remaining >= GIANT_PAGE_SIZE
+            && v.is_aligned(GIANT_PAGE_SIZE as u64)
+            && p.is_aligned(GIANT_PAGE_SIZE as u64)
+            && supports_giant_pages()
+        {
+            map_giant_page(v, p, flags)?;
+            offset += GIANT_PAGE_SIZE;
+        } else if remaining >= HUGE_PAGE_SIZE
+            && v.is_aligned(HUGE_PAGE_SIZE as u64)
+            && p.is_aligned(HUGE_PAGE_SIZE as u64)
+        {
+            map_huge_page(v, p, flags)?;
+            offset += HUGE_PAGE_SIZE;
+        } else {
+            map_page(v, p, flags)?;
+            offset += PAGE_SIZE;
+        }
+    }
+
+    Ok(())
+}
+
 pub fn unmap_page(virt: VirtAddr) -> Result<PhysAddr, &'static str> {
     let page: Page<Size4KiB> = Page::containing_address(virt);
 
@@ -116,7 +272,7 @@ pub struct AddressSpace {
 
 impl AddressSpace {
     pub fn new() -> Result<Self, &'static str> {
-        let pml4_phys_addr = pmm::alloc().ok_or("out of memory for PML4")?;
+        let pml4_phys_addr = oom::alloc_frame().ok_or("out of memory for PML4")?;
         let pml4_phys = PhysAddr::new(pml4_phys_addr);
 
         unsafe {
@@ -173,12 +329,94 @@ impl AddressSpace {
         Ok(())
     }
 
+    pub fn map_huge_page(
+        &self,
+        virt: VirtAddr,
+        phys: PhysAddr,
+        flags: PageTableFlags,
+    ) -> Result<(), &'static str> {
+        unsafe {
+            let mut mapper = get_page_table_at(self.pml4_phys);
+            map_sized::<Size2MiB>(&mut mapper, virt, phys, flags)?.ignore();
+        }
+
+        Ok(())
+    }
+
+    pub fn map_giant_page(
+        &self,
+        virt: VirtAddr,
+        phys: PhysAddr,
+        flags: PageTableFlags,
+    ) -> Result<(), &'static str> {
+        if !supports_giant_pages() {
+            return Err("1 GiB pages not supported");
+        }
+
+        unsafe {
+            let mut mapper = get_page_table_at(self.pml4_phys);
+            map_sized::<Size1GiB>(&mut mapper, virt, phys, flags)?.ignore();
+        }
+
+        Ok(())
+    }
+
+    pub fn map_huge_page_alloc(
+        &self,
+        virt: VirtAddr,
+        flags: PageTableFlags,
+    ) -> Result<PhysAddr, &'static str> {
+        let phys = alloc_zeroed(HUGE_PAGE_SIZE)?;
+
+        if let Err(e) = self.map_huge_page(virt, phys, flags) {
+            pmm::free_contiguous(phys.as_u64(), HUGE_PAGE_SIZE / PAGE_SIZE);
+            return Err(e);
+        }
+
+        Ok(phys)
+    }
+
+    pub fn map_anonymous(
+        &self,
+        virt: VirtAddr,
+        len: usize,
+        flags: PageTableFlags,
+    ) -> Result<(), &'static str> {
+        let start = virt.align_down(PAGE_SIZE as u64);
+        let end = (virt + len as u64).align_up(PAGE_SIZE as u64);
+        let mut addr = start;
+
+        while addr < end {
+            let remaining = (end - addr) as usize;
+
+            if remaining >= HUGE_PAGE_SIZE
+                && addr.is_aligned(HUGE_PAGE_SIZE as u64)
+                && !self.is_range_mapped(addr, HUGE_PAGE_SIZE)
+                && self.map_huge_page_alloc(addr, flags).is_ok()
+            {
+                addr += HUGE_PAGE_SIZE as u64;
+                continue;
+            }
+
+            if !self.is_mapped(addr) {
+                self.map_page_alloc(addr, flags)?;
+            }
+            addr += PAGE_SIZE as u64;
+        }
+
+        Ok(())
+    }
+
+    fn is_range_mapped(&self, virt: VirtAddr, len: usize) -> bool {
+        (0..len / PAGE_SIZE).any(|i| self.is_mapped(virt + (i * PAGE_SIZE) as u64))
+    }
+
     pub fn map_page_alloc(
         &self,
         virt: VirtAddr,
         flags: PageTableFlags,
     ) -> Result<PhysAddr, &'static str> {
-        let phys_addr = pmm::alloc().ok_or("out of memory")?;
+        let phys_addr = oom::alloc_frame().ok_or("out of memory")?;
         let phys = PhysAddr::new(phys_addr);
 
         unsafe {
@@ -190,6 +428,19 @@ impl AddressSpace {
         Ok(phys)
     }
 
+    pub fn unmap_page(&self, virt: VirtAddr) -> Result<PhysAddr, &'static str> {
+        let page: Page<Size4KiB> = Page::containing_address(virt);
+
+        unsafe {
+            let mut mapper = get_page_table_at(self.pml4_phys);
+            let (frame, flush) = mapper
+                .unmap(page)
+                .map_err(|_| "failed to unmap page in address space")?;
+            flush.flush();
+            Ok(frame.start_address())
+        }
+    }
+
     pub fn is_mapped(&self, virt: VirtAddr) -> bool {
         unsafe {
             let mapper = get_page_table_at(self.pml4_phys);
@@ -250,8 +501,116 @@ impl AddressSpace {
     }
 }
 
+fn walk_table(
+    table_phys: PhysAddr,
+    level: u8,
+    base: u64,
+    entries: core::ops::Range<usize>,
+    leaf: &mut dyn FnMut(VirtAddr, &mut PageTableEntry, usize),
+    free_tables: bool,
+) {
+    let table: &mut PageTable = unsafe { &mut *phys_to_virt(table_phys).as_mut_ptr() };
+    let span = 1u64 << (12 + 9 * (level as u64 - 1));
+
+    for i in entries {
+        let entry = &mut table[i];
+        if entry.is_unused() {
+            continue;
+        }
+
+        let virt = VirtAddr::new(base + i as u64 * span);
+        let flags = entry.flags();
+
+        if level == 1 || flags.contains(PageTableFlags::HUGE_PAGE) {
+            leaf(virt, entry, 512usize.pow(level as u32 - 1));
+        } else if flags.contains(PageTableFlags::PRESENT) {
+            walk_table(
+                entry.addr(),
+                level - 1,
+                virt.as_u64(),
+                0..512,
+                leaf,
+                free_tables,
+            );
+            if free_tables {
+                pmm::free(entry.addr().as_u64());
+            }
+        }
+    }
+}
+
+pub fn current_leaf_entry(virt: VirtAddr) -> Option<&'static mut PageTableEntry> {
+    let (pml4_frame, _) = Cr3::read();
+    leaf_entry(pml4_frame.start_address(), virt)
+}
+
+fn leaf_entry(pml4_phys: PhysAddr, virt: VirtAddr) -> Option<&'static mut PageTableEntry> {
+    let mut table_phys = pml4_phys;
+    let indices = [
+        virt.p4_index(),
+        virt.p3_index(),
+        virt.p2_index(),
+        virt.p1_index(),
+    ];
+
+    for (depth, index) in indices.into_iter().enumerate() {
+        let table: &'static mut PageTable = unsafe { &mut *phys_to_virt(table_phys).as_mut_ptr() };
+        let entry = &mut table[index];
+
+        if depth == 3 {
+            return Some(entry);
+        }
+
+        let flags = entry.flags();
+        if !flags.contains(PageTableFlags::PRESENT) || flags.contains(PageTableFlags::HUGE_PAGE) {
+            return None;
+        }
+        table_phys = entry.addr();
+    }
+
+    None
+}
+
+impl AddressSpace {
+    pub fn for_each_user_entry(&self, f: &mut dyn FnMut(VirtAddr, &mut PageTableEntry, usize)) {
+        walk_table(self.pml4_phys, 4, 0, 0..256, f, false);
+    }
+
+    pub fn is_active(&self) -> bool {
+        Cr3::read().0.start_address() == self.pml4_phys
+    }
+
+    pub fn leaf_entry(&self, virt: VirtAddr) -> Option<&'static mut PageTableEntry> {
+        leaf_entry(self.pml4_phys, virt)
+    }
+
+    pub fn resident_pages(&self) -> usize {
+        let mut count = 0;
+        self.for_each_user_entry(&mut |_, entry, pages| {
+            if entry.flags().contains(PageTableFlags::PRESENT) {
+                count += pages;
+            }
+        });
+        count
+    }
+}
+
 impl Drop for AddressSpace {
     fn drop(&mut self) {
-        // this leaks for now, ill do this later i guess
+        walk_table(
+            self.pml4_phys,
+            4,
+            0,
+            0..256,
+            &mut |_, entry, pages| {
+                if entry.flags().contains(PageTableFlags::PRESENT) {
+                    pmm::free_contiguous(entry.addr().as_u64(), pages);
+                } else {
+                    swap::release_entry(entry);
+                }
+            },
+            true,
+        );
+        pmm::free(self.pml4_phys.as_u64());
     }
 }
@@ -0,0 +1,97 @@
+use x86_64::instructions::{interrupts, port::Port};
+
+use crate::{acpi, cmdline, info, vfs, warn};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PowerAction {
+    PowerOff = 1,
+    Reboot = 2,
+    Halt = 3,
+}
+
+impl PowerAction {
+    pub fn from_u64(value: u64) -> Option<Self> {
+        match value {
+            1 => Some(Self::PowerOff),
+            2 => Some(Self::Reboot),
+            3 => Some(Self::Halt),
+            _ => None,
+        }
+    }
+}
+
+fn halt() -> ! {
+    interrupts::disable();
+    loop {
+        x86_64::instructions::hlt();
+    }
+}
+
+// qemu exits with (value << 1) | 1 when -device isa-debug-exit is present
+fn debug_exit(value: u32) {
+    if let Some(port) = cmdline::debug_exit_port() {
+        unsafe { Port::<u32>::new(port).write(value) };
+    }
+}
+
+/// ends a test run. qemu exits with 1 on success and 3 on failure
+#[cfg(test)]
+pub fn test_exit(passed: bool) {
+    debug_exit(if passed { 0 } else { 1 });
+}
+
+fn reset_8042() {
+    unsafe {
+        let mut cmd: Port<u8> = Port::new(0x64);
+        for _ in 0..100_000 {
+            if cmd.read() & 0x02 == 0 {
+                break;
+            }
+        }
+        cmd.write(0xFE);
+    }
+}
+
+fn triple_fault() -> ! {
+    let idt = x86_64::structures::DescriptorTablePointer {
+        limit: 0,
+        base: x86_64::VirtAddr::new(0),
+    };
+    unsafe {
+        x86_64::instructions::tables::lidt(&idt);
+        core::arch::asm!("int3", options(noreturn));
+    }
+}
+
+pub fn shutdown(action: PowerAction) -> ! {
+    info!("syncing filesystems");
+    if let Err(e) = vfs::sync() {
+        warn!("sync failed: {:?}", e);
+    }
+
+    interrupts::disable();
+
+    match action {
+        PowerAction::Halt => {
+            info!("system halted");
+            halt();
+        }
+        PowerAction::PowerOff => {
+            info!("powering off");
+            debug_exit(0);
+            if let Err(e) = acpi::enter_s5() {
+                warn!("ACPI poweroff failed: {}", e);
+            }
+            warn!("poweroff failed, halting");
+            halt();
+        }
+        PowerAction::Reboot => {
+            info!("rebooting");
+            if let Err(e) = acpi::reset() {
+                warn!("ACPI reset failed: {}", e);
+            }
+            reset_8042();
+            triple_fault();
+        }
+    }
+}
@@ -1,31 +1,46 @@
 pub mod switch;
 pub mod task;
 
-use alloc::{collections::VecDeque, string::String};
+use alloc::{collections::VecDeque, string::String, vec, vec::Vec};
+use core::sync::atomic::{AtomicBool, Ordering};
 use spin::Mutex;
 use switch::switch_context;
-use task::{Task, TaskState};
+use task::{Credentials, Task, TaskMode, TaskState};
 use x86_64::{VirtAddr, structures::paging::PageTableFlags};
 
 use crate::{
     cpu::{self, gdt},
     elf, info,
     mem::vmm::AddressSpace,
-    vfs::{VfsError, VfsResult, fd::FdTable},
+    vfs::{self, OpenFlags, VfsError, VfsResult, fd::FdTable},
 };
 
 pub static SCHEDULER: Mutex<Option<Scheduler>> = Mutex::new(None);
+static RESCHEDULE: AtomicBool = AtomicBool::new(false);
 
 pub struct Scheduler {
     pub tasks: VecDeque<Task>,
-    current: usize,
+    pub current: usize,
+    pub init: Option<u64>,
+    exited: Vec<ExitStatus>,
 }
 
+#[derive(Debug, Clone, Copy)]
+pub struct ExitStatus {
+    pub pid: u64,
+    pub parent: u64,
+    pub code: u64,
+}
+
+pub const EXIT_KILLED: u64 = 255;
+
 impl Scheduler {
     pub fn new() -> Self {
         Self {
             tasks: VecDeque::new(),
             current: 0,
+            init: None,
+            exited: Vec::new(),
         }
     }
 
@@ -57,6 +72,54 @@ impl Scheduler {
         }
     }
 
+    // marks the task dead, leaves its status for the parent and hands its
+    // children and their unreaped statuses over to init
+    pub fn terminate(&mut self, idx: usize, code: u64) {
+        let task = &mut self.tasks[idx];
+        task.state = TaskState::Dead;
+        let pid = task.id;
+
+        if let Some(parent) = task.parent {
+            self.exited.push(ExitStatus { pid, parent, code });
+        }
+
+        if self.init == Some(pid) {
+            crate::error!("init (pid {}) exited with code {}", pid, code);
+            self.init = None;
+        }
+
+        let adopter = self.init;
+        for task in self.tasks.iter_mut().filter(|t| t.parent == Some(pid)) {
+            task.parent = adopter;
+        }
+        match adopter {
+            Some(init) => self
+                .exited
+                .iter_mut()
+                .filter(|s| s.parent == pid)
+                .for_each(|s| s.parent = init),
+            None => self.exited.retain(|s| s.parent != pid),
+        }
+    }
+
+    // a task can be stopped anywhere in the kernel, holding locks and with
+    // frames on its stack, so it is only asked to exit and does so itself at
+    // the user boundary. a sleeper is woken to get it there
+    pub fn mark_killed(&mut self, idx: usize) {
+        let task = &mut self.tasks[idx];
+        task.kill_pending = true;
+        if task.state == TaskState::Sleeping {
+            task.state = TaskState::Ready;
+            task.wake_at = None;
+        }
+    }
+
+    pub fn find(&self, pid: u64) -> Option<usize> {
+        self.tasks
+            .iter()
+            .position(|t| t.id == pid && t.state != TaskState::Dead)
+    }
+
     pub fn current_task(&mut self) -> Option<&mut Task> {
         self.tasks.get_mut(self.current)
     }
@@ -90,31 +153,80 @@ pub fn spawn_elf(name: &str, elf_data: &[u8]) -> Result<u64, &'static str> {
     let user_stack_top_page: u64 = 0x7FFFF_F000;
     let user_stack_pages = 4;
 
-    for i in 0..user_stack_pages {
-        let page_addr = user_stack_top_page - (i * 4096);
-        address_space.map_page_alloc(VirtAddr::new(page_addr), flags)?;
-    }
+    let user_stack_bottom = user_stack_top_page - (user_stack_pages - 1) * 4096;
+    address_space.map_anonymous(
+        VirtAddr::new(user_stack_bottom),
+        user_stack_pages as usize * 4096,
+        flags,
+    )?;
 
     let user_stack_top = user_stack_top_page + 4096 - 8;
 
-    let task = Task::new_user(name, address_space, loaded.entry, user_stack_top);
+    let mut task = Task::new_user(name, address_space, loaded.entry, user_stack_top);
     let id = task.id;
 
     info!("spawned task with PID: {}", id);
 
     if let Some(sched) = SCHEDULER.lock().as_mut() {
+        if let Some(parent) = sched.current_task()
+            && parent.mode == TaskMode::User
+        {
+            task.parent = Some(parent.id);
+            task.cwd = parent.cwd.clone();
+            task.creds = parent.creds.clone();
+        }
         sched.add_task(task);
     }
 
     Ok(id)
 }
 
+pub fn spawn_path(path: &str) -> Result<u64, &'static str> {
+    let mut file = vfs::open(path, OpenFlags::O_RDONLY).map_err(|_| "cannot open executable")?;
+    let size = file.metadata().map_err(|_| "cannot stat executable")?.size;
+
+    let mut data = vec![0u8; size];
+    let mut done = 0;
+    while done < size {
+        match file.read(&mut data[done..]) {
+            Ok(0) => return Err("short read on executable"),
+            Ok(n) => done += n,
+            Err(_) => return Err("failed to read executable"),
+        }
+    }
+
+    let name = path.rsplit('/').next().unwrap_or(path);
+    spawn_elf(name, &data)
+}
+
 pub fn schedule() {
+    switch_task(true);
+}
+
+/// asks for a task switch once the interrupt being handled is acknowledged
+pub fn request_schedule() {
+    RESCHEDULE.store(true, Ordering::Relaxed);
+}
+
+/// makes the switch an interrupt asked for. it waits while the interrupted
+/// code holds the scheduler, which would otherwise be spun on forever
+pub fn preempt() {
+    if RESCHEDULE.load(Ordering::Relaxed) && !SCHEDULER.is_locked() {
+        RESCHEDULE.store(false, Ordering::Relaxed);
+        switch_task(false);
+    }
+}
+
+// dead tasks are only freed on a voluntary switch. under an interrupt the
+// code below may hold the heap, or a lock their teardown needs
+fn switch_task(reap: bool) {
     x86_64::instructions::interrupts::without_interrupts(|| {
         let switch_info = {
             let mut guard = SCHEDULER.lock();
             if let Some(sched) = guard.as_mut() {
-                sched.reap_dead();
+                if reap {
+                    sched.reap_dead();
+                }
 
                 let current_tick = cpu::ticks();
                 for task in sched.tasks.iter_mut() {
@@ -158,6 +270,7 @@ pub fn schedule() {
         if let Some((old_sp, new_sp, new_cr3, kernel_stack)) = switch_info {
             if kernel_stack != 0 {
                 gdt::set_kernel_stack(x86_64::VirtAddr::new(kernel_stack));
+                cpu::syscall::set_kernel_stack(kernel_stack);
             }
             unsafe { switch_context(old_sp, new_sp, new_cr3) };
         }
@@ -180,10 +293,17 @@ pub fn sleep(ticks: u64) {
     schedule();
 }
 
-pub fn exit() {
+pub fn set_init(pid: u64) {
+    if let Some(sched) = SCHEDULER.lock().as_mut() {
+        sched.init = Some(pid);
+    }
+}
+
+pub fn exit(code: u64) {
     x86_64::instructions::interrupts::without_interrupts(|| {
         if let Some(sched) = SCHEDULER.lock().as_mut() {
-            sched.tasks[sched.current].state = TaskState::Dead;
+            let current = sched.current;
+            sched.terminate(current, code);
         }
     });
 
@@ -194,6 +314,88 @@ pub fn exit() {
     }
 }
 
+// the task goes once it is back at the user boundary, so a task killing
+// itself goes on the way out of this syscall
+pub fn kill(pid: u64) -> Result<(), &'static str> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut guard = SCHEDULER.lock();
+        let sched = guard.as_mut().ok_or("scheduler not running")?;
+        let idx = sched.find(pid).ok_or("no such task")?;
+
+        if sched.tasks[idx].mode != TaskMode::User {
+            return Err("cannot kill a kernel task");
+        }
+        if sched.init == Some(pid) {
+            return Err("cannot kill init");
+        }
+        let caller = &sched.tasks[sched.current].creds;
+        if !caller.is_root() && caller.uid != sched.tasks[idx].creds.uid {
+            return Err("not the owner of that task");
+        }
+
+        sched.mark_killed(idx);
+        Ok(())
+    })
+}
+
+/// whether the current task has been killed. anything that blocks for long
+/// gives up when it has, so the task can reach the user boundary
+pub fn kill_pending() -> bool {
+    with_current_task(|task| task.kill_pending).unwrap_or(false)
+}
+
+/// ends the current task if it has been killed. only called on the way back
+/// to user mode, where it holds nothing in the kernel
+pub fn exit_if_killed() {
+    if kill_pending() {
+        exit(EXIT_KILLED);
+    }
+}
+
+// waits for a child of the current task to exit. `pid` of None matches any
+// child, a `timeout` of None waits forever. Ok(None) means the timeout passed
+pub fn wait(pid: Option<u64>, timeout: Option<u64>) -> Result<Option<ExitStatus>, &'static str> {
+    let deadline = timeout.map(|ticks| cpu::ticks() + ticks);
+
+    loop {
+        let status = x86_64::instructions::interrupts::without_interrupts(|| {
+            let mut guard = SCHEDULER.lock();
+            let sched = guard.as_mut().ok_or("scheduler not running")?;
+            let me = sched.tasks[sched.current].id;
+            let matches = |child: u64| pid.is_none_or(|pid| pid == child);
+
+            if let Some(i) = sched
+                .exited
+                .iter()
+                .position(|s| s.parent == me && matches(s.pid))
+            {
+                return Ok(Some(sched.exited.remove(i)));
+            }
+
+            let running = sched
+                .tasks
+                .iter()
+                .any(|t| t.parent == Some(me) && t.state != TaskState::Dead && matches(t.id));
+            if running {
+                Ok(None)
+            } else {
+                Err("no children")
+            }
+        })?;
+
+        if status.is_some() {
+            return Ok(status);
+        }
+        if deadline.is_some_and(|deadline| cpu::ticks() >= deadline) {
+            return Ok(None);
+        }
+        if kill_pending() {
+            return Err("interrupted");
+        }
+        sleep(1);
+    }
+}
+
 pub fn with_fd_table<F, R>(f: F) -> VfsResult<R>
 where
     F: FnOnce(&mut FdTable) -> VfsResult<R>,
@@ -204,12 +406,40 @@ where
     f(&mut task.fds)
 }
 
+pub fn with_current_task<F, R>(f: F) -> Option<R>
+where
+    F: FnOnce(&mut Task) -> R,
+{
+    let mut guard = SCHEDULER.lock();
+    let sched = guard.as_mut()?;
+    let task = sched.current_task()?;
+    Some(f(task))
+}
+
 pub fn get_cwd() -> Option<String> {
     let guard = SCHEDULER.lock();
     let sched = guard.as_ref()?;
     Some(sched.tasks.get(sched.current)?.cwd.clone())
 }
 
+/// credentials of the current task. the kernel itself acts as root
+pub fn credentials() -> Credentials {
+    let guard = SCHEDULER.lock();
+    guard
+        .as_ref()
+        .and_then(|sched| sched.tasks.get(sched.current))
+        .map(|task| task.creds.clone())
+        .unwrap_or_default()
+}
+
+pub fn set_credentials(creds: Credentials) -> Result<(), ()> {
+    let mut guard = SCHEDULER.lock();
+    let sched = guard.as_mut().ok_or(())?;
+    let task = sched.tasks.get_mut(sched.current).ok_or(())?;
+    task.creds = creds;
+    Ok(())
+}
+
 pub fn set_cwd(path: String) -> Result<(), ()> {
     let mut guard = SCHEDULER.lock();
     let sched = guard.as_mut().ok_or(())?;
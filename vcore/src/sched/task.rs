@@ -1,7 +1,12 @@
 use alloc::{string::String, vec::Vec};
 use core::sync::atomic::{AtomicU64, Ordering};
+use x86_64::VirtAddr;
 
-use crate::{cpu, mem::vmm::AddressSpace, vfs::fd::FdTable};
+use crate::{
+    cpu,
+    mem::{PAGE_SIZE, shm::ShmMapping, vmm::AddressSpace},
+    vfs::fd::FdTable,
+};
 
 static NEXT_ID: AtomicU64 = AtomicU64::new(0);
 
@@ -19,20 +24,43 @@ pub enum TaskMode {
     User,
 }
 
+/// who a task acts as when it touches files. uid 0 is root
+#[derive(Debug, Clone, Default)]
+pub struct Credentials {
+    pub uid: u32,
+    pub gid: u32,
+    pub groups: Vec<u32>,
+}
+
+impl Credentials {
+    pub fn is_root(&self) -> bool {
+        self.uid == 0
+    }
+
+    pub fn in_group(&self, gid: u32) -> bool {
+        self.gid == gid || self.groups.contains(&gid)
+    }
+}
+
 pub struct Task {
     pub id: u64,
     pub name: String,
     pub state: TaskState,
     pub mode: TaskMode,
+    pub parent: Option<u64>,
     pub stack_ptr: u64,
     pub cr3: u64,
     pub kernel_stack_top: u64,
     pub wake_at: Option<u64>,
+    // killed, but left to exit by itself once it is back at the user boundary
+    pub kill_pending: bool,
     pub user_entry: u64,
     pub user_stack: u64,
     pub fds: FdTable,
     pub cwd: String,
+    pub creds: Credentials,
     pub address_space: Option<AddressSpace>,
+    pub shm_maps: Vec<ShmMapping>,
     _stack: Vec<u8>,
 }
 
@@ -41,7 +69,7 @@ extern "C" fn entry_wrapper(entry: fn()) -> ! {
     x86_64::instructions::interrupts::enable();
 
     entry();
-    super::exit();
+    super::exit(0);
     unreachable!();
 }
 
@@ -115,15 +143,19 @@ impl Task {
             name: String::from(name),
             state: TaskState::Ready,
             mode: TaskMode::Kernel,
+            parent: None,
             stack_ptr: sp,
             cr3: pml4_frame.start_address().as_u64(),
             kernel_stack_top: stack_top,
             wake_at: None,
+            kill_pending: false,
             user_entry: 0,
             user_stack: 0,
             fds: FdTable::new(),
             cwd: String::from("/"),
+            creds: Credentials::default(),
             address_space: None,
+            shm_maps: Vec::new(),
             _stack: stack,
         }
     }
@@ -166,15 +198,19 @@ impl Task {
             name: String::from(name),
             state: TaskState::Ready,
             mode: TaskMode::User,
+            parent: None,
             stack_ptr: sp,
             cr3,
             kernel_stack_top: stack_top,
             wake_at: None,
+            kill_pending: false,
             user_entry,
             user_stack,
             fds: FdTable::new(),
             cwd: String::from("/"),
+            creds: Credentials::default(),
             address_space: Some(address_space),
+            shm_maps: Vec::new(),
             _stack: stack,
         }
     }
@@ -187,16 +223,41 @@ impl Task {
             name: String::from("sched"),
             state: TaskState::Running,
             mode: TaskMode::Kernel,
+            parent: None,
             stack_ptr: 0,
             cr3: pml4_frame.start_address().as_u64(),
             kernel_stack_top: 0,
             wake_at: None,
+            kill_pending: false,
             user_entry: 0,
             user_stack: 0,
             fds: FdTable::new(),
             cwd: String::from("/"),
+            creds: Credentials::default(),
             address_space: None,
+            shm_maps: Vec::new(),
             _stack: Vec::new(),
         }
     }
 }
+
+impl Task {
+    pub fn resident_pages(&self) -> usize {
+        self.address_space
+            .as_ref()
+            .map_or(0, |space| space.resident_pages())
+    }
+}
+
+impl Drop for Task {
+    fn drop(&mut self) {
+        // shm frames belong to the object, keep them out of the address space teardown
+        if let Some(space) = &self.address_space {
+            for mapping in self.shm_maps.drain(..) {
+                for i in 0..mapping.pages {
+                    let _ = space.unmap_page(VirtAddr::new(mapping.addr + (i * PAGE_SIZE) as u64));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,25 @@
+use crate::{info, power};
+
+/// a `#[test_case]` function, named in the log as it runs
+pub trait Test {
+    fn run(&self);
+}
+
+impl<T: Fn()> Test for T {
+    fn run(&self) {
+        info!("test {}", core::any::type_name::<T>());
+        self();
+    }
+}
+
+// called from kmain once memory and clocks are up. a failed test panics, and
+// the panic handler reports it the same way
+pub fn run(tests: &[&dyn Test]) -> ! {
+    info!("running {} tests", tests.len());
+    for test in tests {
+        test.run();
+    }
+    info!("all tests passed");
+    power::test_exit(true);
+    crate::hcf()
+}
@@ -0,0 +1,515 @@
+use alloc::{
+    boxed::Box,
+    collections::BTreeMap,
+    string::{String, ToString},
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
+
+use super::types::*;
+
+const BLOCK_SIZE: usize = 512;
+const CPIO_HEADER_SIZE: usize = 110;
+const NS_PER_SEC: u64 = 1_000_000_000;
+
+const S_IFMT: u32 = 0o170000;
+const S_IFDIR: u32 = 0o040000;
+const S_IFREG: u32 = 0o100000;
+const S_IFLNK: u32 = 0o120000;
+
+#[derive(Clone)]
+enum Archive {
+    Static(&'static [u8]),
+    Owned(Arc<[u8]>),
+}
+
+impl Archive {
+    fn bytes(&self) -> &[u8] {
+        match self {
+            Archive::Static(data) => data,
+            Archive::Owned(data) => data,
+        }
+    }
+}
+
+enum NodeKind {
+    File { start: usize, len: usize },
+    Directory,
+    Symlink(String),
+}
+
+struct Node {
+    kind: NodeKind,
+    mode: u32,
+    uid: u32,
+    gid: u32,
+    mtime: u64,
+    id: u64,
+}
+
+struct ArchiveData {
+    archive: Archive,
+    nodes: BTreeMap<String, Node>,
+    next_id: u64,
+}
+
+pub struct ArchiveFs {
+    data: Arc<ArchiveData>,
+}
+
+fn parse_octal(field: &[u8]) -> Option<usize> {
+    let mut value = 0usize;
+    for &b in field {
+        match b {
+            b'0'..=b'7' => value = value.checked_mul(8)? + (b - b'0') as usize,
+            b' ' | 0 if value == 0 => continue,
+            b' ' | 0 => break,
+            _ => return None,
+        }
+    }
+    Some(value)
+}
+
+fn parse_hex(field: &[u8]) -> Option<usize> {
+    let s = core::str::from_utf8(field).ok()?;
+    usize::from_str_radix(s, 16).ok()
+}
+
+fn parse_str(field: &[u8]) -> &str {
+    let len = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    core::str::from_utf8(&field[..len]).unwrap_or("")
+}
+
+fn clean_path(path: &str) -> String {
+    path.split('/')
+        .filter(|part| !part.is_empty() && *part != ".")
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn parent_of(path: &str) -> &str {
+    path.rfind('/').map_or("", |idx| &path[..idx])
+}
+
+fn join(dir: &str, name: &str) -> String {
+    if dir.is_empty() {
+        name.to_string()
+    } else {
+        alloc::format!("{}/{}", dir, name)
+    }
+}
+
+impl ArchiveFs {
+    pub fn new(data: &'static [u8]) -> Result<Self, &'static str> {
+        Self::parse(Archive::Static(data))
+    }
+
+    pub fn from_file(path: &str) -> Result<Self, &'static str> {
+        let mut file = super::open(path, OpenFlags::O_RDONLY).map_err(|_| "cannot open archive")?;
+        let size = file.metadata().map_err(|_| "cannot stat archive")?.size;
+
+        let mut data = vec![0u8; size];
+        let mut done = 0;
+        while done < size {
+            match file.read(&mut data[done..]) {
+                Ok(0) => return Err("short read on archive"),
+                Ok(n) => done += n,
+                Err(_) => return Err("failed to read archive"),
+            }
+        }
+
+        Self::parse(Archive::Owned(Arc::from(data)))
+    }
+
+    fn parse(archive: Archive) -> Result<Self, &'static str> {
+        let mut fs = ArchiveData {
+            archive,
+            nodes: BTreeMap::new(),
+            next_id: 1,
+        };
+        fs.nodes.insert(
+            String::new(),
+            Node {
+                kind: NodeKind::Directory,
+                mode: 0o755,
+                uid: 0,
+                gid: 0,
+                mtime: 0,
+                id: 0,
+            },
+        );
+
+        let archive = fs.archive.clone();
+        let data = archive.bytes();
+        if data.starts_with(b"070701") || data.starts_with(b"070702") {
+            fs.parse_cpio(data)?;
+        } else {
+            fs.parse_tar(data)?;
+        }
+
+        Ok(Self { data: Arc::new(fs) })
+    }
+}
+
+impl ArchiveData {
+    fn parse_tar(&mut self, data: &[u8]) -> Result<(), &'static str> {
+        let mut offset = 0;
+
+        while offset + BLOCK_SIZE <= data.len() {
+            let header = &data[offset..offset + BLOCK_SIZE];
+            if header.iter().all(|&b| b == 0) {
+                break;
+            }
+
+            if &header[257..262] != b"ustar" {
+                return Err("not a ustar archive");
+            }
+
+            let size = parse_octal(&header[124..136]).ok_or("bad tar entry size")?;
+            let mode = parse_octal(&header[100..108]).unwrap_or(0o644) as u32 & 0o7777;
+            let uid = parse_octal(&header[108..116]).unwrap_or(0) as u32;
+            let gid = parse_octal(&header[116..124]).unwrap_or(0) as u32;
+            let mtime = parse_octal(&header[136..148]).unwrap_or(0) as u64 * NS_PER_SEC;
+            let name = parse_str(&header[0..100]);
+            let prefix = parse_str(&header[345..500]);
+            let path = if prefix.is_empty() {
+                clean_path(name)
+            } else {
+                clean_path(&join(prefix, name))
+            };
+            let link = parse_str(&header[157..257]);
+
+            let start = offset + BLOCK_SIZE;
+            let end = start.checked_add(size).ok_or("bad tar entry size")?;
+            if end > data.len() {
+                return Err("truncated tar archive");
+            }
+
+            // a hard link shares the id of the file it links to
+            let (kind, id) = match header[156] {
+                b'0' | 0 => (Some(NodeKind::File { start, len: size }), 0),
+                b'5' => (Some(NodeKind::Directory), 0),
+                b'2' => (Some(NodeKind::Symlink(link.to_string())), 0),
+                b'1' => match self.nodes.get(&clean_path(link)) {
+                    Some(Node {
+                        kind: NodeKind::File { start, len },
+                        id,
+                        ..
+                    }) => (
+                        Some(NodeKind::File {
+                            start: *start,
+                            len: *len,
+                        }),
+                        *id,
+                    ),
+                    _ => (None, 0),
+                },
+                _ => (None, 0),
+            };
+
+            if let Some(kind) = kind {
+                self.insert(
+                    path,
+                    Node {
+                        kind,
+                        mode,
+                        uid,
+                        gid,
+                        mtime,
+                        id,
+                    },
+                );
+            }
+
+            offset = start + size.div_ceil(BLOCK_SIZE) * BLOCK_SIZE;
+        }
+
+        Ok(())
+    }
+
+    fn parse_cpio(&mut self, data: &[u8]) -> Result<(), &'static str> {
+        let mut offset = 0;
+        // hard links share an inode and only one of them, normally the last,
+        // carries the data. (ino, dev major, dev minor) -> id, data, names
+        type Links = BTreeMap<(usize, usize, usize), (u64, Option<(usize, usize)>, Vec<String>)>;
+        let mut links = Links::new();
+
+        while offset + CPIO_HEADER_SIZE <= data.len() {
+            let header = &data[offset..offset + CPIO_HEADER_SIZE];
+            if &header[0..5] != b"07070" {
+                return Err("not a newc cpio archive");
+            }
+
+            let field = |i: usize| parse_hex(&header[6 + i * 8..14 + i * 8]);
+            let mode = field(1).ok_or("bad cpio header")? as u32;
+            let size = field(6).ok_or("bad cpio header")?;
+            let name_size = field(11).ok_or("bad cpio header")?;
+
+            let name_start = offset + CPIO_HEADER_SIZE;
+            let name_end = name_start + name_size;
+            if name_end > data.len() {
+                return Err("truncated cpio archive");
+            }
+            let name = parse_str(&data[name_start..name_end]);
+
+            let start = name_end.next_multiple_of(4);
+            let end = start.checked_add(size).ok_or("bad cpio entry size")?;
+            if end > data.len() {
+                return Err("truncated cpio archive");
+            }
+
+            if name == "TRAILER!!!" {
+                break;
+            }
+
+            let kind = match mode & S_IFMT {
+                S_IFREG => Some(NodeKind::File { start, len: size }),
+                S_IFDIR => Some(NodeKind::Directory),
+                S_IFLNK => Some(NodeKind::Symlink(parse_str(&data[start..end]).to_string())),
+                _ => None,
+            };
+
+            if let Some(mut kind) = kind {
+                let path = clean_path(name);
+                let mut id = 0;
+
+                if let NodeKind::File { start, len } = kind
+                    && field(4).unwrap_or(1) > 1
+                {
+                    let key = (
+                        field(0).unwrap_or(0),
+                        field(7).unwrap_or(0),
+                        field(8).unwrap_or(0),
+                    );
+                    let (link_id, shared, names) = links
+                        .entry(key)
+                        .or_insert_with(|| (self.next_id(), None, Vec::new()));
+
+                    id = *link_id;
+                    if len > 0 {
+                        for name in names.iter() {
+                            if let Some(node) = self.nodes.get_mut(name) {
+                                node.kind = NodeKind::File { start, len };
+                            }
+                        }
+                        *shared = Some((start, len));
+                    } else if let Some((start, len)) = *shared {
+                        kind = NodeKind::File { start, len };
+                    }
+                    names.push(path.clone());
+                }
+
+                self.insert(
+                    path,
+                    Node {
+                        kind,
+                        mode: mode & 0o7777,
+                        uid: field(2).unwrap_or(0) as u32,
+                        gid: field(3).unwrap_or(0) as u32,
+                        mtime: field(5).unwrap_or(0) as u64 * NS_PER_SEC,
+                        id,
+                    },
+                );
+            }
+
+            offset = end.next_multiple_of(4);
+        }
+
+        Ok(())
+    }
+
+    fn next_id(&mut self) -> u64 {
+        self.next_id += 1;
+        self.next_id - 1
+    }
+
+    fn insert(&mut self, path: String, mut node: Node) {
+        if path.is_empty() {
+            return;
+        }
+
+        let mut parent = parent_of(&path);
+        while !parent.is_empty() && !self.nodes.contains_key(parent) {
+            let id = self.next_id();
+            self.nodes.insert(
+                parent.to_string(),
+                Node {
+                    kind: NodeKind::Directory,
+                    mode: 0o755,
+                    uid: 0,
+                    gid: 0,
+                    mtime: 0,
+                    id,
+                },
+            );
+            parent = parent_of(parent);
+        }
+
+        // a later entry for the same path replaces the earlier one
+        if node.id == 0 {
+            node.id = match self.nodes.get(&path) {
+                Some(old) => old.id,
+                None => self.next_id(),
+            };
+        }
+        self.nodes.insert(path, node);
+    }
+}
+
+struct ArchiveFileHandle {
+    archive: Archive,
+    start: usize,
+    len: usize,
+    position: usize,
+    meta: Metadata,
+}
+
+impl FileHandle for ArchiveFileHandle {
+    fn read(&mut self, buf: &mut [u8]) -> VfsResult<usize> {
+        let available = self.len.saturating_sub(self.position);
+        let to_read = buf.len().min(available);
+        let from = self.start + self.position;
+        buf[..to_read].copy_from_slice(&self.archive.bytes()[from..from + to_read]);
+        self.position += to_read;
+        Ok(to_read)
+    }
+
+    fn write(&mut self, _buf: &[u8]) -> VfsResult<usize> {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn seek(&mut self, pos: SeekFrom) -> VfsResult<usize> {
+        let new_pos = match pos {
+            SeekFrom::Start(n) => n as isize,
+            SeekFrom::Current(n) => self.position as isize + n,
+            SeekFrom::End(n) => self.len as isize + n,
+        };
+        if new_pos < 0 {
+            return Err(VfsError::InvalidPath);
+        }
+        self.position = new_pos as usize;
+        Ok(self.position)
+    }
+
+    fn metadata(&self) -> VfsResult<Metadata> {
+        Ok(self.meta.clone())
+    }
+}
+
+// directories, files and links alike are named by their path
+struct ArchiveNode {
+    data: Arc<ArchiveData>,
+    path: String,
+}
+
+impl ArchiveNode {
+    fn node(&self) -> &Node {
+        &self.data.nodes[&self.path]
+    }
+}
+
+impl Filesystem for ArchiveFs {
+    fn root(&self) -> InodeRef {
+        Arc::new(ArchiveNode {
+            data: self.data.clone(),
+            path: String::new(),
+        })
+    }
+
+    fn name(&self) -> &'static str {
+        "archivefs"
+    }
+}
+
+impl Inode for ArchiveNode {
+    fn id(&self) -> u64 {
+        self.node().id
+    }
+
+    fn metadata(&self) -> VfsResult<Metadata> {
+        let node = self.node();
+        let (file_type, size, mode) = match node.kind {
+            NodeKind::File { len, .. } => (FileType::File, len, node.mode),
+            NodeKind::Directory => (FileType::Directory, 0, node.mode),
+            NodeKind::Symlink(ref target) => (FileType::Symlink, target.len(), 0o777),
+        };
+        Ok(Metadata {
+            file_type,
+            size,
+            uid: node.uid,
+            gid: node.gid,
+            mode,
+            atime: node.mtime,
+            mtime: node.mtime,
+            ctime: node.mtime,
+        })
+    }
+
+    fn open(self: Arc<Self>, flags: OpenFlags) -> VfsResult<Box<dyn FileHandle>> {
+        if flags.is_writable() {
+            return Err(VfsError::PermissionDenied);
+        }
+
+        let node = self.node();
+        if node.mode & 0o444 == 0 {
+            return Err(VfsError::PermissionDenied);
+        }
+
+        match node.kind {
+            NodeKind::File { start, len } => Ok(Box::new(ArchiveFileHandle {
+                archive: self.data.archive.clone(),
+                start,
+                len,
+                position: 0,
+                meta: self.metadata()?,
+            })),
+            NodeKind::Directory => Err(VfsError::IsADirectory),
+            NodeKind::Symlink(_) => Err(VfsError::Loop),
+        }
+    }
+
+    fn lookup(&self, name: &str) -> VfsResult<InodeRef> {
+        if !matches!(self.node().kind, NodeKind::Directory) {
+            return Err(VfsError::NotADirectory);
+        }
+
+        let path = join(&self.path, name);
+        if !self.data.nodes.contains_key(&path) {
+            return Err(VfsError::NotFound);
+        }
+        Ok(Arc::new(ArchiveNode {
+            data: self.data.clone(),
+            path,
+        }))
+    }
+
+    fn readlink(&self) -> VfsResult<String> {
+        match &self.node().kind {
+            NodeKind::Symlink(target) => Ok(target.clone()),
+            _ => Err(VfsError::InvalidPath),
+        }
+    }
+
+    fn readdir(&self) -> VfsResult<Vec<DirEntry>> {
+        if !matches!(self.node().kind, NodeKind::Directory) {
+            return Err(VfsError::NotADirectory);
+        }
+
+        let dir = &self.path;
+        Ok(self
+            .data
+            .nodes
+            .iter()
+            .filter(|(name, _)| !name.is_empty() && parent_of(name) == dir)
+            .map(|(name, node)| DirEntry {
+                name: name.rsplit('/').next().unwrap_or(name).to_string(),
+                file_type: match node.kind {
+                    NodeKind::File { .. } => FileType::File,
+                    NodeKind::Directory => FileType::Directory,
+                    NodeKind::Symlink(_) => FileType::Symlink,
+                },
+            })
+            .collect())
+    }
+}
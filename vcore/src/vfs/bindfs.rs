@@ -0,0 +1,39 @@
+use alloc::sync::Arc;
+
+use super::types::*;
+
+/// a directory that is already mounted, shown again somewhere else, so the
+/// same tree can be reached with different mount flags
+pub struct BindFs {
+    root: InodeRef,
+    fs: Arc<dyn Filesystem>,
+}
+
+impl BindFs {
+    pub fn new(path: &str) -> VfsResult<Self> {
+        let (root, fs) = super::inode_at(path)?;
+        if root.metadata()?.file_type != FileType::Directory {
+            return Err(VfsError::NotADirectory);
+        }
+        Ok(Self { root, fs })
+    }
+}
+
+impl Filesystem for BindFs {
+    fn root(&self) -> InodeRef {
+        self.root.clone()
+    }
+
+    fn name(&self) -> &'static str {
+        "bind"
+    }
+
+    // the original mount's cache is the one that sees changes made through it
+    fn cache_dentries(&self) -> bool {
+        false
+    }
+
+    fn statfs(&self) -> VfsResult<FsStats> {
+        self.fs.statfs()
+    }
+}
@@ -19,4 +19,8 @@ impl BlockDevice for AtaDisk {
     fn write_sector(&self, lba: u32, buf: &[u8; SECTOR_SIZE]) -> Result<(), &'static str> {
         drivers::ata::write_sector(lba, buf)
     }
+
+    fn flush(&self) -> Result<(), &'static str> {
+        drivers::ata::flush()
+    }
 }
@@ -0,0 +1,85 @@
+use alloc::boxed::Box;
+use spin::Mutex;
+
+use super::{BlockDevice, SECTOR_SIZE};
+use crate::vfs::{self, FileHandle, FileType, OpenFlags, SeekFrom};
+
+/// a block device on top of anything the vfs can open, so a filesystem can
+/// be mounted from a /dev node or from an image file
+pub struct FileDevice {
+    handle: Mutex<Box<dyn FileHandle>>,
+    sector_count: u32,
+}
+
+impl FileDevice {
+    pub fn open(path: &str, writable: bool) -> Result<Self, &'static str> {
+        let flags = if writable {
+            OpenFlags::O_RDWR
+        } else {
+            OpenFlags::O_RDONLY
+        };
+        let handle = vfs::open(path, flags).map_err(|_| "cannot open device")?;
+        let meta = handle.metadata().map_err(|_| "cannot stat device")?;
+
+        if !matches!(meta.file_type, FileType::File | FileType::Device) {
+            return Err("not a file or block device");
+        }
+
+        Ok(Self {
+            handle: Mutex::new(handle),
+            sector_count: (meta.size / SECTOR_SIZE) as u32,
+        })
+    }
+}
+
+impl BlockDevice for FileDevice {
+    fn read_sector(&self, lba: u32, buf: &mut [u8; SECTOR_SIZE]) -> Result<(), &'static str> {
+        if lba >= self.sector_count {
+            return Err("sector out of bounds");
+        }
+
+        let mut handle = self.handle.lock();
+        handle
+            .seek(SeekFrom::Start(lba as usize * SECTOR_SIZE))
+            .map_err(|_| "seek failed")?;
+
+        let mut done = 0;
+        while done < SECTOR_SIZE {
+            match handle.read(&mut buf[done..]) {
+                Ok(0) => return Err("short read"),
+                Ok(n) => done += n,
+                Err(_) => return Err("read failed"),
+            }
+        }
+        Ok(())
+    }
+
+    fn write_sector(&self, lba: u32, buf: &[u8; SECTOR_SIZE]) -> Result<(), &'static str> {
+        if lba >= self.sector_count {
+            return Err("sector out of bounds");
+        }
+
+        let mut handle = self.handle.lock();
+        handle
+            .seek(SeekFrom::Start(lba as usize * SECTOR_SIZE))
+            .map_err(|_| "seek failed")?;
+
+        let mut done = 0;
+        while done < SECTOR_SIZE {
+            match handle.write(&buf[done..]) {
+                Ok(0) => return Err("short write"),
+                Ok(n) => done += n,
+                Err(_) => return Err("write failed"),
+            }
+        }
+        Ok(())
+    }
+
+    fn sector_count(&self) -> Option<u32> {
+        Some(self.sector_count)
+    }
+
+    fn flush(&self) -> Result<(), &'static str> {
+        self.handle.lock().sync().map_err(|_| "sync failed")
+    }
+}
@@ -1,17 +1,22 @@
 pub mod ata;
+pub mod file;
 pub mod partition;
 
-use alloc::{boxed::Box, collections::BTreeMap, string::String, vec::Vec};
-use spin::Mutex;
+use alloc::{boxed::Box, collections::BTreeMap, string::String, sync::Arc, vec::Vec};
+use spin::{Lazy, Mutex};
 
 use super::types::*;
+use crate::cpu::time;
 
 pub use ata::AtaDisk;
+pub use file::FileDevice;
 pub use partition::{
-    PartitionInfo, PartitionType, find_partition, first_partition, parse_partitions,
+    PartitionInfo, PartitionType, find_partition, first_partition, format_guid, parse_guid,
+    parse_partitions,
 };
 
 pub const SECTOR_SIZE: usize = 512;
+const SECTORS_PER_PAGE: usize = crate::mem::PAGE_SIZE / SECTOR_SIZE;
 
 pub trait BlockDevice: Send + Sync {
     fn read_sector(&self, lba: u32, buf: &mut [u8; SECTOR_SIZE]) -> Result<(), &'static str>;
@@ -28,6 +33,11 @@ pub trait BlockDevice: Send + Sync {
     fn num_blocks(&self) -> u64 {
         self.sector_count().unwrap_or(0) as u64
     }
+
+    /// waits for anything the device is holding in a write cache
+    fn flush(&self) -> Result<(), &'static str> {
+        Ok(())
+    }
 }
 
 pub struct Partition<D: BlockDevice> {
@@ -72,25 +82,62 @@ impl<D: BlockDevice> BlockDevice for Partition<D> {
     fn sector_count(&self) -> Option<u32> {
         Some(self.sector_count)
     }
+
+    fn flush(&self) -> Result<(), &'static str> {
+        self.device.flush()
+    }
+}
+
+struct DevEntry {
+    device: Box<dyn BlockDevice>,
+    // registered and last written through /dev
+    ctime: u64,
+    mtime: u64,
+}
+
+impl DevEntry {
+    fn metadata(&self) -> Metadata {
+        Metadata {
+            file_type: FileType::Device,
+            size: device_size(self.device.as_ref()),
+            uid: 0,
+            gid: 0,
+            mode: 0o600,
+            atime: self.mtime,
+            mtime: self.mtime,
+            ctime: self.ctime,
+        }
+    }
 }
 
+type Devices = Arc<Mutex<BTreeMap<String, DevEntry>>>;
+
+// every devfs mount shows the same devices
+static DEVICES: Lazy<Devices> = Lazy::new(|| Arc::new(Mutex::new(BTreeMap::new())));
+
 pub struct DevFs {
-    devices: Mutex<BTreeMap<String, Box<dyn BlockDevice>>>,
+    devices: Devices,
 }
 
 impl DevFs {
     pub fn new() -> Self {
         Self {
-            devices: Mutex::new(BTreeMap::new()),
+            devices: DEVICES.clone(),
         }
     }
 
     pub fn register_device(&self, name: &str, device: Box<dyn BlockDevice>) {
-        self.devices.lock().insert(name.into(), device);
+        let now = time::realtime_now();
+        let entry = DevEntry {
+            device,
+            ctime: now,
+            mtime: now,
+        };
+        self.devices.lock().insert(name.into(), entry);
     }
 
     pub fn unregister_device(&self, name: &str) -> Option<Box<dyn BlockDevice>> {
-        self.devices.lock().remove(name)
+        self.devices.lock().remove(name).map(|entry| entry.device)
     }
 }
 
@@ -103,17 +150,16 @@ impl Default for DevFs {
 struct BlockDeviceHandle {
     device_name: String,
     position: u64,
-    fs_ptr: *const DevFs,
+    devices: Devices,
 }
 
-unsafe impl Send for BlockDeviceHandle {}
-unsafe impl Sync for BlockDeviceHandle {}
-
 impl FileHandle for BlockDeviceHandle {
     fn read(&mut self, buf: &mut [u8]) -> VfsResult<usize> {
-        let fs = unsafe { &*self.fs_ptr };
-        let devices = fs.devices.lock();
-        let device = devices.get(&self.device_name).ok_or(VfsError::NotFound)?;
+        let devices = self.devices.lock();
+        let device = &devices
+            .get(&self.device_name)
+            .ok_or(VfsError::NotFound)?
+            .device;
 
         let block_size = device.block_size() as u64;
         let block_num = (self.position / block_size) as u32;
@@ -133,9 +179,11 @@ impl FileHandle for BlockDeviceHandle {
     }
 
     fn write(&mut self, buf: &[u8]) -> VfsResult<usize> {
-        let fs = unsafe { &*self.fs_ptr };
-        let devices = fs.devices.lock();
-        let device = devices.get(&self.device_name).ok_or(VfsError::NotFound)?;
+        let mut devices = self.devices.lock();
+        let entry = devices
+            .get_mut(&self.device_name)
+            .ok_or(VfsError::NotFound)?;
+        let device = &entry.device;
 
         let block_size = device.block_size() as u64;
         let block_num = (self.position / block_size) as u32;
@@ -155,6 +203,7 @@ impl FileHandle for BlockDeviceHandle {
         device
             .write_sector(block_num, &sector)
             .map_err(|_| VfsError::IoError)?;
+        entry.mtime = time::realtime_now();
 
         self.position += to_write as u64;
         Ok(to_write)
@@ -165,9 +214,11 @@ impl FileHandle for BlockDeviceHandle {
             SeekFrom::Start(n) => n as i64,
             SeekFrom::Current(n) => self.position as i64 + n as i64,
             SeekFrom::End(n) => {
-                let fs = unsafe { &*self.fs_ptr };
-                let devices = fs.devices.lock();
-                let device = devices.get(&self.device_name).ok_or(VfsError::NotFound)?;
+                let devices = self.devices.lock();
+                let device = &devices
+                    .get(&self.device_name)
+                    .ok_or(VfsError::NotFound)?
+                    .device;
                 let size = device.num_blocks() * device.block_size() as u64;
                 size as i64 + n as i64
             }
@@ -182,58 +233,150 @@ impl FileHandle for BlockDeviceHandle {
     }
 
     fn metadata(&self) -> VfsResult<Metadata> {
-        let fs = unsafe { &*self.fs_ptr };
-        let devices = fs.devices.lock();
-        let device = devices.get(&self.device_name).ok_or(VfsError::NotFound)?;
-
-        Ok(Metadata {
-            file_type: FileType::Device,
-            size: (device.num_blocks() * device.block_size() as u64) as usize,
-        })
+        let devices = self.devices.lock();
+        let entry = devices.get(&self.device_name).ok_or(VfsError::NotFound)?;
+        Ok(entry.metadata())
     }
-}
 
-impl Filesystem for DevFs {
-    fn open(&self, path: &str, _flags: OpenFlags) -> VfsResult<Box<dyn FileHandle>> {
-        let name = path.trim_start_matches('/');
+    fn read_page_direct(&mut self, index: u64, buf: &mut [u8]) -> VfsResult<()> {
+        let devices = self.devices.lock();
+        let device = &devices
+            .get(&self.device_name)
+            .ok_or(VfsError::NotFound)?
+            .device;
 
-        if name.is_empty() {
-            return Err(VfsError::IsADirectory);
+        let first = index as u32 * SECTORS_PER_PAGE as u32;
+        let mut sector = [0u8; SECTOR_SIZE];
+        for (i, chunk) in buf.chunks_exact_mut(SECTOR_SIZE).enumerate() {
+            device
+                .read_sector(first + i as u32, &mut sector)
+                .map_err(|_| VfsError::IoError)?;
+            chunk.copy_from_slice(&sector);
         }
+        Ok(())
+    }
 
+    fn sync(&mut self) -> VfsResult<()> {
         let devices = self.devices.lock();
-        if !devices.contains_key(name) {
-            return Err(VfsError::NotFound);
+        let device = &devices
+            .get(&self.device_name)
+            .ok_or(VfsError::NotFound)?
+            .device;
+        device.flush().map_err(|_| VfsError::IoError)
+    }
+
+    fn write_page_direct(&mut self, index: u64, buf: &[u8]) -> VfsResult<()> {
+        let devices = self.devices.lock();
+        let device = &devices
+            .get(&self.device_name)
+            .ok_or(VfsError::NotFound)?
+            .device;
+
+        let first = index as u32 * SECTORS_PER_PAGE as u32;
+        let mut sector = [0u8; SECTOR_SIZE];
+        for (i, chunk) in buf.chunks_exact(SECTOR_SIZE).enumerate() {
+            sector.copy_from_slice(chunk);
+            device
+                .write_sector(first + i as u32, &sector)
+                .map_err(|_| VfsError::IoError)?;
         }
+        Ok(())
+    }
+}
 
-        Ok(Box::new(BlockDeviceHandle {
-            device_name: name.into(),
-            position: 0,
-            fs_ptr: self as *const DevFs,
-        }))
+enum DevNode {
+    Root(Devices),
+    Device(Devices, String),
+}
+
+fn device_size(device: &dyn BlockDevice) -> usize {
+    (device.num_blocks() * device.block_size() as u64) as usize
+}
+
+impl Filesystem for DevFs {
+    fn root(&self) -> InodeRef {
+        Arc::new(DevNode::Root(self.devices.clone()))
     }
 
-    fn mkdir(&self, _path: &str) -> VfsResult<()> {
-        Err(VfsError::PermissionDenied)
+    fn name(&self) -> &'static str {
+        "devfs"
     }
 
-    fn remove(&self, _path: &str) -> VfsResult<()> {
-        Err(VfsError::PermissionDenied)
+    fn cache_dentries(&self) -> bool {
+        false
     }
 
-    fn rmdir(&self, _path: &str) -> VfsResult<()> {
-        Err(VfsError::PermissionDenied)
+    fn sync(&self) -> VfsResult<()> {
+        let mut result = Ok(());
+        for entry in self.devices.lock().values() {
+            if entry.device.flush().is_err() {
+                result = Err(VfsError::IoError);
+            }
+        }
+        result
     }
+}
 
-    fn readdir(&self, path: &str) -> VfsResult<Vec<DirEntry>> {
-        let path = path.trim_start_matches('/');
+impl Inode for DevNode {
+    fn id(&self) -> u64 {
+        match self {
+            DevNode::Root(_) => 0,
+            DevNode::Device(devices, name) => {
+                1 + devices.lock().keys().position(|n| n == name).unwrap_or(0) as u64
+            }
+        }
+    }
+
+    fn metadata(&self) -> VfsResult<Metadata> {
+        match self {
+            DevNode::Root(_) => Ok(Metadata {
+                file_type: FileType::Directory,
+                size: 0,
+                uid: 0,
+                gid: 0,
+                mode: 0o755,
+                atime: 0,
+                mtime: 0,
+                ctime: 0,
+            }),
+            DevNode::Device(devices, name) => {
+                let devices = devices.lock();
+                let entry = devices.get(name).ok_or(VfsError::NotFound)?;
+                Ok(entry.metadata())
+            }
+        }
+    }
+
+    fn open(self: Arc<Self>, _flags: OpenFlags) -> VfsResult<Box<dyn FileHandle>> {
+        let DevNode::Device(devices, name) = &*self else {
+            return Err(VfsError::IsADirectory);
+        };
+
+        Ok(Box::new(BlockDeviceHandle {
+            device_name: name.clone(),
+            position: 0,
+            devices: devices.clone(),
+        }))
+    }
 
-        if !path.is_empty() {
+    fn lookup(&self, name: &str) -> VfsResult<InodeRef> {
+        let DevNode::Root(devices) = self else {
             return Err(VfsError::NotADirectory);
+        };
+
+        if !devices.lock().contains_key(name) {
+            return Err(VfsError::NotFound);
         }
+        Ok(Arc::new(DevNode::Device(devices.clone(), name.into())))
+    }
+
+    fn readdir(&self) -> VfsResult<Vec<DirEntry>> {
+        let DevNode::Root(devices) = self else {
+            return Err(VfsError::NotADirectory);
+        };
 
-        let devices = self.devices.lock();
         Ok(devices
+            .lock()
             .keys()
             .map(|name| DirEntry {
                 name: name.clone(),
@@ -241,23 +384,4 @@ impl Filesystem for DevFs {
             })
             .collect())
     }
-
-    fn metadata(&self, path: &str) -> VfsResult<Metadata> {
-        let name = path.trim_start_matches('/');
-
-        if name.is_empty() {
-            return Ok(Metadata {
-                file_type: FileType::Directory,
-                size: 0,
-            });
-        }
-
-        let devices = self.devices.lock();
-        let device = devices.get(name).ok_or(VfsError::NotFound)?;
-
-        Ok(Metadata {
-            file_type: FileType::Device,
-            size: (device.num_blocks() * device.block_size() as u64) as usize,
-        })
-    }
 }
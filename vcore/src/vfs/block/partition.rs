@@ -1,5 +1,5 @@
 use super::{BlockDevice, SECTOR_SIZE};
-use alloc::vec::Vec;
+use alloc::{format, string::String, vec::Vec};
 
 #[derive(Debug, Clone)]
 pub struct PartitionInfo {
@@ -7,6 +7,8 @@ pub struct PartitionInfo {
     pub start_lba: u32,
     pub sector_count: u32,
     pub partition_type: PartitionType,
+    pub guid: Option<[u8; 16]>,
+    pub name: Option<String>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -70,6 +72,8 @@ fn parse_mbr(mbr: &[u8; SECTOR_SIZE]) -> Result<Vec<PartitionInfo>, &'static str
             start_lba,
             sector_count,
             partition_type,
+            guid: None,
+            name: None,
         });
     }
 
@@ -121,11 +125,25 @@ fn parse_gpt<D: BlockDevice>(device: &D) -> Result<Vec<PartitionInfo>, &'static
         let sector_count = end_lba - start_lba + 1;
         let partition_type = identify_gpt_type(type_guid);
 
+        let mut guid = [0u8; 16];
+        guid.copy_from_slice(&entry[16..32]);
+
+        let name_units: Vec<u16> = entry[56..128]
+            .chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+            .take_while(|&c| c != 0)
+            .collect();
+        let name = String::from_utf16(&name_units)
+            .ok()
+            .filter(|n| !n.is_empty());
+
         partitions.push(PartitionInfo {
             index: i as u8,
             start_lba,
             sector_count,
             partition_type,
+            guid: Some(guid),
+            name,
         });
     }
 
@@ -171,3 +189,39 @@ pub fn first_partition<D: BlockDevice>(device: &D) -> Result<Option<PartitionInf
     let partitions = parse_partitions(device)?;
     Ok(partitions.into_iter().next())
 }
+
+pub fn format_guid(guid: &[u8; 16]) -> String {
+    format!(
+        "{:08x}-{:04x}-{:04x}-{:02x}{:02x}-{:02x}{:02x}{:02x}{:02x}{:02x}{:02x}",
+        u32::from_le_bytes([guid[0], guid[1], guid[2], guid[3]]),
+        u16::from_le_bytes([guid[4], guid[5]]),
+        u16::from_le_bytes([guid[6], guid[7]]),
+        guid[8],
+        guid[9],
+        guid[10],
+        guid[11],
+        guid[12],
+        guid[13],
+        guid[14],
+        guid[15]
+    )
+}
+
+pub fn parse_guid(text: &str) -> Option<[u8; 16]> {
+    let hex: Vec<u8> = text.bytes().filter(|&b| b != b'-').collect();
+    if hex.len() != 32 {
+        return None;
+    }
+
+    let mut bytes = [0u8; 16];
+    for (i, pair) in hex.chunks_exact(2).enumerate() {
+        let pair = core::str::from_utf8(pair).ok()?;
+        bytes[i] = u8::from_str_radix(pair, 16).ok()?;
+    }
+
+    // the first three fields are stored little endian on disk
+    bytes[0..4].reverse();
+    bytes[4..6].reverse();
+    bytes[6..8].reverse();
+    Some(bytes)
+}
@@ -0,0 +1,420 @@
+use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
+use core::{
+    ops::Bound,
+    sync::atomic::{AtomicU64, Ordering},
+};
+use spin::Mutex;
+use x86_64::{PhysAddr, instructions::interrupts};
+
+use super::types::*;
+use crate::{
+    mem::{PAGE_SIZE, pmm, vmm},
+    sched,
+};
+
+const LOW_WATERMARK: usize = 256;
+const MAX_CACHED_PAGES: usize = 16384;
+const WRITEBACK_INTERVAL: u64 = 500;
+// the caller's buffer may be user memory, and faulting that in may need to
+// reclaim from the cache, so it is only touched with CACHE let go. this much
+// goes through the stack at a time
+const BOUNCE_SIZE: usize = 512;
+
+/// `file` is an id the filesystem never reuses while the file has pages
+/// here. a page is written back straight from its frame, so `write_page`
+/// must hold the file still, and skip pages past its end
+pub trait PageSource: Send + Sync {
+    fn read_page(&self, file: u64, index: u64, buf: &mut [u8]) -> VfsResult<()>;
+    fn write_page(&self, file: u64, index: u64, buf: &[u8]) -> VfsResult<()>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct PageKey {
+    fs: u64,
+    file: u64,
+    index: u64,
+}
+
+struct CachedPage {
+    frame: u64,
+    dirty: bool,
+    generation: u64,
+    last_used: u64,
+}
+
+impl CachedPage {
+    fn data(&self) -> &[u8] {
+        let ptr = vmm::phys_to_virt(PhysAddr::new(self.frame)).as_ptr::<u8>();
+        unsafe { core::slice::from_raw_parts(ptr, PAGE_SIZE) }
+    }
+
+    fn data_mut(&mut self) -> &mut [u8] {
+        let ptr = vmm::phys_to_virt(PhysAddr::new(self.frame)).as_mut_ptr::<u8>();
+        unsafe { core::slice::from_raw_parts_mut(ptr, PAGE_SIZE) }
+    }
+}
+
+struct PageCache {
+    pages: BTreeMap<PageKey, CachedPage>,
+    sources: BTreeMap<u64, Arc<dyn PageSource>>,
+    clock: u64,
+    generation: u64,
+    // the page being written back and its frame, which the writer frees if
+    // the page is dropped in the meantime
+    writing: Option<(PageKey, u64)>,
+}
+
+impl PageCache {
+    const fn new() -> Self {
+        Self {
+            pages: BTreeMap::new(),
+            sources: BTreeMap::new(),
+            clock: 0,
+            generation: 0,
+            writing: None,
+        }
+    }
+
+    fn touch(&mut self, key: PageKey) -> Option<&mut CachedPage> {
+        self.clock += 1;
+        let clock = self.clock;
+        let page = self.pages.get_mut(&key)?;
+        page.last_used = clock;
+        Some(page)
+    }
+
+    fn is_evictable(&self, key: &PageKey, page: &CachedPage) -> bool {
+        !page.dirty && self.sources.contains_key(&key.fs)
+    }
+
+    fn remove(&mut self, key: &PageKey) -> bool {
+        let Some(page) = self.pages.remove(key) else {
+            return false;
+        };
+        if self.writing != Some((*key, page.frame)) {
+            pmm::free(page.frame);
+        }
+        true
+    }
+
+    fn evict_clean(&mut self, count: usize) -> usize {
+        let mut victims: Vec<(u64, PageKey)> = self
+            .pages
+            .iter()
+            .filter(|(key, page)| self.is_evictable(key, page))
+            .map(|(key, page)| (page.last_used, *key))
+            .collect();
+        victims.sort_unstable();
+
+        let mut freed = 0;
+        for (_, key) in victims.into_iter().take(count) {
+            if self.remove(&key) {
+                freed += 1;
+            }
+        }
+        freed
+    }
+
+    fn file_range(fs: u64, file: u64) -> core::ops::RangeInclusive<PageKey> {
+        PageKey { fs, file, index: 0 }..=PageKey {
+            fs,
+            file,
+            index: u64::MAX,
+        }
+    }
+
+    // the first dirty page after `after`, which becomes the one being written
+    fn start_writeback(
+        &mut self,
+        after: Option<PageKey>,
+        fs: Option<u64>,
+        file: Option<u64>,
+    ) -> Option<(PageKey, u64, u64, Arc<dyn PageSource>)> {
+        let start = after.map_or(Bound::Unbounded, Bound::Excluded);
+        let (key, page, source) = self
+            .pages
+            .range((start, Bound::Unbounded))
+            .filter(|(key, page)| {
+                page.dirty
+                    && fs.is_none_or(|fs| key.fs == fs)
+                    && file.is_none_or(|file| key.file == file)
+            })
+            .find_map(|(key, page)| Some((*key, page, self.sources.get(&key.fs)?)))?;
+
+        let found = (key, page.generation, page.frame, source.clone());
+        self.writing = Some((key, page.frame));
+        Some(found)
+    }
+}
+
+static CACHE: Mutex<PageCache> = Mutex::new(PageCache::new());
+// one writer at a time, so only one page is ever pinned
+static WRITEBACK: Mutex<()> = Mutex::new(());
+static NEXT_FS_ID: AtomicU64 = AtomicU64::new(1);
+
+pub fn alloc_fs_id() -> u64 {
+    NEXT_FS_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+pub fn register(fs: u64, source: Arc<dyn PageSource>) {
+    CACHE.lock().sources.insert(fs, source);
+}
+
+/// drops a filesystem's pages once they are written back. if that fails
+/// the pages and their source stay, so writeback can try again
+pub fn unregister(fs: u64) -> VfsResult<()> {
+    sync_fs(fs)?;
+
+    let mut cache = CACHE.lock();
+    cache.sources.remove(&fs);
+
+    let keys: Vec<PageKey> = cache
+        .pages
+        .keys()
+        .filter(|key| key.fs == fs)
+        .copied()
+        .collect();
+    for key in keys {
+        cache.remove(&key);
+    }
+    Ok(())
+}
+
+fn alloc_frame() -> VfsResult<u64> {
+    let pressure =
+        pmm::free_pages() < LOW_WATERMARK || CACHE.lock().pages.len() >= MAX_CACHED_PAGES;
+    if pressure {
+        shrink(LOW_WATERMARK / 4);
+    }
+
+    if let Some(frame) = pmm::alloc() {
+        return Ok(frame);
+    }
+
+    shrink(LOW_WATERMARK);
+    pmm::alloc().ok_or(VfsError::NoSpace)
+}
+
+fn load_page(key: PageKey) -> VfsResult<()> {
+    if CACHE.lock().touch(key).is_some() {
+        return Ok(());
+    }
+
+    let frame = alloc_frame()?;
+    let buf = unsafe {
+        let ptr = vmm::phys_to_virt(PhysAddr::new(frame)).as_mut_ptr::<u8>();
+        core::slice::from_raw_parts_mut(ptr, PAGE_SIZE)
+    };
+    buf.fill(0);
+
+    let source = CACHE.lock().sources.get(&key.fs).cloned();
+    if let Some(source) = source
+        && let Err(e) = source.read_page(key.file, key.index, buf)
+    {
+        pmm::free(frame);
+        return Err(e);
+    }
+
+    let mut cache = CACHE.lock();
+    if cache.pages.contains_key(&key) {
+        pmm::free(frame);
+    } else {
+        cache.clock += 1;
+        let last_used = cache.clock;
+        cache.pages.insert(
+            key,
+            CachedPage {
+                frame,
+                dirty: false,
+                generation: 0,
+                last_used,
+            },
+        );
+    }
+
+    Ok(())
+}
+
+pub fn read(fs: u64, file: u64, offset: usize, buf: &mut [u8]) -> VfsResult<usize> {
+    let mut bounce = [0u8; BOUNCE_SIZE];
+    let mut done = 0;
+
+    while done < buf.len() {
+        let pos = offset + done;
+        let key = PageKey {
+            fs,
+            file,
+            index: (pos / PAGE_SIZE) as u64,
+        };
+        let page_offset = pos % PAGE_SIZE;
+        let chunk = (PAGE_SIZE - page_offset)
+            .min(buf.len() - done)
+            .min(BOUNCE_SIZE);
+
+        load_page(key)?;
+
+        match CACHE.lock().touch(key) {
+            Some(page) => {
+                bounce[..chunk].copy_from_slice(&page.data()[page_offset..page_offset + chunk])
+            }
+            None => continue,
+        }
+        buf[done..done + chunk].copy_from_slice(&bounce[..chunk]);
+        done += chunk;
+    }
+
+    Ok(done)
+}
+
+pub fn write(fs: u64, file: u64, offset: usize, buf: &[u8]) -> VfsResult<usize> {
+    let mut bounce = [0u8; BOUNCE_SIZE];
+    let mut done = 0;
+
+    while done < buf.len() {
+        let pos = offset + done;
+        let key = PageKey {
+            fs,
+            file,
+            index: (pos / PAGE_SIZE) as u64,
+        };
+        let page_offset = pos % PAGE_SIZE;
+        let chunk = (PAGE_SIZE - page_offset)
+            .min(buf.len() - done)
+            .min(BOUNCE_SIZE);
+
+        bounce[..chunk].copy_from_slice(&buf[done..done + chunk]);
+        load_page(key)?;
+
+        let mut cache = CACHE.lock();
+        cache.generation += 1;
+        let generation = cache.generation;
+        match cache.touch(key) {
+            Some(page) => {
+                page.data_mut()[page_offset..page_offset + chunk].copy_from_slice(&bounce[..chunk]);
+                page.dirty = true;
+                page.generation = generation;
+                done += chunk;
+            }
+            None => continue,
+        }
+    }
+
+    Ok(done)
+}
+
+pub fn truncate(fs: u64, file: u64, size: usize) {
+    let mut cache = CACHE.lock();
+    let first_dropped = size.div_ceil(PAGE_SIZE) as u64;
+
+    let keys: Vec<PageKey> = cache
+        .pages
+        .range(PageCache::file_range(fs, file))
+        .filter(|(key, _)| key.index >= first_dropped)
+        .map(|(key, _)| *key)
+        .collect();
+    for key in keys {
+        cache.remove(&key);
+    }
+
+    if !size.is_multiple_of(PAGE_SIZE) {
+        let key = PageKey {
+            fs,
+            file,
+            index: (size / PAGE_SIZE) as u64,
+        };
+        if let Some(page) = cache.pages.get_mut(&key) {
+            page.data_mut()[size % PAGE_SIZE..].fill(0);
+        }
+    }
+}
+
+pub fn invalidate(fs: u64, file: u64) {
+    truncate(fs, file, 0);
+}
+
+// writes dirty pages one at a time from their own frames, so it needs no
+// memory and can run when there is none. the caller holds WRITEBACK
+fn write_dirty(fs: Option<u64>, file: Option<u64>) -> VfsResult<()> {
+    let mut result = Ok(());
+    let mut cursor = None;
+
+    loop {
+        let next = CACHE.lock().start_writeback(cursor, fs, file);
+        let Some((key, generation, frame, source)) = next else {
+            break;
+        };
+        cursor = Some(key);
+
+        let data = unsafe {
+            let ptr = vmm::phys_to_virt(PhysAddr::new(frame)).as_ptr::<u8>();
+            core::slice::from_raw_parts(ptr, PAGE_SIZE)
+        };
+        let written = source.write_page(key.file, key.index, data);
+
+        let mut cache = CACHE.lock();
+        cache.writing = None;
+        match cache.pages.get_mut(&key) {
+            Some(page) if page.frame == frame => {
+                if written.is_ok() && page.generation == generation {
+                    page.dirty = false;
+                }
+            }
+            _ => pmm::free(frame),
+        }
+        drop(cache);
+
+        if let Err(e) = written {
+            result = Err(e);
+        }
+    }
+
+    result
+}
+
+pub fn sync_file(fs: u64, file: u64) -> VfsResult<()> {
+    let _writer = WRITEBACK.lock();
+    write_dirty(Some(fs), Some(file))
+}
+
+pub fn sync_fs(fs: u64) -> VfsResult<()> {
+    let _writer = WRITEBACK.lock();
+    write_dirty(Some(fs), None)
+}
+
+pub fn sync_all() -> VfsResult<()> {
+    let _writer = WRITEBACK.lock();
+    write_dirty(None, None)
+}
+
+pub fn shrink(target: usize) -> usize {
+    let freed = CACHE.lock().evict_clean(target);
+    if freed >= target {
+        return freed;
+    }
+
+    // writing back waits on filesystem locks, which is only safe where this
+    // could be preempted, and the allocation may come from under a writer
+    if interrupts::are_enabled()
+        && let Some(_writer) = WRITEBACK.try_lock()
+    {
+        let _ = write_dirty(None, None);
+    }
+    freed + CACHE.lock().evict_clean(target - freed)
+}
+
+pub fn cached_pages() -> usize {
+    CACHE.lock().pages.len()
+}
+
+pub fn dirty_pages() -> usize {
+    CACHE.lock().pages.values().filter(|p| p.dirty).count()
+}
+
+pub fn writeback_task() {
+    loop {
+        sched::sleep(WRITEBACK_INTERVAL);
+        if let Err(e) = sync_all() {
+            crate::warn!("page cache writeback failed: {:?}", e);
+        }
+    }
+}
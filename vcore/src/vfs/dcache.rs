@@ -0,0 +1,83 @@
+use alloc::{collections::BTreeMap, string::String, sync::Arc, vec::Vec};
+use core::sync::atomic::{AtomicU64, Ordering};
+use spin::Mutex;
+
+use super::types::*;
+
+const MAX_DENTRIES: usize = 1024;
+
+// (mount, parent inode, name)
+type Key = (u64, u64, String);
+
+static DENTRIES: Mutex<BTreeMap<Key, InodeRef>> = Mutex::new(BTreeMap::new());
+
+// bumped under the lock on every invalidation, so a lookup that raced with
+// an unlink does not put the dead entry back
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+fn insert(key: Key, inode: InodeRef, generation: u64) {
+    let mut dentries = DENTRIES.lock();
+    if GENERATION.load(Ordering::Acquire) != generation {
+        return;
+    }
+
+    if dentries.len() >= MAX_DENTRIES {
+        // drop whatever only the cache is keeping alive, or failing that
+        // anything at all
+        dentries.retain(|_, inode| Arc::strong_count(inode) > 1);
+        if dentries.len() >= MAX_DENTRIES {
+            dentries.pop_first();
+        }
+    }
+
+    dentries.insert(key, inode);
+}
+
+/// looks `name` up in `parent`, going to the filesystem on a miss
+pub fn lookup(mount: u64, parent: &InodeRef, name: &str) -> VfsResult<InodeRef> {
+    let key = (mount, parent.id(), String::from(name));
+
+    if let Some(inode) = DENTRIES.lock().get(&key) {
+        return Ok(inode.clone());
+    }
+
+    let generation = GENERATION.load(Ordering::Acquire);
+    let inode = parent.lookup(name)?;
+    insert(key, inode.clone(), generation);
+
+    Ok(inode)
+}
+
+pub fn add(mount: u64, parent: &InodeRef, name: &str, inode: InodeRef) {
+    let generation = GENERATION.load(Ordering::Acquire);
+    insert((mount, parent.id(), String::from(name)), inode, generation);
+}
+
+/// forgets every name under `parent` that leads to `inode`, which covers
+/// case-insensitive filesystems caching one file under several spellings
+pub fn remove(mount: u64, parent: &InodeRef, inode: &InodeRef) {
+    let (parent, id) = (parent.id(), inode.id());
+    let mut dentries = DENTRIES.lock();
+    GENERATION.fetch_add(1, Ordering::AcqRel);
+
+    let stale: Vec<Key> = dentries
+        .range((mount, parent, String::new())..)
+        .take_while(|((m, p, _), _)| *m == mount && *p == parent)
+        .filter(|(_, inode)| inode.id() == id)
+        .map(|(key, _)| key.clone())
+        .collect();
+
+    for key in stale {
+        dentries.remove(&key);
+    }
+}
+
+pub fn remove_mount(mount: u64) {
+    let mut dentries = DENTRIES.lock();
+    GENERATION.fetch_add(1, Ordering::AcqRel);
+    dentries.retain(|(m, _, _), _| *m != mount);
+}
+
+pub fn len() -> usize {
+    DENTRIES.lock().len()
+}
@@ -1,14 +1,22 @@
 use alloc::{
     boxed::Box,
+    collections::BTreeMap,
     format,
     string::{String, ToString},
+    sync::{Arc, Weak},
     vec,
     vec::Vec,
 };
-use spin::Mutex;
+use core::{
+    any::Any,
+    sync::atomic::{AtomicU32, AtomicU64, Ordering},
+};
+use spin::{Mutex, MutexGuard};
 
 use super::block::{BlockDevice, SECTOR_SIZE};
+use super::cache::{self, PageSource};
 use super::types::*;
+use crate::{cpu::time, drivers::rtc::DateTime, mem::PAGE_SIZE};
 
 const FAT32_EOC: u32 = 0x0FFFFFF8;
 const FAT32_FREE: u32 = 0x00000000;
@@ -25,6 +33,12 @@ const ATTR_LFN: u8 = 0x0F;
 const LFN_LAST_ENTRY: u8 = 0x40;
 const LFN_SEQ_MASK: u8 = 0x1F;
 
+const FSINFO_LEAD_SIG: u32 = 0x41615252;
+const FSINFO_STRUCT_SIG: u32 = 0x61417272;
+const FSINFO_FREE_COUNT: usize = 488;
+// not counted yet
+const FREE_UNKNOWN: u32 = u32::MAX;
+
 const DIR_ENTRY_SIZE: usize = 32;
 const DELETED_MARKER: u8 = 0xE5;
 
@@ -96,6 +110,52 @@ impl Bpb {
     }
 }
 
+pub fn volume_label<D: BlockDevice>(device: &D) -> Option<String> {
+    let mut sector = [0u8; SECTOR_SIZE];
+    device.read_sector(0, &mut sector).ok()?;
+    Bpb::parse(&sector)?;
+
+    let label = core::str::from_utf8(&sector[71..82]).ok()?.trim_end();
+    if label.is_empty() || label == "NO NAME" {
+        None
+    } else {
+        Some(String::from(label))
+    }
+}
+
+const NS_PER_SEC: u64 = 1_000_000_000;
+
+// fat keeps time to two seconds with no zone, we keep utc in it. anything
+// before 1980 cannot be written down and is left as 0, which reads as unknown
+fn to_fat_time(ns: u64) -> (u16, u16, u8) {
+    let secs = ns / NS_PER_SEC;
+    let t = DateTime::from_unix(secs);
+    if !(1980..=2107).contains(&t.year) {
+        return (0, 0, 0);
+    }
+    let date = ((t.year - 1980) << 9) | (t.month as u16) << 5 | t.day as u16;
+    let time = (t.hour as u16) << 11 | (t.minute as u16) << 5 | (t.second as u16 / 2);
+    // the creation time also has the hundredths past the two second step
+    let hundredths = (secs % 2 * 100 + ns % NS_PER_SEC / 10_000_000) as u8;
+    (date, time, hundredths)
+}
+
+fn from_fat_time(date: u16, time: u16) -> u64 {
+    let (month, day) = (((date >> 5) & 0xF) as u8, (date & 0x1F) as u8);
+    if !(1..=12).contains(&month) || day == 0 {
+        return 0;
+    }
+    let t = DateTime {
+        year: 1980 + (date >> 9),
+        month,
+        day,
+        hour: (time >> 11) as u8,
+        minute: ((time >> 5) & 0x3F) as u8,
+        second: ((time & 0x1F) * 2) as u8,
+    };
+    t.to_unix() * NS_PER_SEC
+}
+
 #[derive(Debug, Clone)]
 struct ShortDirEntry {
     name: [u8; 11],
@@ -205,21 +265,42 @@ impl ShortDirEntry {
 
     fn new(name: &str, attr: u8, cluster: u32, size: u32) -> Self {
         let short_name = make_short_name(name);
+        let (date, time, hundredths) = to_fat_time(time::realtime_now());
         Self {
             name: short_name,
             attr,
             nt_res: 0,
-            create_time_tenth: 0,
-            create_time: 0,
-            create_date: 0,
-            access_date: 0,
+            create_time_tenth: hundredths,
+            create_time: time,
+            create_date: date,
+            access_date: date,
             cluster_high: (cluster >> 16) as u16,
-            modify_time: 0,
-            modify_date: 0,
+            modify_time: time,
+            modify_date: date,
             cluster_low: cluster as u16,
             size,
         }
     }
+
+    fn accessed(&self) -> u64 {
+        from_fat_time(self.access_date, 0)
+    }
+
+    fn modified(&self) -> u64 {
+        from_fat_time(self.modify_date, self.modify_time)
+    }
+
+    fn set_accessed(&mut self, ns: u64) {
+        self.access_date = to_fat_time(ns).0;
+    }
+
+    // false if it is still the same two seconds
+    fn set_modified(&mut self, ns: u64) -> bool {
+        let (date, time, _) = to_fat_time(ns);
+        let changed = (date, time) != (self.modify_date, self.modify_time);
+        (self.modify_date, self.modify_time) = (date, time);
+        changed
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -511,9 +592,19 @@ impl FatDirEntry {
     }
 }
 
+fn le_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        buf[offset],
+        buf[offset + 1],
+        buf[offset + 2],
+        buf[offset + 3],
+    ])
+}
+
 struct Fat32Inner<D: BlockDevice> {
     device: D,
     bpb: Bpb,
+    free_clusters: AtomicU32,
 }
 
 impl<D: BlockDevice> Fat32Inner<D> {
@@ -567,8 +658,97 @@ impl<D: BlockDevice> Fat32Inner<D> {
             sector[offset..offset + 4].copy_from_slice(&bytes);
 
             self.write_sector(fat_sector, &sector)?;
+            if fat_num == 0 {
+                self.track_free(existing & 0x0FFFFFFF, value & 0x0FFFFFFF);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn track_free(&self, old: u32, new: u32) {
+        let delta = match (old == FAT32_FREE, new == FAT32_FREE) {
+            (true, false) => -1,
+            (false, true) => 1,
+            _ => return,
+        };
+        let _ = self
+            .free_clusters
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| {
+                (n != FREE_UNKNOWN).then(|| n.wrapping_add_signed(delta))
+            });
+    }
+
+    fn count_free_clusters(&self) -> Result<u32, &'static str> {
+        let end = self.bpb.total_clusters() + 2;
+        let per_sector = (SECTOR_SIZE / 4) as u32;
+        let mut sector = [0u8; SECTOR_SIZE];
+        let mut free = 0;
+
+        for index in 0..end.div_ceil(per_sector) {
+            self.read_sector(self.bpb.fat_start_sector() + index, &mut sector)?;
+            for (i, entry) in sector.chunks_exact(4).enumerate() {
+                let cluster = index * per_sector + i as u32;
+                if (2..end).contains(&cluster) && le_u32(entry, 0) & 0x0FFFFFFF == FAT32_FREE {
+                    free += 1;
+                }
+            }
+        }
+
+        Ok(free)
+    }
+
+    // the FSInfo sector, if the volume has a valid one
+    fn read_fs_info(&self) -> Result<Option<[u8; SECTOR_SIZE]>, &'static str> {
+        let lba = self.bpb.fs_info_sector;
+        if lba == 0 || lba == 0xFFFF {
+            return Ok(None);
+        }
+
+        let mut sector = [0u8; SECTOR_SIZE];
+        self.read_sector(lba as u32, &mut sector)?;
+        let valid =
+            le_u32(&sector, 0) == FSINFO_LEAD_SIG && le_u32(&sector, 484) == FSINFO_STRUCT_SIG;
+        Ok(valid.then_some(sector))
+    }
+
+    // FSInfo's count is only a hint that another system may have left
+    // stale, so the FAT is counted once and tracked from then on
+    fn free_clusters(&self) -> Result<u32, &'static str> {
+        let known = self.free_clusters.load(Ordering::Relaxed);
+        if known != FREE_UNKNOWN {
+            return Ok(known);
         }
 
+        let counted = self.count_free_clusters()?;
+        if let Some(sector) = self.read_fs_info()? {
+            let hint = le_u32(&sector, FSINFO_FREE_COUNT);
+            if hint != counted {
+                crate::warn!(
+                    "fat32: FSInfo claims {} free clusters, found {}",
+                    hint,
+                    counted
+                );
+            }
+        }
+        self.free_clusters.store(counted, Ordering::Relaxed);
+        Ok(counted)
+    }
+
+    // leaves the counted free total in FSInfo for the next mount
+    fn store_fs_info(&self) -> Result<(), &'static str> {
+        let free = self.free_clusters.load(Ordering::Relaxed);
+        if free == FREE_UNKNOWN {
+            return Ok(());
+        }
+        let Some(mut sector) = self.read_fs_info()? else {
+            return Ok(());
+        };
+
+        if le_u32(&sector, FSINFO_FREE_COUNT) != free {
+            sector[FSINFO_FREE_COUNT..FSINFO_FREE_COUNT + 4].copy_from_slice(&free.to_le_bytes());
+            self.write_sector(self.bpb.fs_info_sector as u32, &sector)?;
+        }
         Ok(())
     }
 
@@ -890,8 +1070,23 @@ impl<D: BlockDevice> Fat32Inner<D> {
         attr: u8,
         cluster: u32,
         size: u32,
-    ) -> Result<(), &'static str> {
+    ) -> Result<FatDirEntry, &'static str> {
+        self.insert_dir_entry(
+            dir_cluster,
+            name,
+            ShortDirEntry::new(name, attr, cluster, size),
+        )
+    }
+
+    // writes `short_entry` under `name`, keeping its attributes and times
+    fn insert_dir_entry(
+        &self,
+        dir_cluster: u32,
+        name: &str,
+        mut short_entry: ShortDirEntry,
+    ) -> Result<FatDirEntry, &'static str> {
         let short_name = make_short_name(name);
+        short_entry.name = short_name;
         let need_lfn = needs_lfn(name);
 
         let lfn_entries = if need_lfn {
@@ -908,13 +1103,18 @@ impl<D: BlockDevice> Fat32Inner<D> {
             data[entry_offset..entry_offset + DIR_ENTRY_SIZE].copy_from_slice(&lfn.serialize());
         }
 
-        let short_entry = ShortDirEntry::new(name, attr, cluster, size);
         let short_offset = offset + lfn_entries.len() * DIR_ENTRY_SIZE;
         data[short_offset..short_offset + DIR_ENTRY_SIZE].copy_from_slice(&short_entry.serialize());
 
         self.write_chain(dir_cluster, &data)?;
 
-        Ok(())
+        Ok(FatDirEntry {
+            name: name.to_string(),
+            short_entry,
+            entry_count: lfn_entries.len() + 1,
+            lfn_entries,
+            entry_offset: offset,
+        })
     }
 
     fn update_dir_entry(&self, dir_cluster: u32, entry: &FatDirEntry) -> Result<(), &'static str> {
@@ -948,278 +1148,141 @@ impl<D: BlockDevice> Fat32Inner<D> {
         Ok(())
     }
 
-    fn resolve_path(&self, path: &str) -> Result<(u32, Option<FatDirEntry>), &'static str> {
-        let parts: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
-
-        if parts.is_empty() {
-            return Ok((self.bpb.root_cluster, None));
-        }
-
-        let mut current_cluster = self.bpb.root_cluster;
-
-        for (i, part) in parts.iter().enumerate() {
-            let entry = self
-                .find_in_directory(current_cluster, part)?
-                .ok_or("path not found")?;
-
-            if i == parts.len() - 1 {
-                return Ok((current_cluster, Some(entry)));
-            }
-
-            if !entry.is_directory() {
-                return Err("not a directory");
-            }
+    // points a moved directory's `..` entry at its new parent
+    fn set_parent_link(&self, dir_cluster: u32, parent_cluster: u32) -> Result<(), &'static str> {
+        let mut data = vec![0u8; self.bpb.bytes_per_cluster()];
+        self.read_cluster(dir_cluster, &mut data)?;
 
-            current_cluster = entry.cluster();
-        }
+        let slot = DIR_ENTRY_SIZE..2 * DIR_ENTRY_SIZE;
+        let mut dotdot = ShortDirEntry::parse(&data[slot.clone()])
+            .filter(|e| &e.name == b"..         ")
+            .ok_or("directory has no .. entry")?;
+        dotdot.set_cluster(parent_cluster);
+        data[slot].copy_from_slice(&dotdot.serialize());
 
-        Ok((current_cluster, None))
+        self.write_cluster(dir_cluster, &data)
     }
 
-    fn split_path(&self, path: &str) -> Result<(u32, String), &'static str> {
-        let path = path.trim_matches('/');
+    fn cluster_at(&self, start: u32, index: usize) -> Result<Option<u32>, &'static str> {
+        let mut cluster = start;
 
-        if path.is_empty() {
-            return Err("invalid path");
+        for _ in 0..index {
+            if !(2..FAT32_EOC).contains(&cluster) {
+                return Ok(None);
+            }
+            cluster = self.get_fat_entry(cluster)?;
         }
 
-        if let Some(pos) = path.rfind('/') {
-            let parent_path = &path[..pos];
-            let name = &path[pos + 1..];
-
-            let (_, parent_entry) = self.resolve_path(parent_path)?;
-            let parent_cluster = match parent_entry {
-                Some(e) if e.is_directory() => e.cluster(),
-                Some(_) => return Err("parent is not a directory"),
-                None => self.bpb.root_cluster,
-            };
-
-            Ok((parent_cluster, name.to_string()))
+        if !(2..FAT32_EOC).contains(&cluster) {
+            Ok(None)
         } else {
-            Ok((self.bpb.root_cluster, path.to_string()))
+            Ok(Some(cluster))
         }
     }
-}
-
-struct FatFileHandle<D: BlockDevice + 'static> {
-    fs: *const Fat32Fs<D>,
-    path: String,
-    cluster: u32,
-    size: u32,
-    position: usize,
-    data: Vec<u8>,
-    dirty: bool,
-    flags: OpenFlags,
-}
-
-unsafe impl<D: BlockDevice + 'static> Send for FatFileHandle<D> {}
-unsafe impl<D: BlockDevice + 'static> Sync for FatFileHandle<D> {}
-
-impl<D: BlockDevice + 'static> FileHandle for FatFileHandle<D> {
-    fn read(&mut self, buf: &mut [u8]) -> VfsResult<usize> {
-        let available = self.data.len().saturating_sub(self.position);
-        let to_read = buf.len().min(available);
-        buf[..to_read].copy_from_slice(&self.data[self.position..self.position + to_read]);
-        self.position += to_read;
-        Ok(to_read)
-    }
 
-    fn write(&mut self, buf: &[u8]) -> VfsResult<usize> {
-        if !self.flags.is_writable() {
-            return Err(VfsError::PermissionDenied);
-        }
+    fn ensure_chain(&self, start: u32, len: usize) -> Result<u32, &'static str> {
+        let cluster_size = self.bpb.bytes_per_cluster();
+        let clusters_needed = len.div_ceil(cluster_size);
 
-        let end_pos = self.position + buf.len();
-        if end_pos > self.data.len() {
-            self.data.resize(end_pos, 0);
+        if clusters_needed == 0 {
+            return Ok(start);
         }
 
-        self.data[self.position..end_pos].copy_from_slice(buf);
-        self.position = end_pos;
-        self.dirty = true;
-
-        Ok(buf.len())
-    }
-
-    fn seek(&mut self, pos: SeekFrom) -> VfsResult<usize> {
-        let new_pos = match pos {
-            SeekFrom::Start(n) => n as isize,
-            SeekFrom::Current(n) => self.position as isize + n,
-            SeekFrom::End(n) => self.data.len() as isize + n,
+        let first = if start < 2 {
+            self.allocate_cluster()?
+        } else {
+            start
         };
 
-        if new_pos < 0 {
-            return Err(VfsError::InvalidPath);
+        let mut cluster = first;
+        for _ in 1..clusters_needed {
+            let next = self.get_fat_entry(cluster)?;
+            cluster = if next >= FAT32_EOC {
+                self.extend_chain(cluster)?
+            } else {
+                next
+            };
         }
 
-        self.position = new_pos as usize;
-        Ok(self.position)
-    }
-
-    fn metadata(&self) -> VfsResult<Metadata> {
-        Ok(Metadata {
-            file_type: FileType::File,
-            size: self.data.len(),
-        })
+        Ok(first)
     }
-}
 
-impl<D: BlockDevice + 'static> Drop for FatFileHandle<D> {
-    fn drop(&mut self) {
-        if self.dirty {
-            let fs = unsafe { &*self.fs };
-            let _ = fs.sync_file(&self.path, self.cluster, &self.data);
+    // keeps as much of the chain as `len` bytes need and frees the rest.
+    // returns the new start, 0 once nothing is left
+    fn truncate_chain(&self, start: u32, len: usize) -> Result<u32, &'static str> {
+        let keep = len.div_ceil(self.bpb.bytes_per_cluster());
+        if keep == 0 {
+            self.free_chain(start)?;
+            return Ok(0);
         }
-    }
-}
-
-pub struct Fat32Fs<D: BlockDevice + 'static> {
-    inner: Mutex<Fat32Inner<D>>,
-}
-
-impl<D: BlockDevice + 'static> Fat32Fs<D> {
-    pub fn new(device: D) -> Result<Self, &'static str> {
-        let mut sector = [0u8; SECTOR_SIZE];
-        device.read_sector(0, &mut sector)?;
-
-        let bpb = Bpb::parse(&sector).ok_or("invalid FAT32 boot sector")?;
 
-        if bpb.sectors_per_fat == 0 {
-            return Err("not a FAT32 filesystem");
+        let Some(last) = self.cluster_at(start, keep - 1)? else {
+            return Ok(start);
+        };
+        let rest = self.get_fat_entry(last)?;
+        if (2..FAT32_EOC).contains(&rest) {
+            // cut first, so a failure part way leaks clusters rather than
+            // leaving the file pointing at free ones
+            self.set_fat_entry(last, FAT32_EOC)?;
+            self.free_chain(rest)?;
         }
-
-        Ok(Self {
-            inner: Mutex::new(Fat32Inner { device, bpb }),
-        })
+        Ok(start)
     }
 
-    fn sync_file(&self, path: &str, old_cluster: u32, data: &[u8]) -> VfsResult<()> {
-        let inner = self.inner.lock();
-
-        let (parent_cluster, name) = inner.split_path(path).map_err(|_| VfsError::InvalidPath)?;
-
-        let entry = inner
-            .find_in_directory(parent_cluster, &name)
-            .map_err(|_| VfsError::IoError)?
-            .ok_or(VfsError::NotFound)?;
+    fn for_each_page_sector(
+        &self,
+        start: u32,
+        index: u64,
+        mut f: impl FnMut(&Self, u32, usize) -> Result<(), &'static str>,
+    ) -> Result<(), &'static str> {
+        let sectors_per_page = PAGE_SIZE / SECTOR_SIZE;
+        let spc = self.bpb.sectors_per_cluster as usize;
+        let first_sector = index as usize * sectors_per_page;
 
-        let new_cluster = if data.is_empty() {
-            if old_cluster >= 2 {
-                inner
-                    .free_chain(old_cluster)
-                    .map_err(|_| VfsError::IoError)?;
-            }
-            0
-        } else {
-            inner
-                .write_chain(old_cluster, data)
-                .map_err(|_| VfsError::IoError)?
+        let mut cluster_index = first_sector / spc;
+        let Some(mut cluster) = self.cluster_at(start, cluster_index)? else {
+            return Ok(());
         };
 
-        let mut updated_entry = entry;
-        updated_entry.short_entry.set_cluster(new_cluster);
-        updated_entry.short_entry.size = data.len() as u32;
-
-        inner
-            .update_dir_entry(parent_cluster, &updated_entry)
-            .map_err(|_| VfsError::IoError)?;
-
-        Ok(())
-    }
-}
-
-impl<D: BlockDevice + 'static> Filesystem for Fat32Fs<D> {
-    fn open(&self, path: &str, flags: OpenFlags) -> VfsResult<Box<dyn FileHandle>> {
-        let inner = self.inner.lock();
-
-        let (_parent_cluster, entry_opt) =
-            inner.resolve_path(path).map_err(|_| VfsError::NotFound)?;
+        for i in 0..sectors_per_page {
+            let file_sector = first_sector + i;
 
-        match entry_opt {
-            Some(entry) => {
-                if entry.is_directory() {
-                    return Err(VfsError::IsADirectory);
+            if file_sector / spc != cluster_index {
+                cluster_index = file_sector / spc;
+                cluster = self.get_fat_entry(cluster)?;
+                if !(2..FAT32_EOC).contains(&cluster) {
+                    break;
                 }
-
-                let data = if entry.cluster() >= 2 {
-                    let mut d = inner
-                        .read_chain(entry.cluster())
-                        .map_err(|_| VfsError::IoError)?;
-                    d.truncate(entry.size() as usize);
-                    d
-                } else {
-                    Vec::new()
-                };
-
-                let position = if flags.contains(OpenFlags::O_APPEND) {
-                    data.len()
-                } else {
-                    0
-                };
-
-                let data = if flags.contains(OpenFlags::O_TRUNC) {
-                    Vec::new()
-                } else {
-                    data
-                };
-
-                drop(inner);
-
-                Ok(Box::new(FatFileHandle {
-                    fs: self as *const _,
-                    path: path.to_string(),
-                    cluster: entry.cluster(),
-                    size: entry.size(),
-                    position,
-                    data,
-                    dirty: flags.contains(OpenFlags::O_TRUNC),
-                    flags,
-                }))
             }
-            None => {
-                if !flags.contains(OpenFlags::O_CREAT) {
-                    return Err(VfsError::NotFound);
-                }
-
-                let (parent_cluster, name) =
-                    inner.split_path(path).map_err(|_| VfsError::InvalidPath)?;
-
-                inner
-                    .add_dir_entry(parent_cluster, &name, ATTR_ARCHIVE, 0, 0)
-                    .map_err(|_| VfsError::IoError)?;
 
-                drop(inner);
-
-                Ok(Box::new(FatFileHandle {
-                    fs: self as *const _,
-                    path: path.to_string(),
-                    cluster: 0,
-                    size: 0,
-                    position: 0,
-                    data: Vec::new(),
-                    dirty: false,
-                    flags,
-                }))
-            }
+            let sector = self.bpb.cluster_to_sector(cluster) + (file_sector % spc) as u32;
+            f(self, sector, i * SECTOR_SIZE)?;
         }
-    }
 
-    fn mkdir(&self, path: &str) -> VfsResult<()> {
-        let inner = self.inner.lock();
+        Ok(())
+    }
 
-        let (parent_cluster, name) = inner.split_path(path).map_err(|_| VfsError::InvalidPath)?;
+    fn read_file_page(&self, start: u32, index: u64, buf: &mut [u8]) -> Result<(), &'static str> {
+        self.for_each_page_sector(start, index, |inner, sector, offset| {
+            let mut data = [0u8; SECTOR_SIZE];
+            inner.read_sector(sector, &mut data)?;
+            buf[offset..offset + SECTOR_SIZE].copy_from_slice(&data);
+            Ok(())
+        })
+    }
 
-        if inner
-            .find_in_directory(parent_cluster, &name)
-            .map_err(|_| VfsError::IoError)?
-            .is_some()
-        {
-            return Err(VfsError::AlreadyExists);
-        }
+    fn write_file_page(&self, start: u32, index: u64, buf: &[u8]) -> Result<(), &'static str> {
+        self.for_each_page_sector(start, index, |inner, sector, offset| {
+            let mut data = [0u8; SECTOR_SIZE];
+            data.copy_from_slice(&buf[offset..offset + SECTOR_SIZE]);
+            inner.write_sector(sector, &data)
+        })
+    }
 
-        let dir_cluster = inner.allocate_cluster().map_err(|_| VfsError::IoError)?;
+    fn init_directory(&self, parent_cluster: u32) -> Result<u32, &'static str> {
+        let dir_cluster = self.allocate_cluster()?;
 
-        let cluster_size = inner.bpb.bytes_per_cluster();
+        let cluster_size = self.bpb.bytes_per_cluster();
         let mut dir_data = vec![0u8; cluster_size];
 
         let dot_entry = ShortDirEntry {
@@ -1254,132 +1317,1188 @@ impl<D: BlockDevice + 'static> Filesystem for Fat32Fs<D> {
         };
         dir_data[32..64].copy_from_slice(&dotdot_entry.serialize());
 
-        inner
-            .write_cluster(dir_cluster, &dir_data)
-            .map_err(|_| VfsError::IoError)?;
-
-        inner
-            .add_dir_entry(parent_cluster, &name, ATTR_DIRECTORY, dir_cluster, 0)
-            .map_err(|_| VfsError::IoError)?;
+        if let Err(e) = self.write_cluster(dir_cluster, &dir_data) {
+            let _ = self.free_chain(dir_cluster);
+            return Err(e);
+        }
 
-        Ok(())
+        Ok(dir_cluster)
     }
+}
 
-    fn remove(&self, path: &str) -> VfsResult<()> {
-        let inner = self.inner.lock();
-
-        let (parent_cluster, name) = inner.split_path(path).map_err(|_| VfsError::InvalidPath)?;
+// where a directory entry lives, which stays put for the life of the file
+fn entry_location(parent: u32, entry: &FatDirEntry) -> u64 {
+    let slot = entry.entry_offset / DIR_ENTRY_SIZE + entry.entry_count - 1;
+    ((parent as u64) << 32) | slot as u64
+}
 
-        let entry = inner
-            .find_in_directory(parent_cluster, &name)
-            .map_err(|_| VfsError::IoError)?
-            .ok_or(VfsError::NotFound)?;
+struct FatVolume<D: BlockDevice + 'static> {
+    id: u64,
+    inner: Arc<Mutex<Fat32Inner<D>>>,
+    // one inode per live directory entry, so every opener shares its size
+    // and cluster chain
+    inodes: Mutex<BTreeMap<u64, Weak<FatInode<D>>>>,
+    // the same inodes by number, which is what the page cache knows them by
+    files: Mutex<BTreeMap<u64, Weak<FatInode<D>>>>,
+    next_ino: AtomicU64,
+}
 
-        if entry.is_directory() {
-            return Err(VfsError::IsADirectory);
-        }
+impl<D: BlockDevice + 'static> FatVolume<D> {
+    fn inode(self: &Arc<Self>, parent: u32, entry: FatDirEntry) -> Arc<FatInode<D>> {
+        let location = entry_location(parent, &entry);
+        let mut inodes = self.inodes.lock();
 
-        if entry.cluster() >= 2 {
-            inner
-                .free_chain(entry.cluster())
-                .map_err(|_| VfsError::IoError)?;
+        if let Some(inode) = inodes.get(&location).and_then(Weak::upgrade) {
+            return inode;
         }
 
-        inner
-            .remove_dir_entry(parent_cluster, &entry)
-            .map_err(|_| VfsError::IoError)?;
-
-        Ok(())
+        let inode = Arc::new(FatInode {
+            vol: self.clone(),
+            ino: self.next_ino.fetch_add(1, Ordering::Relaxed),
+            node: Mutex::new(FatNode {
+                directory: entry.is_directory(),
+                parent,
+                cluster: entry.cluster(),
+                size: entry.size(),
+                entry: Some(entry),
+                unlinked: false,
+                claimed: false,
+            }),
+        });
+        inodes.insert(location, Arc::downgrade(&inode));
+        self.files.lock().insert(inode.ino, Arc::downgrade(&inode));
+        inode
+    }
+
+    fn live(&self, parent: u32, entry: &FatDirEntry) -> Option<Arc<FatInode<D>>> {
+        self.inodes
+            .lock()
+            .get(&entry_location(parent, entry))
+            .and_then(Weak::upgrade)
+    }
+
+    fn file(&self, ino: u64) -> VfsResult<Arc<FatInode<D>>> {
+        self.files
+            .lock()
+            .get(&ino)
+            .and_then(Weak::upgrade)
+            .ok_or(VfsError::NotFound)
+    }
+
+    // detaches the inode for an entry that is about to be removed
+    fn take(&self, parent: u32, entry: &FatDirEntry) -> Option<Arc<FatInode<D>>> {
+        self.inodes
+            .lock()
+            .remove(&entry_location(parent, entry))
+            .and_then(|inode| inode.upgrade())
     }
+}
 
-    fn rmdir(&self, path: &str) -> VfsResult<()> {
-        let inner = self.inner.lock();
-
-        let (parent_cluster, name) = inner.split_path(path).map_err(|_| VfsError::InvalidPath)?;
+// pages are cached by inode number. holding the node keeps the chain from
+// being freed, or handed to another file, while a page goes out
+impl<D: BlockDevice + 'static> PageSource for FatVolume<D> {
+    fn read_page(&self, file: u64, index: u64, buf: &mut [u8]) -> VfsResult<()> {
+        let inode = self.file(file)?;
+        let node = inode.node.lock();
+        if node.cluster < 2 {
+            return Ok(());
+        }
+        self.inner
+            .lock()
+            .read_file_page(node.cluster, index, buf)
+            .map_err(|_| VfsError::IoError)
+    }
+
+    fn write_page(&self, file: u64, index: u64, buf: &[u8]) -> VfsResult<()> {
+        let inode = self.file(file)?;
+        let node = inode.node.lock();
+        // cut short since the page was written
+        if node.cluster < 2 || index as usize * PAGE_SIZE >= node.size as usize {
+            return Ok(());
+        }
+        self.inner
+            .lock()
+            .write_file_page(node.cluster, index, buf)
+            .map_err(|_| VfsError::IoError)
+    }
+}
 
-        let entry = inner
-            .find_in_directory(parent_cluster, &name)
-            .map_err(|_| VfsError::IoError)?
-            .ok_or(VfsError::NotFound)?;
+struct FatNode {
+    directory: bool,
+    // the directory holding our entry, and the entry itself. the root has none
+    parent: u32,
+    entry: Option<FatDirEntry>,
+    cluster: u32,
+    size: u32,
+    // removed from its directory, clusters are freed once the last user is gone
+    unlinked: bool,
+    // a handle does direct I/O on it, so the page cache must stay out
+    claimed: bool,
+}
 
-        if !entry.is_directory() {
+impl FatNode {
+    fn dir_cluster(&self) -> VfsResult<u32> {
+        if !self.directory {
             return Err(VfsError::NotADirectory);
         }
-
-        let entries = inner
-            .read_directory(entry.cluster())
-            .map_err(|_| VfsError::IoError)?;
-
-        if !entries.is_empty() {
-            return Err(VfsError::NotEmpty);
+        if self.unlinked {
+            return Err(VfsError::NotFound);
         }
+        Ok(self.cluster)
+    }
 
-        inner
-            .free_chain(entry.cluster())
-            .map_err(|_| VfsError::IoError)?;
+    // writes the first cluster and size back to the directory entry
+    fn store<D: BlockDevice>(&mut self, inner: &Fat32Inner<D>) -> VfsResult<()> {
+        if self.unlinked {
+            return Ok(());
+        }
+        let Some(entry) = self.entry.as_mut() else {
+            return Ok(());
+        };
 
+        entry.short_entry.set_cluster(self.cluster);
+        entry.short_entry.size = self.size;
         inner
-            .remove_dir_entry(parent_cluster, &entry)
-            .map_err(|_| VfsError::IoError)?;
-
-        Ok(())
+            .update_dir_entry(self.parent, entry)
+            .map_err(|_| VfsError::IoError)
     }
+}
 
-    fn readdir(&self, path: &str) -> VfsResult<Vec<DirEntry>> {
-        let inner = self.inner.lock();
-
-        let cluster = if path.trim_matches('/').is_empty() {
-            inner.bpb.root_cluster
-        } else {
-            let (_, entry) = inner.resolve_path(path).map_err(|_| VfsError::NotFound)?;
+fn try_lock_node<D: BlockDevice + 'static>(
+    inode: &Option<Arc<FatInode<D>>>,
+) -> Option<Option<MutexGuard<'_, FatNode>>> {
+    match inode {
+        Some(inode) => inode.node.try_lock().map(Some),
+        None => Some(None),
+    }
+}
 
-            let entry = entry.ok_or(VfsError::NotFound)?;
+// lock order is directory node, then child node, then the volume
+struct FatInode<D: BlockDevice + 'static> {
+    vol: Arc<FatVolume<D>>,
+    ino: u64,
+    node: Mutex<FatNode>,
+}
 
-            if !entry.is_directory() {
-                return Err(VfsError::NotADirectory);
+impl<D: BlockDevice + 'static> Drop for FatInode<D> {
+    fn drop(&mut self) {
+        let node = self.node.get_mut();
+        // every handle wrote its pages back when it closed, and the number
+        // they are cached under dies with us
+        cache::invalidate(self.vol.id, self.ino);
+        self.vol.files.lock().remove(&self.ino);
+
+        if node.unlinked {
+            if node.cluster >= 2 {
+                let _ = self.vol.inner.lock().free_chain(node.cluster);
+            }
+        } else if let Some(entry) = &node.entry {
+            let mut inodes = self.vol.inodes.lock();
+            let location = entry_location(node.parent, entry);
+            // the slot may already belong to a newer inode
+            if inodes.get(&location).is_some_and(|i| i.strong_count() == 0) {
+                inodes.remove(&location);
+            }
+        }
+    }
+}
+
+impl<D: BlockDevice + 'static> Inode for FatInode<D> {
+    fn id(&self) -> u64 {
+        self.ino
+    }
+
+    fn metadata(&self) -> VfsResult<Metadata> {
+        let node = self.node.lock();
+        let (file_type, size, mode) = if node.directory {
+            (FileType::Directory, 0, 0o755)
+        } else {
+            (FileType::File, node.size as usize, 0o644)
+        };
+        // fat has no owners, only a read-only attribute that takes away
+        // every write bit. it has no change time either
+        let entry = node.entry.as_ref().map(|e| &e.short_entry);
+        let read_only = entry.is_some_and(|e| e.attr & ATTR_READ_ONLY != 0);
+        let (atime, mtime) = entry.map_or((0, 0), |e| (e.accessed(), e.modified()));
+        Ok(Metadata {
+            file_type,
+            size,
+            uid: 0,
+            gid: 0,
+            mode: if read_only { mode & !0o222 } else { mode },
+            atime,
+            mtime,
+            ctime: mtime,
+        })
+    }
+
+    fn set_times(&self, atime: Option<u64>, mtime: Option<u64>) -> VfsResult<()> {
+        let mut node = self.node.lock();
+        let entry = node.entry.as_mut().ok_or(VfsError::NotSupported)?;
+        if let Some(atime) = atime {
+            entry.short_entry.set_accessed(atime);
+        }
+        if let Some(mtime) = mtime {
+            entry.short_entry.set_modified(mtime);
+        }
+
+        let inner = self.vol.inner.lock();
+        node.store(&inner)
+    }
+
+    fn chmod(&self, mode: u32) -> VfsResult<()> {
+        let mut node = self.node.lock();
+        let entry = node.entry.as_mut().ok_or(VfsError::NotSupported)?;
+        if mode & 0o200 == 0 {
+            entry.short_entry.attr |= ATTR_READ_ONLY;
+        } else {
+            entry.short_entry.attr &= !ATTR_READ_ONLY;
+        }
+
+        let inner = self.vol.inner.lock();
+        node.store(&inner)
+    }
+
+    fn open(self: Arc<Self>, flags: OpenFlags) -> VfsResult<Box<dyn FileHandle>> {
+        let position = {
+            let mut node = self.node.lock();
+            if node.directory {
+                return Err(VfsError::IsADirectory);
+            }
+            if node.claimed {
+                return Err(VfsError::Busy);
+            }
+
+            if flags.contains(OpenFlags::O_TRUNC) && node.size > 0 {
+                let inner = self.vol.inner.lock();
+                cache::invalidate(self.vol.id, self.ino);
+                if node.cluster >= 2 {
+                    inner
+                        .free_chain(node.cluster)
+                        .map_err(|_| VfsError::IoError)?;
+                }
+                node.cluster = 0;
+                node.size = 0;
+                if let Some(entry) = node.entry.as_mut() {
+                    entry.short_entry.set_modified(time::realtime_now());
+                }
+                node.store(&inner)?;
             }
 
-            entry.cluster()
+            if flags.contains(OpenFlags::O_APPEND) {
+                node.size as usize
+            } else {
+                0
+            }
         };
 
-        let entries = inner
+        Ok(Box::new(FatFileHandle {
+            inode: self,
+            position,
+            dirty: false,
+            claimed: false,
+            flags,
+        }))
+    }
+
+    fn lookup(&self, name: &str) -> VfsResult<InodeRef> {
+        let node = self.node.lock();
+        let cluster = node.dir_cluster()?;
+
+        let entry = self
+            .vol
+            .inner
+            .lock()
+            .find_in_directory(cluster, name)
+            .map_err(|_| VfsError::IoError)?
+            .ok_or(VfsError::NotFound)?;
+
+        Ok(self.vol.inode(cluster, entry))
+    }
+
+    fn create(&self, name: &str, file_type: FileType) -> VfsResult<InodeRef> {
+        let node = self.node.lock();
+        let cluster = node.dir_cluster()?;
+
+        let entry = {
+            let inner = self.vol.inner.lock();
+            if inner
+                .find_in_directory(cluster, name)
+                .map_err(|_| VfsError::IoError)?
+                .is_some()
+            {
+                return Err(VfsError::AlreadyExists);
+            }
+
+            match file_type {
+                FileType::Directory => {
+                    let dir_cluster = inner
+                        .init_directory(cluster)
+                        .map_err(|_| VfsError::NoSpace)?;
+                    inner
+                        .add_dir_entry(cluster, name, ATTR_DIRECTORY, dir_cluster, 0)
+                        .map_err(|_| {
+                            let _ = inner.free_chain(dir_cluster);
+                            VfsError::IoError
+                        })?
+                }
+                FileType::File => inner
+                    .add_dir_entry(cluster, name, ATTR_ARCHIVE, 0, 0)
+                    .map_err(|_| VfsError::IoError)?,
+                _ => return Err(VfsError::NotSupported),
+            }
+        };
+
+        Ok(self.vol.inode(cluster, entry))
+    }
+
+    fn unlink(&self, name: &str) -> VfsResult<()> {
+        let node = self.node.lock();
+        let cluster = node.dir_cluster()?;
+
+        let entry = self
+            .vol
+            .inner
+            .lock()
+            .find_in_directory(cluster, name)
+            .map_err(|_| VfsError::IoError)?
+            .ok_or(VfsError::NotFound)?;
+
+        if entry.is_directory() {
+            return Err(VfsError::IsADirectory);
+        }
+
+        let live = self.vol.take(cluster, &entry);
+        let mut child = live.as_ref().map(|inode| inode.node.lock());
+
+        {
+            let inner = self.vol.inner.lock();
+            inner
+                .remove_dir_entry(cluster, &entry)
+                .map_err(|_| VfsError::IoError)?;
+
+            match child.as_mut() {
+                Some(child) => child.unlinked = true,
+                // without an inode nothing of it is cached
+                None if entry.cluster() >= 2 => {
+                    inner
+                        .free_chain(entry.cluster())
+                        .map_err(|_| VfsError::IoError)?;
+                }
+                None => {}
+            }
+        }
+
+        // the last reference frees the clusters, which needs the volume
+        drop(child);
+        drop(node);
+        drop(live);
+        Ok(())
+    }
+
+    fn rmdir(&self, name: &str) -> VfsResult<()> {
+        let node = self.node.lock();
+        let cluster = node.dir_cluster()?;
+
+        let entry = self
+            .vol
+            .inner
+            .lock()
+            .find_in_directory(cluster, name)
+            .map_err(|_| VfsError::IoError)?
+            .ok_or(VfsError::NotFound)?;
+
+        if !entry.is_directory() {
+            return Err(VfsError::NotADirectory);
+        }
+
+        let live = self.vol.take(cluster, &entry);
+        // held until the entry is gone so nothing is created in between
+        let mut child = live.as_ref().map(|inode| inode.node.lock());
+
+        let result = (|| {
+            let inner = self.vol.inner.lock();
+            let entries = inner
+                .read_directory(entry.cluster())
+                .map_err(|_| VfsError::IoError)?;
+            if !entries.is_empty() {
+                return Err(VfsError::NotEmpty);
+            }
+
+            inner
+                .free_chain(entry.cluster())
+                .map_err(|_| VfsError::IoError)?;
+            inner
+                .remove_dir_entry(cluster, &entry)
+                .map_err(|_| VfsError::IoError)
+        })();
+
+        match child.as_mut() {
+            Some(child) if result.is_ok() => {
+                child.unlinked = true;
+                child.cluster = 0;
+            }
+            // still there, put it back
+            Some(_) => {
+                if let Some(inode) = &live {
+                    self.vol
+                        .inodes
+                        .lock()
+                        .insert(entry_location(cluster, &entry), Arc::downgrade(inode));
+                }
+            }
+            None => {}
+        }
+
+        drop(child);
+        drop(node);
+        drop(live);
+        result
+    }
+
+    fn rename(&self, name: &str, new_dir: &InodeRef, new_name: &str) -> VfsResult<()> {
+        let target = (new_dir.as_ref() as &dyn Any)
+            .downcast_ref::<FatInode<D>>()
+            .filter(|dir| Arc::ptr_eq(&dir.vol, &self.vol))
+            .ok_or(VfsError::CrossDevice)?;
+        let same_dir = self.ino == target.ino;
+
+        // rmdir locks a parent and then its child, and either directory here
+        // may be the other's ancestor, so everything past the first lock is
+        // only tried and dropped again on contention
+        loop {
+            let src = self.node.lock();
+            let dst = if same_dir {
+                None
+            } else if let Some(dst) = target.node.try_lock() {
+                Some(dst)
+            } else {
+                continue;
+            };
+
+            let src_cluster = src.dir_cluster()?;
+            let dst_cluster = match &dst {
+                Some(dst) => dst.dir_cluster()?,
+                None => src_cluster,
+            };
+
+            let (entry, existing) = {
+                let inner = self.vol.inner.lock();
+                let entry = inner
+                    .find_in_directory(src_cluster, name)
+                    .map_err(|_| VfsError::IoError)?
+                    .ok_or(VfsError::NotFound)?;
+                // a rename that only changes case finds the entry itself
+                let existing = inner
+                    .find_in_directory(dst_cluster, new_name)
+                    .map_err(|_| VfsError::IoError)?
+                    .filter(|old| {
+                        entry_location(dst_cluster, old) != entry_location(src_cluster, &entry)
+                    });
+                (entry, existing)
+            };
+
+            if same_dir && existing.is_none() && entry.name == new_name {
+                return Ok(());
+            }
+            if let Some(old) = &existing {
+                match (entry.is_directory(), old.is_directory()) {
+                    (true, false) => return Err(VfsError::NotADirectory),
+                    (false, true) => return Err(VfsError::IsADirectory),
+                    _ => {}
+                }
+            }
+
+            let moved = self.vol.live(src_cluster, &entry);
+            let replaced = existing
+                .as_ref()
+                .and_then(|old| self.vol.live(dst_cluster, old));
+            let Some(mut moved_node) = try_lock_node(&moved) else {
+                continue;
+            };
+            let Some(mut replaced_node) = try_lock_node(&replaced) else {
+                continue;
+            };
+
+            let new_entry = {
+                let inner = self.vol.inner.lock();
+
+                if let Some(old) = &existing
+                    && old.is_directory()
+                    && !inner
+                        .read_directory(old.cluster())
+                        .map_err(|_| VfsError::IoError)?
+                        .is_empty()
+                {
+                    return Err(VfsError::NotEmpty);
+                }
+
+                let (cluster, size) = moved_node
+                    .as_ref()
+                    .map_or((entry.cluster(), entry.size()), |n| (n.cluster, n.size));
+                let mut short_entry = entry.short_entry.clone();
+                short_entry.set_cluster(cluster);
+                short_entry.size = size;
+
+                // the new name goes in first, so running out of room leaves
+                // both names as they were
+                let new_entry = inner
+                    .insert_dir_entry(dst_cluster, new_name, short_entry)
+                    .map_err(|_| VfsError::IoError)?;
+                inner
+                    .remove_dir_entry(src_cluster, &entry)
+                    .map_err(|_| VfsError::IoError)?;
+
+                if let Some(old) = &existing {
+                    inner
+                        .remove_dir_entry(dst_cluster, old)
+                        .map_err(|_| VfsError::IoError)?;
+                    // a file still open keeps its clusters until it is closed
+                    if old.cluster() >= 2 && (old.is_directory() || replaced.is_none()) {
+                        inner
+                            .free_chain(old.cluster())
+                            .map_err(|_| VfsError::IoError)?;
+                    }
+                }
+
+                if entry.is_directory() && !same_dir {
+                    inner
+                        .set_parent_link(cluster, dst_cluster)
+                        .map_err(|_| VfsError::IoError)?;
+                }
+                new_entry
+            };
+
+            {
+                let mut inodes = self.vol.inodes.lock();
+                inodes.remove(&entry_location(src_cluster, &entry));
+                if let Some(old) = &existing {
+                    inodes.remove(&entry_location(dst_cluster, old));
+                }
+                let location = entry_location(dst_cluster, &new_entry);
+                match &moved {
+                    Some(inode) => inodes.insert(location, Arc::downgrade(inode)),
+                    None => inodes.remove(&location),
+                };
+            }
+
+            if let Some(node) = moved_node.as_mut() {
+                node.parent = dst_cluster;
+                node.entry = Some(new_entry);
+            }
+            if let Some(node) = replaced_node.as_mut() {
+                node.unlinked = true;
+                if node.directory {
+                    node.cluster = 0;
+                }
+            }
+
+            return Ok(());
+        }
+    }
+
+    fn readdir(&self) -> VfsResult<Vec<DirEntry>> {
+        let node = self.node.lock();
+        let cluster = node.dir_cluster()?;
+
+        let entries = self
+            .vol
+            .inner
+            .lock()
             .read_directory(cluster)
             .map_err(|_| VfsError::IoError)?;
 
         Ok(entries
             .into_iter()
             .map(|e| DirEntry {
-                name: e.name.clone(),
                 file_type: if e.is_directory() {
                     FileType::Directory
                 } else {
                     FileType::File
                 },
+                name: e.name,
             })
             .collect())
     }
+}
 
-    fn metadata(&self, path: &str) -> VfsResult<Metadata> {
-        let inner = self.inner.lock();
+static ZEROS: [u8; SECTOR_SIZE] = [0; SECTOR_SIZE];
 
-        if path.trim_matches('/').is_empty() {
-            return Ok(Metadata {
-                file_type: FileType::Directory,
-                size: 0,
-            });
+// the last cluster may still hold old data past the end of the file, which
+// has to read back as zeros once the file grows over it. clusters allocated
+// later are zeroed already
+fn zero_gap(fs: u64, ino: u64, cluster_size: usize, from: usize, to: usize) -> VfsResult<()> {
+    let end = to.min(from.next_multiple_of(cluster_size));
+    let mut pos = from;
+    while pos < end {
+        let n = (end - pos).min(ZEROS.len());
+        cache::write(fs, ino, pos, &ZEROS[..n])?;
+        pos += n;
+    }
+    Ok(())
+}
+
+struct FatFileHandle<D: BlockDevice + 'static> {
+    inode: Arc<FatInode<D>>,
+    position: usize,
+    dirty: bool,
+    claimed: bool,
+    flags: OpenFlags,
+}
+
+impl<D: BlockDevice + 'static> FatFileHandle<D> {
+    fn extent(&self) -> (u32, u32) {
+        let node = self.inode.node.lock();
+        (node.cluster, node.size)
+    }
+
+    fn check_cached(&self) -> VfsResult<()> {
+        if self.inode.node.lock().claimed {
+            return Err(VfsError::Busy);
         }
+        Ok(())
+    }
+}
 
-        let (_, entry) = inner.resolve_path(path).map_err(|_| VfsError::NotFound)?;
+impl<D: BlockDevice + 'static> FileHandle for FatFileHandle<D> {
+    fn read(&mut self, buf: &mut [u8]) -> VfsResult<usize> {
+        self.check_cached()?;
+        let (cluster, size) = self.extent();
+        let available = (size as usize).saturating_sub(self.position);
+        let to_read = buf.len().min(available);
 
-        let entry = entry.ok_or(VfsError::NotFound)?;
+        if to_read == 0 || cluster < 2 {
+            return Ok(0);
+        }
 
-        Ok(Metadata {
-            file_type: if entry.is_directory() {
-                FileType::Directory
+        let read = cache::read(
+            self.inode.vol.id,
+            self.inode.ino,
+            self.position,
+            &mut buf[..to_read],
+        )?;
+        self.position += read;
+        Ok(read)
+    }
+
+    fn write(&mut self, buf: &[u8]) -> VfsResult<usize> {
+        if !self.flags.is_writable() {
+            return Err(VfsError::PermissionDenied);
+        }
+        self.check_cached()?;
+
+        let vol = &self.inode.vol;
+        let (old_size, cluster_size) = {
+            let mut node = self.inode.node.lock();
+
+            if self.flags.contains(OpenFlags::O_APPEND) {
+                self.position = node.size as usize;
+            }
+            // a fat32 file cannot be 4 GiB or more
+            let end_pos = self.position + buf.len();
+            if end_pos > u32::MAX as usize {
+                return Err(VfsError::NoSpace);
+            }
+            let old_size = node.size as usize;
+
+            let inner = vol.inner.lock();
+            let cluster = inner
+                .ensure_chain(node.cluster, end_pos)
+                .map_err(|_| VfsError::NoSpace)?;
+            let size = node.size.max(end_pos as u32);
+            let stamped = node
+                .entry
+                .as_mut()
+                .is_some_and(|e| e.short_entry.set_modified(time::realtime_now()));
+
+            if stamped || cluster != node.cluster || size != node.size {
+                node.cluster = cluster;
+                node.size = size;
+                node.store(&inner)?;
+            }
+            (old_size, inner.bpb.bytes_per_cluster())
+        };
+
+        let ino = self.inode.ino;
+        if self.position > old_size {
+            zero_gap(vol.id, ino, cluster_size, old_size, self.position)?;
+        }
+        let written = cache::write(vol.id, ino, self.position, buf)?;
+        self.position += written;
+        self.dirty = true;
+
+        Ok(written)
+    }
+
+    fn seek(&mut self, pos: SeekFrom) -> VfsResult<usize> {
+        let new_pos = match pos {
+            SeekFrom::Start(n) => n as isize,
+            SeekFrom::Current(n) => self.position as isize + n,
+            SeekFrom::End(n) => self.extent().1 as isize + n,
+        };
+
+        if new_pos < 0 {
+            return Err(VfsError::InvalidPath);
+        }
+
+        self.position = new_pos as usize;
+        Ok(self.position)
+    }
+
+    fn metadata(&self) -> VfsResult<Metadata> {
+        self.inode.metadata()
+    }
+
+    fn read_page_direct(&mut self, index: u64, buf: &mut [u8]) -> VfsResult<()> {
+        let (cluster, size) = self.extent();
+        if (index as usize + 1) * PAGE_SIZE > size as usize {
+            return Err(VfsError::InvalidPath);
+        }
+
+        self.inode
+            .vol
+            .inner
+            .lock()
+            .read_file_page(cluster, index, buf)
+            .map_err(|_| VfsError::IoError)
+    }
+
+    fn write_page_direct(&mut self, index: u64, buf: &[u8]) -> VfsResult<()> {
+        if !self.flags.is_writable() {
+            return Err(VfsError::PermissionDenied);
+        }
+        let (cluster, size) = self.extent();
+        if (index as usize + 1) * PAGE_SIZE > size as usize {
+            return Err(VfsError::InvalidPath);
+        }
+
+        self.inode
+            .vol
+            .inner
+            .lock()
+            .write_file_page(cluster, index, buf)
+            .map_err(|_| VfsError::IoError)
+    }
+
+    fn set_len(&mut self, len: usize) -> VfsResult<()> {
+        if !self.flags.is_writable() {
+            return Err(VfsError::PermissionDenied);
+        }
+        self.check_cached()?;
+        let new_size = u32::try_from(len).map_err(|_| VfsError::NoSpace)?;
+
+        let vol = &self.inode.vol;
+        // let go of the node before zeroing, which reads pages in through it
+        let (old_size, cluster_size) = {
+            let mut node = self.inode.node.lock();
+            let old_size = node.size as usize;
+            let inner = vol.inner.lock();
+            let cluster = if len < old_size {
+                // cached pages past the end must not be written back into
+                // clusters that are about to be freed
+                cache::truncate(vol.id, self.inode.ino, len);
+                inner
+                    .truncate_chain(node.cluster, len)
+                    .map_err(|_| VfsError::IoError)?
             } else {
-                FileType::File
-            },
-            size: entry.size() as usize,
+                inner
+                    .ensure_chain(node.cluster, len)
+                    .map_err(|_| VfsError::NoSpace)?
+            };
+
+            node.cluster = cluster;
+            node.size = new_size;
+            if let Some(entry) = node.entry.as_mut() {
+                entry.short_entry.set_modified(time::realtime_now());
+            }
+            node.store(&inner)?;
+            (old_size, inner.bpb.bytes_per_cluster())
+        };
+
+        if len > old_size {
+            zero_gap(vol.id, self.inode.ino, cluster_size, old_size, len)?;
+            self.dirty = true;
+        }
+        Ok(())
+    }
+
+    // claimed first so nothing new gets cached, then written back without
+    // the node, which writeback takes
+    fn claim_direct(&mut self) -> VfsResult<()> {
+        let (vol, ino) = (self.inode.vol.id, self.inode.ino);
+        {
+            let mut node = self.inode.node.lock();
+            if node.claimed {
+                return Err(VfsError::Busy);
+            }
+            node.claimed = true;
+        }
+
+        if let Err(e) = cache::sync_file(vol, ino) {
+            self.inode.node.lock().claimed = false;
+            return Err(e);
+        }
+        cache::invalidate(vol, ino);
+        self.claimed = true;
+        self.dirty = false;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> VfsResult<()> {
+        if self.dirty {
+            cache::sync_file(self.inode.vol.id, self.inode.ino)?;
+        }
+        self.dirty = false;
+        Ok(())
+    }
+
+    // the directory entry is written as soon as it changes, so after the
+    // data only the device cache is left
+    fn sync(&mut self) -> VfsResult<()> {
+        self.flush()?;
+        self.inode
+            .vol
+            .inner
+            .lock()
+            .device
+            .flush()
+            .map_err(|_| VfsError::IoError)
+    }
+}
+
+impl<D: BlockDevice + 'static> Drop for FatFileHandle<D> {
+    fn drop(&mut self) {
+        if self.claimed {
+            self.inode.node.lock().claimed = false;
+        }
+        let _ = self.flush();
+    }
+}
+
+pub struct Fat32Fs<D: BlockDevice + 'static> {
+    vol: Arc<FatVolume<D>>,
+    root: Arc<FatInode<D>>,
+}
+
+impl<D: BlockDevice + 'static> Fat32Fs<D> {
+    pub fn new(device: D) -> Result<Self, &'static str> {
+        let mut sector = [0u8; SECTOR_SIZE];
+        device.read_sector(0, &mut sector)?;
+
+        let bpb = Bpb::parse(&sector).ok_or("invalid FAT32 boot sector")?;
+
+        if bpb.sectors_per_fat == 0 {
+            return Err("not a FAT32 filesystem");
+        }
+
+        let root_cluster = bpb.root_cluster;
+        let id = cache::alloc_fs_id();
+        let inner = Arc::new(Mutex::new(Fat32Inner {
+            device,
+            bpb,
+            free_clusters: AtomicU32::new(FREE_UNKNOWN),
+        }));
+
+        let vol = Arc::new(FatVolume {
+            id,
+            inner,
+            inodes: Mutex::new(BTreeMap::new()),
+            files: Mutex::new(BTreeMap::new()),
+            next_ino: AtomicU64::new(2),
+        });
+        cache::register(id, vol.clone());
+        let root = Arc::new(FatInode {
+            vol: vol.clone(),
+            ino: 1,
+            node: Mutex::new(FatNode {
+                directory: true,
+                parent: 0,
+                entry: None,
+                cluster: root_cluster,
+                size: 0,
+                unlinked: false,
+                claimed: false,
+            }),
+        });
+
+        Ok(Self { vol, root })
+    }
+}
+
+impl<D: BlockDevice + 'static> Drop for Fat32Fs<D> {
+    fn drop(&mut self) {
+        // unmount has normally done this already
+        if let Err(e) = cache::unregister(self.vol.id) {
+            crate::warn!("fat32: pages left unwritten: {:?}", e);
+        }
+        let inner = self.vol.inner.lock();
+        let _ = inner.store_fs_info();
+        let _ = inner.device.flush();
+    }
+}
+
+impl<D: BlockDevice + 'static> Filesystem for Fat32Fs<D> {
+    fn root(&self) -> InodeRef {
+        self.root.clone()
+    }
+
+    fn name(&self) -> &'static str {
+        "fat32"
+    }
+
+    // the FAT and directories bypass the page cache, so they are already on
+    // the device. only FSInfo and the device's write cache are left
+    fn sync(&self) -> VfsResult<()> {
+        cache::sync_fs(self.vol.id)?;
+        let inner = self.vol.inner.lock();
+        inner.store_fs_info().map_err(|_| VfsError::IoError)?;
+        inner.device.flush().map_err(|_| VfsError::IoError)
+    }
+
+    // the pages stay registered, and so does the mount, if they cannot be
+    // written back
+    fn unmount(&self) -> VfsResult<()> {
+        self.sync()?;
+        cache::unregister(self.vol.id)
+    }
+
+    fn statfs(&self) -> VfsResult<FsStats> {
+        let inner = self.vol.inner.lock();
+        let free = inner.free_clusters().map_err(|_| VfsError::IoError)?;
+        Ok(FsStats {
+            fs_type: self.name(),
+            block_size: inner.bpb.bytes_per_cluster(),
+            total_blocks: inner.bpb.total_clusters() as u64,
+            free_blocks: free as u64,
+            max_name_len: NAME_MAX,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECTORS: u32 = 512;
+    const RESERVED: u16 = 32;
+    const FAT_SECTORS: u32 = 4;
+    // one sector per cluster, so boundaries are cheap to reach
+    const CLUSTER: usize = SECTOR_SIZE;
+
+    struct RamDisk(Mutex<Vec<u8>>);
+
+    impl BlockDevice for RamDisk {
+        fn read_sector(&self, lba: u32, buf: &mut [u8; SECTOR_SIZE]) -> Result<(), &'static str> {
+            let start = lba as usize * SECTOR_SIZE;
+            let data = self.0.lock();
+            buf.copy_from_slice(data.get(start..start + SECTOR_SIZE).ok_or("out of range")?);
+            Ok(())
+        }
+
+        fn write_sector(&self, lba: u32, buf: &[u8; SECTOR_SIZE]) -> Result<(), &'static str> {
+            let start = lba as usize * SECTOR_SIZE;
+            let mut data = self.0.lock();
+            data.get_mut(start..start + SECTOR_SIZE)
+                .ok_or("out of range")?
+                .copy_from_slice(buf);
+            Ok(())
+        }
+
+        fn sector_count(&self) -> Option<u32> {
+            Some(SECTORS)
+        }
+    }
+
+    fn put_u32(buf: &mut [u8], offset: usize, value: u32) {
+        buf[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    // an empty volume with the root directory in cluster 2
+    fn mount() -> Fat32Fs<RamDisk> {
+        let mut disk = vec![0u8; SECTORS as usize * SECTOR_SIZE];
+
+        let boot = &mut disk[..SECTOR_SIZE];
+        boot[11..13].copy_from_slice(&(SECTOR_SIZE as u16).to_le_bytes());
+        boot[13] = 1;
+        boot[14..16].copy_from_slice(&RESERVED.to_le_bytes());
+        boot[16] = 2;
+        put_u32(boot, 32, SECTORS);
+        put_u32(boot, 36, FAT_SECTORS);
+        put_u32(boot, 44, 2);
+        boot[48..50].copy_from_slice(&1u16.to_le_bytes());
+        boot[510] = 0x55;
+        boot[511] = 0xAA;
+
+        let data_start = RESERVED as u32 + 2 * FAT_SECTORS;
+        let fs_info = &mut disk[SECTOR_SIZE..2 * SECTOR_SIZE];
+        put_u32(fs_info, 0, FSINFO_LEAD_SIG);
+        put_u32(fs_info, 484, FSINFO_STRUCT_SIG);
+        put_u32(fs_info, FSINFO_FREE_COUNT, SECTORS - data_start - 1);
+        put_u32(fs_info, 492, 3);
+        put_u32(fs_info, 508, 0xAA550000);
+
+        for fat in 0..2 {
+            let start = (RESERVED as u32 + fat * FAT_SECTORS) as usize * SECTOR_SIZE;
+            put_u32(&mut disk, start, 0x0FFFFFF8);
+            put_u32(&mut disk, start + 4, 0x0FFFFFFF);
+            put_u32(&mut disk, start + 8, 0x0FFFFFFF);
+        }
+
+        Fat32Fs::new(RamDisk(Mutex::new(disk))).expect("test volume did not mount")
+    }
+
+    fn create(fs: &Fat32Fs<RamDisk>, contents: &[u8]) -> Box<dyn FileHandle> {
+        let inode = fs.root.create("file", FileType::File).unwrap();
+        let mut handle = inode.open(OpenFlags::O_RDWR).unwrap();
+        assert_eq!(handle.write(contents).unwrap(), contents.len());
+        handle
+    }
+
+    // the file's cluster chain as the FAT and its directory entry have it
+    fn chain(fs: &Fat32Fs<RamDisk>) -> Vec<u32> {
+        let inner = fs.vol.inner.lock();
+        let entry = inner
+            .find_in_directory(inner.bpb.root_cluster, "file")
+            .unwrap()
+            .unwrap();
+
+        let mut clusters = Vec::new();
+        let mut cluster = entry.cluster();
+        while (2..FAT32_EOC).contains(&cluster) {
+            clusters.push(cluster);
+            cluster = inner.get_fat_entry(cluster).unwrap();
+        }
+        clusters
+    }
+
+    // writes the file out and forgets the cached copy, so reads go to disk
+    fn settle(fs: &Fat32Fs<RamDisk>) {
+        cache::sync_fs(fs.vol.id).unwrap();
+        let ino = fs.root.lookup("file").unwrap().id();
+        cache::invalidate(fs.vol.id, ino);
+    }
+
+    fn read_all(handle: &mut Box<dyn FileHandle>) -> Vec<u8> {
+        let mut data = vec![0u8; handle.metadata().unwrap().size];
+        handle.seek(SeekFrom::Start(0)).unwrap();
+        let mut done = 0;
+        while done < data.len() {
+            let n = handle.read(&mut data[done..]).unwrap();
+            assert!(n > 0, "short read");
+            done += n;
+        }
+        data
+    }
+
+    // the clusters on disk still hold the old bytes when the file grows
+    // back over them, and none of those may show through
+    fn check_grow(len: usize) {
+        let fs = mount();
+        let mut handle = create(&fs, &[0xAA; 2 * CLUSTER]);
+        settle(&fs);
+        handle.set_len(100).unwrap();
+        settle(&fs);
+
+        handle.set_len(len).unwrap();
+        assert_eq!(chain(&fs).len(), len.div_ceil(CLUSTER));
+
+        for _ in 0..2 {
+            let data = read_all(&mut handle);
+            assert_eq!(data.len(), len);
+            assert!(data[..100].iter().all(|&b| b == 0xAA));
+            assert!(data[100..].iter().all(|&b| b == 0));
+            settle(&fs);
+        }
+    }
+
+    fn check_shrink(len: usize) {
+        let fs = mount();
+        let mut handle = create(&fs, &[0x55; 4 * CLUSTER]);
+        let before = chain(&fs);
+        let free_before = fs.statfs().unwrap().free_blocks;
+
+        handle.set_len(len).unwrap();
+        assert_eq!(handle.metadata().unwrap().size, len);
+
+        let keep = len.div_ceil(CLUSTER);
+        assert_eq!(chain(&fs), before[..keep]);
+        {
+            let inner = fs.vol.inner.lock();
+            for &cluster in &before[keep..] {
+                assert_eq!(inner.get_fat_entry(cluster).unwrap(), FAT32_FREE);
+            }
+        }
+
+        let free_after = fs.statfs().unwrap().free_blocks;
+        assert_eq!(free_after - free_before, (before.len() - keep) as u64);
+        let counted = fs.vol.inner.lock().count_free_clusters().unwrap();
+        assert_eq!(free_after, counted as u64);
+
+        let data = read_all(&mut handle);
+        assert!(data.iter().all(|&b| b == 0x55));
+    }
+
+    // the size field is 32 bits, so a write ending past it must fail
+    // rather than wrap the size round
+    fn check_too_large(pos: usize) {
+        let fs = mount();
+        let mut handle = create(&fs, &[0x11; CLUSTER]);
+        let before = chain(&fs);
+
+        handle.seek(SeekFrom::Start(pos)).unwrap();
+        assert!(matches!(handle.write(&[0x22; 8]), Err(VfsError::NoSpace)));
+
+        assert_eq!(handle.metadata().unwrap().size, CLUSTER);
+        assert_eq!(chain(&fs), before);
+    }
+
+    #[test_case]
+    fn grow_to_just_under_a_cluster() {
+        check_grow(CLUSTER - 1);
+    }
+
+    #[test_case]
+    fn grow_to_a_cluster_boundary() {
+        check_grow(CLUSTER);
+    }
+
+    #[test_case]
+    fn grow_past_a_cluster_boundary() {
+        check_grow(CLUSTER + 1);
+    }
+
+    #[test_case]
+    fn grow_across_several_clusters() {
+        check_grow(3 * CLUSTER + 7);
+    }
+
+    #[test_case]
+    fn shrink_to_just_past_a_cluster_boundary() {
+        check_shrink(2 * CLUSTER + 1);
+    }
+
+    #[test_case]
+    fn shrink_to_a_cluster_boundary() {
+        check_shrink(2 * CLUSTER);
+    }
+
+    #[test_case]
+    fn shrink_to_just_under_a_cluster_boundary() {
+        check_shrink(2 * CLUSTER - 1);
+    }
+
+    #[test_case]
+    fn shrink_to_nothing() {
+        check_shrink(0);
+    }
+
+    #[test_case]
+    fn write_ending_just_past_4_gib() {
+        check_too_large(u32::MAX as usize - 4);
+    }
+
+    #[test_case]
+    fn write_starting_past_4_gib() {
+        check_too_large(u32::MAX as usize + 1);
+    }
+}
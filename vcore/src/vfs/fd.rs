@@ -1,6 +1,9 @@
-use alloc::boxed::Box;
+use alloc::{boxed::Box, sync::Arc};
 
-use crate::vfs::{DirEntry, FileHandle, VfsError, VfsResult};
+use crate::{
+    mem::shm::ShmObject,
+    vfs::{DirEntry, FileHandle, VfsError, VfsResult},
+};
 
 pub const MAX_FDS: usize = 64;
 
@@ -11,6 +14,7 @@ pub enum FdKind {
         entries: alloc::vec::Vec<DirEntry>,
         position: usize,
     },
+    Shm(Arc<ShmObject>),
     Stdin,
     Stdout,
     Stderr,
@@ -62,10 +66,12 @@ impl FdTable {
         if fd < 3 {
             return Err(VfsError::PermissionDenied);
         }
-        if self.fds[fd].is_none() {
-            return Err(VfsError::InvalidFd);
+        // the descriptor is gone either way, but a failed write back is
+        // still reported
+        match self.fds[fd].take() {
+            Some(FdKind::File(mut handle)) => handle.flush(),
+            Some(_) => Ok(()),
+            None => Err(VfsError::InvalidFd),
         }
-        self.fds[fd] = None;
-        Ok(())
     }
 }
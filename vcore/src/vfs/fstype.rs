@@ -0,0 +1,49 @@
+use alloc::boxed::Box;
+
+use super::{
+    ArchiveFs, BindFs, DevFs, Fat32Fs, FileDevice, Filesystem, LiveFs, MountFlags, ShmFs, TasksFs,
+    TmpFs, memfs,
+};
+
+type Create = fn(Option<&str>, MountFlags) -> Result<Box<dyn Filesystem>, &'static str>;
+
+/// filesystems that can be mounted by name
+static FS_TYPES: &[(&str, Create)] = &[
+    ("fat32", fat32),
+    ("tmpfs", |_, _| Ok(Box::new(TmpFs::new()))),
+    ("devfs", |_, _| Ok(Box::new(DevFs::new()))),
+    ("tasksfs", |_, _| Ok(Box::new(TasksFs::new()))),
+    ("memfs", |_, _| Ok(Box::new(LiveFs::new(memfs::render)))),
+    ("shmfs", |_, _| Ok(Box::new(ShmFs::new()))),
+    ("archivefs", archivefs),
+    ("bind", bind),
+];
+
+fn fat32(source: Option<&str>, flags: MountFlags) -> Result<Box<dyn Filesystem>, &'static str> {
+    let source = source.ok_or("fat32 needs a source device")?;
+    let device = FileDevice::open(source, !flags.contains(MountFlags::READ_ONLY))?;
+    Ok(Box::new(Fat32Fs::new(device)?))
+}
+
+fn archivefs(source: Option<&str>, _: MountFlags) -> Result<Box<dyn Filesystem>, &'static str> {
+    let source = source.ok_or("archivefs needs a source file")?;
+    Ok(Box::new(ArchiveFs::from_file(source)?))
+}
+
+fn bind(source: Option<&str>, _: MountFlags) -> Result<Box<dyn Filesystem>, &'static str> {
+    let source = source.ok_or("bind needs a source directory")?;
+    let fs = BindFs::new(source).map_err(|_| "cannot bind source directory")?;
+    Ok(Box::new(fs))
+}
+
+pub fn create(
+    name: &str,
+    source: Option<&str>,
+    flags: MountFlags,
+) -> Result<Box<dyn Filesystem>, &'static str> {
+    let (_, create) = FS_TYPES
+        .iter()
+        .find(|(n, _)| *n == name)
+        .ok_or("unknown filesystem type")?;
+    create(source, flags)
+}
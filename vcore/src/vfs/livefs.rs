@@ -0,0 +1,110 @@
+use alloc::{boxed::Box, string::String, sync::Arc, vec::Vec};
+
+use super::types::*;
+use crate::cpu::time;
+
+pub struct LiveFs {
+    render: fn() -> String,
+}
+
+impl LiveFs {
+    pub fn new(render: fn() -> String) -> Self {
+        Self { render }
+    }
+}
+
+struct LiveFileHandle {
+    content: Vec<u8>,
+    position: usize,
+}
+
+impl FileHandle for LiveFileHandle {
+    fn read(&mut self, buf: &mut [u8]) -> VfsResult<usize> {
+        let available = self.content.len().saturating_sub(self.position);
+        let to_read = buf.len().min(available);
+        buf[..to_read].copy_from_slice(&self.content[self.position..self.position + to_read]);
+        self.position += to_read;
+        Ok(to_read)
+    }
+
+    fn write(&mut self, _buf: &[u8]) -> VfsResult<usize> {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn seek(&mut self, pos: SeekFrom) -> VfsResult<usize> {
+        let new_pos = match pos {
+            SeekFrom::Start(n) => n as isize,
+            SeekFrom::Current(n) => self.position as isize + n,
+            SeekFrom::End(n) => self.content.len() as isize + n,
+        };
+        if new_pos < 0 {
+            return Err(VfsError::InvalidPath);
+        }
+        self.position = new_pos as usize;
+        Ok(self.position)
+    }
+
+    fn metadata(&self) -> VfsResult<Metadata> {
+        // rendered when read, so never older than now
+        let now = time::realtime_now();
+        Ok(Metadata {
+            file_type: FileType::File,
+            size: self.content.len(),
+            uid: 0,
+            gid: 0,
+            mode: 0o444,
+            atime: now,
+            mtime: now,
+            ctime: now,
+        })
+    }
+}
+
+// the mount point itself is the file
+struct LiveNode {
+    render: fn() -> String,
+}
+
+impl Filesystem for LiveFs {
+    fn root(&self) -> InodeRef {
+        Arc::new(LiveNode {
+            render: self.render,
+        })
+    }
+
+    fn name(&self) -> &'static str {
+        "livefs"
+    }
+
+    fn cache_dentries(&self) -> bool {
+        false
+    }
+}
+
+impl Inode for LiveNode {
+    fn id(&self) -> u64 {
+        0
+    }
+
+    fn metadata(&self) -> VfsResult<Metadata> {
+        // rendered when read, so never older than now
+        let now = time::realtime_now();
+        Ok(Metadata {
+            file_type: FileType::File,
+            size: 0,
+            uid: 0,
+            gid: 0,
+            mode: 0o444,
+            atime: now,
+            mtime: now,
+            ctime: now,
+        })
+    }
+
+    fn open(self: Arc<Self>, _flags: OpenFlags) -> VfsResult<Box<dyn FileHandle>> {
+        Ok(Box::new(LiveFileHandle {
+            content: (self.render)().into_bytes(),
+            position: 0,
+        }))
+    }
+}
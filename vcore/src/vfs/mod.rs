@@ -1,21 +1,40 @@
 use alloc::{
     boxed::Box,
+    format,
     string::{String, ToString},
+    sync::Arc,
     vec::Vec,
 };
 use spin::{Lazy, Mutex};
 
+use crate::{
+    cpu::time,
+    sched::{self, task::Credentials},
+};
+
+pub mod archivefs;
+pub mod bindfs;
 pub mod block;
+pub mod cache;
+pub mod dcache;
 pub mod fat32;
 pub mod fd;
+pub mod fstype;
+pub mod livefs;
 pub mod memfs;
+pub mod root;
+pub mod shmfs;
 pub mod tasksfs;
 pub mod tmpfs;
 pub mod types;
 
+#[allow(unused_imports)]
+pub use archivefs::ArchiveFs;
+#[allow(unused_imports)]
+pub use bindfs::BindFs;
 #[allow(unused_imports)]
 pub use block::{
-    AtaDisk, BlockDevice, DevFs, Partition, PartitionInfo, PartitionType, SECTOR_SIZE,
+    AtaDisk, BlockDevice, DevFs, FileDevice, Partition, PartitionInfo, PartitionType, SECTOR_SIZE,
     find_partition, first_partition, parse_partitions,
 };
 #[allow(unused_imports)]
@@ -23,7 +42,9 @@ pub use fat32::Fat32Fs;
 #[allow(unused_imports)]
 pub use fd::{FdKind, FdTable};
 #[allow(unused_imports)]
-pub use memfs::MemFs;
+pub use livefs::LiveFs;
+#[allow(unused_imports)]
+pub use shmfs::ShmFs;
 #[allow(unused_imports)]
 pub use tasksfs::TasksFs;
 #[allow(unused_imports)]
@@ -32,126 +53,407 @@ pub use tmpfs::TmpFs;
 pub use types::*;
 
 struct Mount {
+    id: u64,
     path: String,
-    fs: Box<dyn Filesystem>,
+    source: String,
+    flags: MountFlags,
+    fs: Arc<dyn Filesystem>,
+    // (parent mount, directory it sits in, name); the directory is held so
+    // its inode id stays valid. none for the root
+    parent: Option<(u64, InodeRef, String)>,
+    // cloned into every handle opened through the mount
+    users: Arc<()>,
+}
+
+impl Mount {
+    fn is_on(&self, mount: u64, dir: &InodeRef, name: &str) -> bool {
+        self.parent
+            .as_ref()
+            .is_some_and(|(m, d, n)| *m == mount && d.id() == dir.id() && n == name)
+    }
 }
 
 pub struct Vfs {
-    mounts: Vec<Mount>,
+    mounts: Arc<Vec<Arc<Mount>>>,
+    next_id: u64,
 }
 
 impl Vfs {
     pub fn new() -> Self {
-        Self { mounts: Vec::new() }
+        Self {
+            mounts: Arc::new(Vec::new()),
+            next_id: 1,
+        }
     }
 
-    pub fn mount(&mut self, path: &str, fs: Box<dyn Filesystem>) -> VfsResult<()> {
-        let path = normalize_path(path);
-
-        if self.mounts.iter().any(|m| m.path == path) {
+    fn add(&mut self, mut mount: Mount) -> VfsResult<()> {
+        let taken = match &mount.parent {
+            None => self.mounts.iter().any(|m| m.parent.is_none()),
+            Some((mount, dir, name)) => {
+                if !self.mounts.iter().any(|m| m.id == *mount) {
+                    return Err(VfsError::NotFound);
+                }
+                self.mounts.iter().any(|m| m.is_on(*mount, dir, name))
+            }
+        };
+        if taken {
             return Err(VfsError::AlreadyExists);
         }
 
-        self.mounts.push(Mount { path, fs });
+        mount.id = self.next_id;
+        self.next_id += 1;
+        Arc::make_mut(&mut self.mounts).push(Arc::new(mount));
+        Ok(())
+    }
 
-        self.mounts.sort_by(|a, b| b.path.len().cmp(&a.path.len()));
+    // hands back the mount and where it was, so it can be put back
+    fn remove(&mut self, id: u64) -> VfsResult<(usize, Arc<Mount>)> {
+        let mounts = Arc::make_mut(&mut self.mounts);
+        let idx = mounts
+            .iter()
+            .position(|m| m.id == id)
+            .ok_or(VfsError::NotFound)?;
 
-        Ok(())
+        let covered = mounts
+            .iter()
+            .any(|m| m.parent.as_ref().is_some_and(|(p, _, _)| *p == id));
+        if covered || Arc::strong_count(&mounts[idx].users) > 1 {
+            return Err(VfsError::Busy);
+        }
+
+        Ok((idx, mounts.remove(idx)))
+    }
+
+    fn restore(&mut self, idx: usize, mount: Arc<Mount>) {
+        let mounts = Arc::make_mut(&mut self.mounts);
+        mounts.insert(idx.min(mounts.len()), mount);
     }
+}
+
+const MAX_SYMLINKS: usize = 40;
 
-    pub fn unmount(&mut self, path: &str) -> VfsResult<()> {
-        let path = normalize_path(path);
-        let idx = self
-            .mounts
+const MAY_READ: u32 = 4;
+const MAY_WRITE: u32 = 2;
+const MAY_EXEC: u32 = 1;
+
+// picks the owner, group or other bits that apply
+fn permits(meta: &Metadata, creds: &Credentials, access: u32) -> bool {
+    let bits = if meta.uid == creds.uid {
+        meta.mode >> 6
+    } else if creds.in_group(meta.gid) {
+        meta.mode >> 3
+    } else {
+        meta.mode
+    };
+    bits & access == access
+}
+
+fn open_access(flags: OpenFlags) -> u32 {
+    let mut access = 0;
+    if flags.is_readable() {
+        access |= MAY_READ;
+    }
+    if flags.is_writable() || flags.contains(OpenFlags::O_TRUNC) {
+        access |= MAY_WRITE;
+    }
+    access
+}
+
+struct Step {
+    mount: Arc<Mount>,
+    inode: InodeRef,
+    name: String,
+}
+
+// a path walked one component at a time from the root, crossing into
+// mounts on the way down and back out of them on `..`
+struct Walk {
+    mounts: Arc<Vec<Arc<Mount>>>,
+    stack: Vec<Step>,
+    links: usize,
+    creds: Credentials,
+}
+
+impl Walk {
+    fn new() -> VfsResult<Self> {
+        // the table lock is only held for the snapshot, never across
+        // filesystem calls
+        let mounts = VFS.lock().mounts.clone();
+        let root = mounts
             .iter()
-            .position(|m| m.path == path)
+            .find(|m| m.parent.is_none())
+            .cloned()
             .ok_or(VfsError::NotFound)?;
-        self.mounts.remove(idx);
-        Ok(())
+
+        Ok(Self {
+            stack: alloc::vec![Step {
+                inode: root.fs.root(),
+                mount: root,
+                name: String::new(),
+            }],
+            mounts,
+            links: 0,
+            creds: sched::credentials(),
+        })
     }
 
-    fn resolve(&self, path: &str) -> VfsResult<(&dyn Filesystem, String)> {
-        let path = normalize_path(path);
+    fn top(&self) -> &Step {
+        self.stack.last().expect("walk lost its root")
+    }
 
-        for mount in &self.mounts {
-            if mount.path == "/" {
-                return Ok((mount.fs.as_ref(), path.clone()));
-            }
-            if path == mount.path {
-                return Ok((mount.fs.as_ref(), "/".to_string()));
+    fn mounted_on(&self, name: &str) -> Option<Arc<Mount>> {
+        let top = self.top();
+        self.mounts
+            .iter()
+            .find(|m| m.is_on(top.mount.id, &top.inode, name))
+            .cloned()
+    }
+
+    fn is_mount_root(&self) -> bool {
+        match self.stack.len() {
+            1 => true,
+            n => self.stack[n - 2].mount.id != self.stack[n - 1].mount.id,
+        }
+    }
+
+    fn writable(&self) -> VfsResult<()> {
+        if self.top().mount.flags.contains(MountFlags::READ_ONLY) {
+            Err(VfsError::ReadOnly)
+        } else {
+            Ok(())
+        }
+    }
+
+    // root passes every check
+    fn check(&self, inode: &InodeRef, access: u32) -> VfsResult<()> {
+        if self.creds.is_root() || permits(&inode.metadata()?, &self.creds, access) {
+            Ok(())
+        } else {
+            Err(VfsError::PermissionDenied)
+        }
+    }
+
+    // names in the directory on top can be looked up
+    fn search(&self) -> VfsResult<()> {
+        self.check(&self.top().inode, MAY_EXEC)
+    }
+
+    // entries can be added to or removed from the directory on top
+    fn modifiable(&self) -> VfsResult<()> {
+        self.writable()?;
+        self.check(&self.top().inode, MAY_WRITE | MAY_EXEC)
+    }
+
+    fn lookup(&self, name: &str) -> VfsResult<InodeRef> {
+        let top = self.top();
+        if top.mount.fs.cache_dentries() {
+            dcache::lookup(top.mount.id, &top.inode, name)
+        } else {
+            top.inode.lookup(name)
+        }
+    }
+
+    fn create(&self, name: &str, file_type: FileType) -> VfsResult<InodeRef> {
+        let inode = self.top().inode.create(name, file_type)?;
+        self.remember(name, &inode);
+        Ok(inode)
+    }
+
+    fn remember(&self, name: &str, inode: &InodeRef) {
+        let top = self.top();
+        if top.mount.fs.cache_dentries() {
+            dcache::add(top.mount.id, &top.inode, name, inode.clone());
+        }
+    }
+
+    fn forget(&self, inode: &InodeRef) {
+        let top = self.top();
+        if top.mount.fs.cache_dentries() {
+            dcache::remove(top.mount.id, &top.inode, inode);
+        }
+    }
+
+    fn step(&mut self, name: &str) -> VfsResult<()> {
+        match name {
+            "" | "." => {}
+            ".." => {
+                // `..` at the root stays there
+                if self.stack.len() > 1 {
+                    self.stack.pop();
+                }
             }
-            if path.starts_with(&mount.path) {
-                let rest = &path[mount.path.len()..];
-                let relative = if rest.is_empty() || rest == "/" {
-                    "/".to_string()
-                } else if rest.starts_with('/') {
-                    rest.to_string()
-                } else {
-                    continue;
+            name => {
+                self.search()?;
+                let (mount, inode) = match self.mounted_on(name) {
+                    Some(mount) => {
+                        let root = mount.fs.root();
+                        (mount, root)
+                    }
+                    None => {
+                        let inode = self.lookup(name)?;
+                        if inode.metadata()?.file_type == FileType::Symlink {
+                            return self.follow(&inode);
+                        }
+                        (self.top().mount.clone(), inode)
+                    }
                 };
-                return Ok((mount.fs.as_ref(), relative));
+                self.stack.push(Step {
+                    mount,
+                    inode,
+                    name: String::from(name),
+                });
             }
         }
+        Ok(())
+    }
+
+    // carries on from the link's directory, or from the root for an
+    // absolute target
+    fn follow(&mut self, link: &InodeRef) -> VfsResult<()> {
+        self.links += 1;
+        if self.links > MAX_SYMLINKS {
+            return Err(VfsError::Loop);
+        }
+
+        let target = link.readlink()?;
+        if target.is_empty() {
+            return Err(VfsError::NotFound);
+        }
+        if target.starts_with('/') {
+            self.stack.truncate(1);
+        }
+        self.walk(&target)
+    }
+
+    fn walk(&mut self, path: &str) -> VfsResult<()> {
+        for part in path.split('/') {
+            self.step(part)?;
+        }
+        Ok(())
+    }
+
+    fn path(&self) -> String {
+        if self.stack.len() == 1 {
+            return "/".to_string();
+        }
+        let mut path = String::new();
+        for step in &self.stack[1..] {
+            path.push('/');
+            path.push_str(&step.name);
+        }
+        path
+    }
+}
+
+fn walk_path(path: &str) -> VfsResult<Walk> {
+    let mut walk = Walk::new()?;
+    walk.walk(path)?;
+    Ok(walk)
+}
+
+// walks everything but the last component and hands that back, unless it is
+// `.`, `..` or the root, which do not name an entry and get walked as well
+fn walk_parent(path: &str) -> VfsResult<(Walk, Option<&str>)> {
+    let trimmed = path.trim_end_matches('/');
+    let (parent, name) = trimmed.rsplit_once('/').unwrap_or(("", trimmed));
+
+    let mut walk = walk_path(parent)?;
+    match name {
+        "" | "." | ".." => {
+            walk.step(name)?;
+            Ok((walk, None))
+        }
+        name => Ok((walk, Some(name))),
+    }
+}
+
+// the inode a path leads to and the filesystem it is on, for filesystems
+// built over another one
+fn inode_at(path: &str) -> VfsResult<(InodeRef, Arc<dyn Filesystem>)> {
+    let walk = walk_path(path)?;
+    let top = walk.top();
+    Ok((top.inode.clone(), top.mount.fs.clone()))
+}
+
+// keeps the mount it was opened through busy until it is closed
+struct MountedHandle {
+    handle: Box<dyn FileHandle>,
+    _users: Arc<()>,
+}
+
+impl FileHandle for MountedHandle {
+    fn read(&mut self, buf: &mut [u8]) -> VfsResult<usize> {
+        self.handle.read(buf)
+    }
 
-        Err(VfsError::NotFound)
+    fn write(&mut self, buf: &[u8]) -> VfsResult<usize> {
+        self.handle.write(buf)
     }
 
-    pub fn open(&self, path: &str, flags: OpenFlags) -> VfsResult<Box<dyn FileHandle>> {
-        let (fs, relative) = self.resolve(path)?;
-        fs.open(&relative, flags)
+    fn seek(&mut self, pos: SeekFrom) -> VfsResult<usize> {
+        self.handle.seek(pos)
     }
 
-    pub fn mkdir(&self, path: &str) -> VfsResult<()> {
-        let (fs, relative) = self.resolve(path)?;
-        fs.mkdir(&relative)
+    fn metadata(&self) -> VfsResult<Metadata> {
+        self.handle.metadata()
     }
 
-    pub fn remove(&self, path: &str) -> VfsResult<()> {
-        let (fs, relative) = self.resolve(path)?;
-        fs.remove(&relative)
+    fn read_page_direct(&mut self, index: u64, buf: &mut [u8]) -> VfsResult<()> {
+        self.handle.read_page_direct(index, buf)
     }
 
-    pub fn rmdir(&self, path: &str) -> VfsResult<()> {
-        let (fs, relative) = self.resolve(path)?;
-        fs.rmdir(&relative)
+    fn write_page_direct(&mut self, index: u64, buf: &[u8]) -> VfsResult<()> {
+        self.handle.write_page_direct(index, buf)
     }
 
-    pub fn readdir(&self, path: &str) -> VfsResult<Vec<DirEntry>> {
-        let (fs, relative) = self.resolve(path)?;
-        fs.readdir(&relative)
+    fn claim_direct(&mut self) -> VfsResult<()> {
+        self.handle.claim_direct()
     }
 
-    pub fn metadata(&self, path: &str) -> VfsResult<Metadata> {
-        let (fs, relative) = self.resolve(path)?;
-        fs.metadata(&relative)
+    fn set_len(&mut self, len: usize) -> VfsResult<()> {
+        self.handle.set_len(len)
     }
 
-    pub fn exists(&self, path: &str) -> bool {
-        self.metadata(path).is_ok()
+    fn flush(&mut self) -> VfsResult<()> {
+        self.handle.flush()
+    }
+
+    fn sync(&mut self) -> VfsResult<()> {
+        self.handle.sync()
     }
 }
 
-fn normalize_path(path: &str) -> String {
-    let mut parts: Vec<&str> = Vec::new();
+fn remove_at(path: &str, directory: bool) -> VfsResult<()> {
+    let (walk, name) = walk_parent(path)?;
+    let name = name.ok_or(VfsError::InvalidPath)?;
+    if walk.mounted_on(name).is_some() {
+        return Err(VfsError::Busy);
+    }
+    walk.modifiable()?;
 
-    for part in path.split('/') {
-        match part {
-            "" | "." => continue,
-            ".." => {
-                parts.pop();
-            }
-            p => parts.push(p),
-        }
+    let inode = walk.lookup(name)?;
+    let parent = &walk.top().inode;
+    if directory {
+        parent.rmdir(name)?;
+    } else {
+        parent.unlink(name)?;
+    }
+    walk.forget(&inode);
+    Ok(())
+}
+
+// drops empty and `.` components; `..` is left for the walk, which knows
+// where the mounts are
+fn normalize_path(path: &str) -> String {
+    let mut result = String::new();
+    for part in path.split('/').filter(|p| !p.is_empty() && *p != ".") {
+        result.push('/');
+        result.push_str(part);
     }
 
-    if parts.is_empty() {
+    if result.is_empty() {
         "/".to_string()
     } else {
-        let mut result = String::new();
-        for part in parts {
-            result.push('/');
-            result.push_str(part);
-        }
         result
     }
 }
@@ -172,37 +474,400 @@ pub fn resolve_path(path: &str, cwd: &str) -> String {
 static VFS: Lazy<Mutex<Vfs>> = Lazy::new(|| Mutex::new(Vfs::new()));
 
 pub fn mount(path: &str, fs: Box<dyn Filesystem>) -> VfsResult<()> {
-    VFS.lock().mount(path, fs)
+    mount_from(path, fs, "none", MountFlags::empty())
+}
+
+pub fn mount_from(
+    path: &str,
+    fs: Box<dyn Filesystem>,
+    source: &str,
+    flags: MountFlags,
+) -> VfsResult<()> {
+    let has_root = VFS.lock().mounts.iter().any(|m| m.parent.is_none());
+
+    let (path, parent) = if has_root {
+        let (walk, name) = walk_parent(path)?;
+        let name = name.ok_or(VfsError::AlreadyExists)?;
+        let top = walk.top();
+        if top.inode.metadata()?.file_type != FileType::Directory {
+            return Err(VfsError::NotADirectory);
+        }
+
+        let mut target = walk.path();
+        if !target.ends_with('/') {
+            target.push('/');
+        }
+        target.push_str(name);
+
+        // the mount point itself need not exist in the parent filesystem
+        (
+            target,
+            Some((top.mount.id, top.inode.clone(), name.to_string())),
+        )
+    } else if normalize_path(path) == "/" {
+        ("/".to_string(), None)
+    } else {
+        return Err(VfsError::NotFound);
+    };
+
+    VFS.lock().add(Mount {
+        id: 0,
+        path,
+        source: source.to_string(),
+        flags,
+        fs: Arc::from(fs),
+        parent,
+        users: Arc::new(()),
+    })
 }
 
 pub fn unmount(path: &str) -> VfsResult<()> {
-    VFS.lock().unmount(path)
+    let walk = walk_path(path)?;
+    if !walk.is_mount_root() {
+        return Err(VfsError::InvalidPath);
+    }
+
+    let id = walk.top().mount.id;
+    drop(walk);
+    let (idx, mount) = VFS.lock().remove(id)?;
+    // a filesystem that cannot write back what it holds stays mounted
+    if let Err(e) = mount.fs.unmount() {
+        VFS.lock().restore(idx, mount);
+        return Err(e);
+    }
+    dcache::remove_mount(id);
+    Ok(())
 }
 
 pub fn open(path: &str, flags: OpenFlags) -> VfsResult<Box<dyn FileHandle>> {
-    VFS.lock().open(path, flags)
+    let (mut walk, name) = walk_parent(path)?;
+    let writes = flags.is_writable()
+        || flags.contains(OpenFlags::O_CREAT)
+        || flags.contains(OpenFlags::O_TRUNC);
+
+    // a file created here may be opened whatever its mode says
+    let (mount, inode, created) = match name {
+        Some(name) if walk.mounted_on(name).is_none() => {
+            walk.search()?;
+            if writes {
+                walk.writable()?;
+            }
+            let (inode, created) = match walk.lookup(name) {
+                Ok(_) if flags.contains(OpenFlags::O_CREAT | OpenFlags::O_EXCL) => {
+                    return Err(VfsError::AlreadyExists);
+                }
+                Ok(inode) => (inode, false),
+                Err(VfsError::NotFound) if flags.contains(OpenFlags::O_CREAT) => {
+                    walk.modifiable()?;
+                    match walk.create(name, FileType::File) {
+                        // lost a race with another creator
+                        Err(VfsError::AlreadyExists) if !flags.contains(OpenFlags::O_EXCL) => {
+                            (walk.lookup(name)?, false)
+                        }
+                        result => (result?, true),
+                    }
+                }
+                Err(e) => return Err(e),
+            };
+
+            if inode.metadata()?.file_type != FileType::Symlink {
+                (walk.top().mount.clone(), inode, created)
+            } else if flags.contains(OpenFlags::O_NOFOLLOW) {
+                return Err(VfsError::Loop);
+            } else {
+                walk.follow(&inode)?;
+                if writes {
+                    walk.writable()?;
+                }
+                let top = walk.top();
+                (top.mount.clone(), top.inode.clone(), false)
+            }
+        }
+        name => {
+            if flags.contains(OpenFlags::O_CREAT | OpenFlags::O_EXCL) {
+                return Err(VfsError::AlreadyExists);
+            }
+            if let Some(name) = name {
+                walk.step(name)?;
+            }
+            if writes {
+                walk.writable()?;
+            }
+            let top = walk.top();
+            (top.mount.clone(), top.inode.clone(), false)
+        }
+    };
+    if !created {
+        walk.check(&inode, open_access(flags))?;
+    }
+
+    Ok(Box::new(MountedHandle {
+        handle: inode.open(flags)?,
+        _users: mount.users.clone(),
+    }))
 }
 
 pub fn mkdir(path: &str) -> VfsResult<()> {
-    VFS.lock().mkdir(path)
+    let (walk, name) = walk_parent(path)?;
+    let name = name.ok_or(VfsError::AlreadyExists)?;
+    if walk.mounted_on(name).is_some() {
+        return Err(VfsError::AlreadyExists);
+    }
+    walk.modifiable()?;
+    walk.create(name, FileType::Directory).map(|_| ())
+}
+
+pub fn symlink(target: &str, path: &str) -> VfsResult<()> {
+    let (walk, name) = walk_parent(path)?;
+    let name = name.ok_or(VfsError::AlreadyExists)?;
+    if walk.mounted_on(name).is_some() {
+        return Err(VfsError::AlreadyExists);
+    }
+    walk.modifiable()?;
+
+    let inode = walk.top().inode.symlink(name, target)?;
+    walk.remember(name, &inode);
+    Ok(())
+}
+
+pub fn link(from: &str, to: &str) -> VfsResult<()> {
+    let (from_walk, from_name) = walk_parent(from)?;
+    let (to_walk, to_name) = walk_parent(to)?;
+    // directories cannot be linked, and a mount root is one
+    let from_name = from_name.ok_or(VfsError::PermissionDenied)?;
+    if from_walk.mounted_on(from_name).is_some() {
+        return Err(VfsError::PermissionDenied);
+    }
+    let to_name = to_name.ok_or(VfsError::AlreadyExists)?;
+    if to_walk.mounted_on(to_name).is_some() {
+        return Err(VfsError::AlreadyExists);
+    }
+
+    if from_walk.top().mount.id != to_walk.top().mount.id {
+        return Err(VfsError::CrossDevice);
+    }
+    from_walk.search()?;
+    to_walk.modifiable()?;
+
+    let inode = from_walk.lookup(from_name)?;
+    to_walk.top().inode.link(to_name, &inode)?;
+    to_walk.remember(to_name, &inode);
+    Ok(())
+}
+
+pub fn readlink(path: &str) -> VfsResult<String> {
+    let (walk, name) = walk_parent(path)?;
+    let name = name.ok_or(VfsError::InvalidPath)?;
+    if walk.mounted_on(name).is_some() {
+        return Err(VfsError::InvalidPath);
+    }
+    walk.search()?;
+    walk.lookup(name)?.readlink()
 }
 
 pub fn remove(path: &str) -> VfsResult<()> {
-    VFS.lock().remove(path)
+    remove_at(path, false)
 }
 
 pub fn rmdir(path: &str) -> VfsResult<()> {
-    VFS.lock().rmdir(path)
+    remove_at(path, true)
+}
+
+// one rename at a time, so the tree cannot change shape between checking
+// that a directory is not moved below itself and moving it
+static RENAME: Mutex<()> = Mutex::new(());
+
+pub fn rename(from: &str, to: &str) -> VfsResult<()> {
+    let _renaming = RENAME.lock();
+
+    let (from_walk, from_name) = walk_parent(from)?;
+    let (to_walk, to_name) = walk_parent(to)?;
+    let (Some(from_name), Some(to_name)) = (from_name, to_name) else {
+        return Err(VfsError::InvalidPath);
+    };
+    if from_walk.mounted_on(from_name).is_some() || to_walk.mounted_on(to_name).is_some() {
+        return Err(VfsError::Busy);
+    }
+
+    let (src, dst) = (from_walk.top(), to_walk.top());
+    if src.mount.id != dst.mount.id {
+        return Err(VfsError::CrossDevice);
+    }
+    from_walk.modifiable()?;
+    to_walk.modifiable()?;
+
+    let inode = from_walk.lookup(from_name)?;
+    if to_walk
+        .stack
+        .iter()
+        .any(|step| step.mount.id == src.mount.id && step.inode.id() == inode.id())
+    {
+        return Err(VfsError::InvalidPath);
+    }
+    let replaced = to_walk.lookup(to_name).ok();
+
+    src.inode.rename(from_name, &dst.inode, to_name)?;
+
+    from_walk.forget(&inode);
+    if let Some(replaced) = replaced {
+        to_walk.forget(&replaced);
+    }
+    Ok(())
 }
 
 pub fn readdir(path: &str) -> VfsResult<Vec<DirEntry>> {
-    VFS.lock().readdir(path)
+    let walk = walk_path(path)?;
+    let top = walk.top();
+    walk.check(&top.inode, MAY_READ)?;
+    let mut entries = top.inode.readdir()?;
+
+    // mounts hide whatever the directory has under their name
+    for mount in walk.mounts.iter() {
+        let Some((parent, dir, name)) = &mount.parent else {
+            continue;
+        };
+        if *parent != top.mount.id || dir.id() != top.inode.id() {
+            continue;
+        }
+
+        let file_type = mount
+            .fs
+            .root()
+            .metadata()
+            .map_or(FileType::Directory, |m| m.file_type);
+        match entries.iter_mut().find(|e| e.name == *name) {
+            Some(entry) => entry.file_type = file_type,
+            None => entries.push(DirEntry {
+                name: name.clone(),
+                file_type,
+            }),
+        }
+    }
+
+    Ok(entries)
+}
+
+pub fn truncate(path: &str, len: usize) -> VfsResult<()> {
+    open(path, OpenFlags::O_WRONLY)?.set_len(len)
+}
+
+/// the capacity of whatever filesystem `path` is on
+pub fn statfs(path: &str) -> VfsResult<FsStats> {
+    walk_path(path)?.top().mount.fs.statfs()
 }
 
 pub fn metadata(path: &str) -> VfsResult<Metadata> {
-    VFS.lock().metadata(path)
+    walk_path(path)?.top().inode.metadata()
+}
+
+/// like `metadata`, but a symlink at the end of the path is not followed
+pub fn symlink_metadata(path: &str) -> VfsResult<Metadata> {
+    let (walk, name) = walk_parent(path)?;
+    match name {
+        Some(name) if walk.mounted_on(name).is_none() => {
+            walk.search()?;
+            walk.lookup(name)?.metadata()
+        }
+        _ => metadata(path),
+    }
+}
+
+/// setting both times to now only takes write permission, anything else
+/// is for the owner
+pub fn utimens(path: &str, atime: SetTime, mtime: SetTime) -> VfsResult<()> {
+    let walk = walk_path(path)?;
+    walk.writable()?;
+
+    let inode = &walk.top().inode;
+    let meta = inode.metadata()?;
+    let to_now = !matches!(atime, SetTime::At(_)) && !matches!(mtime, SetTime::At(_));
+    let creds = &walk.creds;
+    if !creds.is_root() && meta.uid != creds.uid && !(to_now && permits(&meta, creds, MAY_WRITE)) {
+        return Err(VfsError::PermissionDenied);
+    }
+
+    let now = time::realtime_now();
+    let resolve = |t| match t {
+        SetTime::Now => Some(now),
+        SetTime::Omit => None,
+        SetTime::At(ns) => Some(ns),
+    };
+    inode.set_times(resolve(atime), resolve(mtime))
+}
+
+pub fn chmod(path: &str, mode: u32) -> VfsResult<()> {
+    let walk = walk_path(path)?;
+    walk.writable()?;
+
+    let inode = &walk.top().inode;
+    if !walk.creds.is_root() && inode.metadata()?.uid != walk.creds.uid {
+        return Err(VfsError::PermissionDenied);
+    }
+    inode.chmod(mode & 0o7777)
+}
+
+/// `u32::MAX` keeps the owner or group. only root gives a file away; its
+/// owner may move it between their own groups
+pub fn chown(path: &str, uid: u32, gid: u32) -> VfsResult<()> {
+    let walk = walk_path(path)?;
+    walk.writable()?;
+
+    let inode = &walk.top().inode;
+    let meta = inode.metadata()?;
+    let uid = if uid == u32::MAX { meta.uid } else { uid };
+    let gid = if gid == u32::MAX { meta.gid } else { gid };
+
+    let creds = &walk.creds;
+    if !creds.is_root()
+        && (meta.uid != creds.uid || uid != meta.uid || (gid != meta.gid && !creds.in_group(gid)))
+    {
+        return Err(VfsError::PermissionDenied);
+    }
+    inode.chown(uid, gid)
+}
+
+pub fn render_mounts() -> String {
+    let mounts = VFS.lock().mounts.clone();
+
+    let mut out = String::new();
+    for mount in mounts.iter() {
+        let mode = if mount.flags.contains(MountFlags::READ_ONLY) {
+            "ro"
+        } else {
+            "rw"
+        };
+        out.push_str(&format!(
+            "{} {} {} {}\n",
+            mount.source,
+            mount.path,
+            mount.fs.name(),
+            mode
+        ));
+    }
+    out
+}
+
+/// the path with every `..` resolved against the mount tree
+pub fn canonicalize(path: &str) -> VfsResult<String> {
+    Ok(walk_path(path)?.path())
+}
+
+/// syncs every mount, newest first so a filesystem living in a file reaches
+/// the filesystem holding that file before it is synced in turn. keeps going
+/// past failures and reports the first
+pub fn sync() -> VfsResult<()> {
+    let mounts = VFS.lock().mounts.clone();
+
+    let mut result = Ok(());
+    for mount in mounts.iter().rev() {
+        if let Err(e) = mount.fs.sync() {
+            crate::warn!("sync {}: {:?}", mount.path, e);
+            result = result.and(Err(e));
+        }
+    }
+    result.and(cache::sync_all())
 }
 
 pub fn exists(path: &str) -> bool {
-    VFS.lock().exists(path)
+    metadata(path).is_ok()
 }
@@ -0,0 +1,103 @@
+use alloc::{boxed::Box, format, string::String, vec::Vec};
+
+use super::{
+    Fat32Fs, MountFlags, TmpFs,
+    block::{AtaDisk, Partition, PartitionInfo, format_guid, parse_guid, parse_partitions},
+    fat32,
+};
+
+enum RootSpec<'a> {
+    Default,
+    Tmpfs,
+    Device(&'a str),
+    PartUuid([u8; 16]),
+    PartLabel(&'a str),
+    Label(&'a str),
+}
+
+impl<'a> RootSpec<'a> {
+    fn parse(spec: Option<&'a str>) -> Result<Self, &'static str> {
+        let Some(spec) = spec else {
+            return Ok(RootSpec::Default);
+        };
+
+        if spec == "tmpfs" {
+            Ok(RootSpec::Tmpfs)
+        } else if let Some(guid) = spec.strip_prefix("PARTUUID=") {
+            parse_guid(guid)
+                .map(RootSpec::PartUuid)
+                .ok_or("malformed partition GUID")
+        } else if let Some(name) = spec.strip_prefix("PARTLABEL=") {
+            Ok(RootSpec::PartLabel(name))
+        } else if let Some(label) = spec.strip_prefix("LABEL=") {
+            Ok(RootSpec::Label(label))
+        } else if let Some(device) = spec.strip_prefix("/dev/") {
+            Ok(RootSpec::Device(device))
+        } else {
+            Err("unrecognised root= value")
+        }
+    }
+}
+
+pub fn partition_name(part: &PartitionInfo) -> String {
+    format!("ata0p{}", part.index + 1)
+}
+
+fn label_of(part: &PartitionInfo) -> Option<String> {
+    let disk = AtaDisk::new().ok()?;
+    fat32::volume_label(&Partition::new(disk, part.start_lba, part.sector_count))
+}
+
+pub fn describe(part: &PartitionInfo) -> String {
+    let mut desc = partition_name(part);
+    if let Some(guid) = &part.guid {
+        desc.push_str(&format!(" PARTUUID={}", format_guid(guid)));
+    }
+    if let Some(name) = &part.name {
+        desc.push_str(&format!(" PARTLABEL={}", name));
+    }
+    if let Some(label) = label_of(part) {
+        desc.push_str(&format!(" LABEL={}", label));
+    }
+    desc
+}
+
+pub fn mount_root(spec: Option<&str>) -> Result<String, &'static str> {
+    let spec = RootSpec::parse(spec)?;
+
+    if let RootSpec::Tmpfs = spec {
+        super::mount("/", Box::new(TmpFs::new())).map_err(|_| "failed to mount tmpfs")?;
+        return Ok(String::from("tmpfs"));
+    }
+
+    let disk = AtaDisk::new()?;
+
+    if let RootSpec::Device("ata0") = spec {
+        let fat = Fat32Fs::new(disk)?;
+        super::mount_from("/", Box::new(fat), "/dev/ata0", MountFlags::empty())
+            .map_err(|_| "failed to mount root")?;
+        return Ok(String::from("ata0"));
+    }
+
+    let partitions: Vec<PartitionInfo> = parse_partitions(&disk)?;
+
+    let part = match spec {
+        RootSpec::Default => partitions.first(),
+        RootSpec::Device(name) => partitions.iter().find(|p| partition_name(p) == name),
+        RootSpec::PartUuid(guid) => partitions.iter().find(|p| p.guid == Some(guid)),
+        RootSpec::PartLabel(name) => partitions.iter().find(|p| p.name.as_deref() == Some(name)),
+        RootSpec::Label(label) => partitions
+            .iter()
+            .find(|p| label_of(p).as_deref() == Some(label)),
+        RootSpec::Tmpfs => unreachable!(),
+    }
+    .ok_or("no matching partition")?;
+
+    let partition = Partition::new(disk, part.start_lba, part.sector_count);
+    let fat = Fat32Fs::new(partition)?;
+    let source = format!("/dev/{}", partition_name(part));
+    super::mount_from("/", Box::new(fat), &source, MountFlags::empty())
+        .map_err(|_| "failed to mount root")?;
+
+    Ok(describe(part))
+}
@@ -0,0 +1,166 @@
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
+
+use super::types::*;
+use crate::mem::shm::{self, ShmObject};
+
+pub struct ShmFs;
+
+impl ShmFs {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+struct ShmFileHandle {
+    object: Arc<ShmObject>,
+    position: usize,
+    flags: OpenFlags,
+}
+
+impl FileHandle for ShmFileHandle {
+    fn read(&mut self, buf: &mut [u8]) -> VfsResult<usize> {
+        if !self.flags.is_readable() {
+            return Err(VfsError::PermissionDenied);
+        }
+
+        let read = self.object.read(self.position, buf);
+        self.position += read;
+        Ok(read)
+    }
+
+    fn write(&mut self, buf: &[u8]) -> VfsResult<usize> {
+        if !self.flags.is_writable() {
+            return Err(VfsError::PermissionDenied);
+        }
+
+        let end = self.position + buf.len();
+        if end > self.object.size() {
+            self.object.resize(end).map_err(|_| VfsError::NoSpace)?;
+        }
+
+        let written = self.object.write(self.position, buf);
+        self.position += written;
+        Ok(written)
+    }
+
+    fn seek(&mut self, pos: SeekFrom) -> VfsResult<usize> {
+        let new_pos = match pos {
+            SeekFrom::Start(n) => n as isize,
+            SeekFrom::Current(n) => self.position as isize + n,
+            SeekFrom::End(n) => self.object.size() as isize + n,
+        };
+        if new_pos < 0 {
+            return Err(VfsError::InvalidPath);
+        }
+        self.position = new_pos as usize;
+        Ok(self.position)
+    }
+
+    fn metadata(&self) -> VfsResult<Metadata> {
+        Ok(Metadata {
+            file_type: FileType::File,
+            size: self.object.size(),
+            uid: 0,
+            gid: 0,
+            mode: 0o666,
+            atime: 0,
+            mtime: 0,
+            ctime: 0,
+        })
+    }
+}
+
+struct ShmDir;
+
+struct ShmNode {
+    object: Arc<ShmObject>,
+}
+
+impl Filesystem for ShmFs {
+    fn root(&self) -> InodeRef {
+        Arc::new(ShmDir)
+    }
+
+    fn name(&self) -> &'static str {
+        "shmfs"
+    }
+
+    // objects are also created and unlinked through the shm syscalls
+    fn cache_dentries(&self) -> bool {
+        false
+    }
+}
+
+impl Inode for ShmDir {
+    fn id(&self) -> u64 {
+        0
+    }
+
+    fn metadata(&self) -> VfsResult<Metadata> {
+        Ok(Metadata {
+            file_type: FileType::Directory,
+            size: 0,
+            uid: 0,
+            gid: 0,
+            mode: 0o777,
+            atime: 0,
+            mtime: 0,
+            ctime: 0,
+        })
+    }
+
+    fn lookup(&self, name: &str) -> VfsResult<InodeRef> {
+        let object = shm::lookup(name).ok_or(VfsError::NotFound)?;
+        Ok(Arc::new(ShmNode { object }))
+    }
+
+    fn create(&self, name: &str, file_type: FileType) -> VfsResult<InodeRef> {
+        if file_type != FileType::File {
+            return Err(VfsError::PermissionDenied);
+        }
+
+        let object = shm::open(name, true, true).map_err(|_| VfsError::AlreadyExists)?;
+        Ok(Arc::new(ShmNode { object }))
+    }
+
+    fn unlink(&self, name: &str) -> VfsResult<()> {
+        shm::unlink(name).map_err(|_| VfsError::NotFound)
+    }
+
+    fn readdir(&self) -> VfsResult<Vec<DirEntry>> {
+        Ok(shm::names()
+            .into_iter()
+            .map(|name| DirEntry {
+                name,
+                file_type: FileType::File,
+            })
+            .collect())
+    }
+}
+
+impl Inode for ShmNode {
+    fn id(&self) -> u64 {
+        Arc::as_ptr(&self.object) as u64
+    }
+
+    fn metadata(&self) -> VfsResult<Metadata> {
+        Ok(Metadata {
+            file_type: FileType::File,
+            size: self.object.size(),
+            uid: 0,
+            gid: 0,
+            mode: 0o666,
+            atime: 0,
+            mtime: 0,
+            ctime: 0,
+        })
+    }
+
+    fn open(self: Arc<Self>, flags: OpenFlags) -> VfsResult<Box<dyn FileHandle>> {
+        Ok(Box::new(ShmFileHandle {
+            object: self.object.clone(),
+            position: 0,
+            flags,
+        }))
+    }
+}
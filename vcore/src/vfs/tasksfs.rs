@@ -1,6 +1,7 @@
-use alloc::{boxed::Box, format, string::ToString, vec, vec::Vec};
+use alloc::{boxed::Box, format, string::ToString, sync::Arc, vec::Vec};
 
 use super::types::*;
+use crate::cpu::time;
 use crate::sched::{
     SCHEDULER,
     task::{TaskMode, TaskState},
@@ -12,10 +13,6 @@ impl TasksFs {
     pub fn new() -> Self {
         Self
     }
-
-    fn path_parts(path: &str) -> Vec<&str> {
-        path.split('/').filter(|s| !s.is_empty()).collect()
-    }
 }
 
 struct TaskFileHandle {
@@ -50,164 +47,182 @@ impl FileHandle for TaskFileHandle {
     }
 
     fn metadata(&self) -> VfsResult<Metadata> {
+        // rendered when read, so never older than now
+        let now = time::realtime_now();
         Ok(Metadata {
             file_type: FileType::File,
             size: self.content.len(),
+            uid: 0,
+            gid: 0,
+            mode: 0o444,
+            atime: now,
+            mtime: now,
+            ctime: now,
         })
     }
 }
 
-impl Filesystem for TasksFs {
-    fn open(&self, path: &str, _flags: OpenFlags) -> VfsResult<Box<dyn FileHandle>> {
-        let parts = Self::path_parts(path);
+const TASK_FILES: &[&str] = &["status", "name"];
 
-        if parts.len() != 2 {
-            return Err(VfsError::IsADirectory);
-        }
+enum TaskNode {
+    Root,
+    Task(u64),
+    File(u64, &'static str),
+}
 
-        let pid: u64 = parts[0].parse().map_err(|_| VfsError::NotFound)?;
-        let file = parts[1];
-
-        let guard = SCHEDULER.lock();
-        let sched = guard.as_ref().ok_or(VfsError::IoError)?;
-
-        let task = sched
-            .tasks
-            .iter()
-            .find(|t| t.id == pid)
-            .ok_or(VfsError::NotFound)?;
-
-        let content = match file {
-            "status" => {
-                let state = match task.state {
-                    TaskState::Ready => "ready",
-                    TaskState::Running => "running",
-                    TaskState::Sleeping => "sleeping",
-                    TaskState::Dead => "dead",
-                };
-                let mode = match task.mode {
-                    TaskMode::Kernel => "kernel",
-                    TaskMode::User => "user",
-                };
-                format!("pid: {}\nstate: {}\nmode: {}", task.id, state, mode).into_bytes()
-            }
-            "name" => format!("{}", task.name).into_bytes(),
-            _ => return Err(VfsError::NotFound),
-        };
+fn task_exists(pid: u64) -> VfsResult<()> {
+    let guard = SCHEDULER.lock();
+    let sched = guard.as_ref().ok_or(VfsError::IoError)?;
+    sched
+        .tasks
+        .iter()
+        .any(|t| t.id == pid)
+        .then_some(())
+        .ok_or(VfsError::NotFound)
+}
 
-        Ok(Box::new(TaskFileHandle {
-            content,
-            position: 0,
-        }))
+fn render(pid: u64, file: &str) -> VfsResult<Vec<u8>> {
+    let guard = SCHEDULER.lock();
+    let sched = guard.as_ref().ok_or(VfsError::IoError)?;
+
+    let task = sched
+        .tasks
+        .iter()
+        .find(|t| t.id == pid)
+        .ok_or(VfsError::NotFound)?;
+
+    Ok(match file {
+        "status" => {
+            let state = match task.state {
+                TaskState::Ready => "ready",
+                TaskState::Running => "running",
+                TaskState::Sleeping => "sleeping",
+                TaskState::Dead => "dead",
+            };
+            let mode = match task.mode {
+                TaskMode::Kernel => "kernel",
+                TaskMode::User => "user",
+            };
+            let ppid = task.parent.map_or("-".to_string(), |p| p.to_string());
+            format!(
+                "pid: {}\nppid: {}\nstate: {}\nmode: {}\nresident: {} pages",
+                task.id,
+                ppid,
+                state,
+                mode,
+                task.resident_pages()
+            )
+            .into_bytes()
+        }
+        _ => format!("{}", task.name).into_bytes(),
+    })
+}
+
+impl Filesystem for TasksFs {
+    fn root(&self) -> InodeRef {
+        Arc::new(TaskNode::Root)
     }
 
-    fn mkdir(&self, _path: &str) -> VfsResult<()> {
-        Err(VfsError::PermissionDenied)
+    fn name(&self) -> &'static str {
+        "tasksfs"
     }
 
-    fn remove(&self, _path: &str) -> VfsResult<()> {
-        Err(VfsError::PermissionDenied)
+    fn cache_dentries(&self) -> bool {
+        false
     }
+}
 
-    fn rmdir(&self, _path: &str) -> VfsResult<()> {
-        Err(VfsError::PermissionDenied)
+impl Inode for TaskNode {
+    fn id(&self) -> u64 {
+        match self {
+            TaskNode::Root => 0,
+            TaskNode::Task(pid) => (pid + 1) << 8,
+            TaskNode::File(pid, file) => {
+                ((pid + 1) << 8) + 1 + TASK_FILES.iter().position(|f| f == file).unwrap_or(0) as u64
+            }
+        }
     }
 
-    fn readdir(&self, path: &str) -> VfsResult<Vec<DirEntry>> {
-        let parts = Self::path_parts(path);
+    fn metadata(&self) -> VfsResult<Metadata> {
+        // rendered when read, so never older than now
+        let now = time::realtime_now();
+        let file_type = match self {
+            TaskNode::File(..) => FileType::File,
+            _ => FileType::Directory,
+        };
+        let mode = match file_type {
+            FileType::File => 0o444,
+            _ => 0o555,
+        };
+        Ok(Metadata {
+            file_type,
+            size: 0,
+            uid: 0,
+            gid: 0,
+            mode,
+            atime: now,
+            mtime: now,
+            ctime: now,
+        })
+    }
 
-        match parts.len() {
-            0 => {
-                let guard = SCHEDULER.lock();
-                let sched = guard.as_ref().ok_or(VfsError::IoError)?;
+    fn open(self: Arc<Self>, _flags: OpenFlags) -> VfsResult<Box<dyn FileHandle>> {
+        let TaskNode::File(pid, file) = *self else {
+            return Err(VfsError::IsADirectory);
+        };
 
-                let entries = sched
-                    .tasks
-                    .iter()
-                    .filter(|t| t.state != TaskState::Dead)
-                    .map(|t| DirEntry {
-                        name: format!("{}", t.id),
-                        file_type: FileType::Directory,
-                    })
-                    .collect();
+        Ok(Box::new(TaskFileHandle {
+            content: render(pid, file)?,
+            position: 0,
+        }))
+    }
 
-                Ok(entries)
+    fn lookup(&self, name: &str) -> VfsResult<InodeRef> {
+        match self {
+            TaskNode::Root => {
+                let pid: u64 = name.parse().map_err(|_| VfsError::NotFound)?;
+                task_exists(pid)?;
+                Ok(Arc::new(TaskNode::Task(pid)))
             }
-            1 => {
-                let pid: u64 = parts[0].parse().map_err(|_| VfsError::NotFound)?;
-
-                let guard = SCHEDULER.lock();
-                let sched = guard.as_ref().ok_or(VfsError::IoError)?;
-
-                let _task = sched
-                    .tasks
+            TaskNode::Task(pid) => {
+                let file = TASK_FILES
                     .iter()
-                    .find(|t| t.id == pid)
+                    .find(|f| **f == name)
                     .ok_or(VfsError::NotFound)?;
-
-                Ok(vec![
-                    DirEntry {
-                        name: "status".to_string(),
-                        file_type: FileType::File,
-                    },
-                    DirEntry {
-                        name: "name".to_string(),
-                        file_type: FileType::File,
-                    },
-                ])
+                task_exists(*pid)?;
+                Ok(Arc::new(TaskNode::File(*pid, file)))
             }
-            _ => Err(VfsError::NotADirectory),
+            TaskNode::File(..) => Err(VfsError::NotADirectory),
         }
     }
 
-    fn metadata(&self, path: &str) -> VfsResult<Metadata> {
-        let parts = Self::path_parts(path);
-
-        match parts.len() {
-            0 => Ok(Metadata {
-                file_type: FileType::Directory,
-                size: 0,
-            }),
-            1 => {
-                let pid: u64 = parts[0].parse().map_err(|_| VfsError::NotFound)?;
-
+    fn readdir(&self) -> VfsResult<Vec<DirEntry>> {
+        match self {
+            TaskNode::Root => {
                 let guard = SCHEDULER.lock();
                 let sched = guard.as_ref().ok_or(VfsError::IoError)?;
 
-                let _task = sched
+                Ok(sched
                     .tasks
                     .iter()
-                    .find(|t| t.id == pid)
-                    .ok_or(VfsError::NotFound)?;
-
-                Ok(Metadata {
-                    file_type: FileType::Directory,
-                    size: 0,
-                })
+                    .filter(|t| t.state != TaskState::Dead)
+                    .map(|t| DirEntry {
+                        name: format!("{}", t.id),
+                        file_type: FileType::Directory,
+                    })
+                    .collect())
             }
-            2 => {
-                let pid: u64 = parts[0].parse().map_err(|_| VfsError::NotFound)?;
-                let file = parts[1];
-
-                let guard = SCHEDULER.lock();
-                let sched = guard.as_ref().ok_or(VfsError::IoError)?;
-
-                let _task = sched
-                    .tasks
+            TaskNode::Task(pid) => {
+                task_exists(*pid)?;
+                Ok(TASK_FILES
                     .iter()
-                    .find(|t| t.id == pid)
-                    .ok_or(VfsError::NotFound)?;
-
-                if file == "status" || file == "name" {
-                    Ok(Metadata {
+                    .map(|name| DirEntry {
+                        name: name.to_string(),
                         file_type: FileType::File,
-                        size: 0,
                     })
-                } else {
-                    Err(VfsError::NotFound)
-                }
+                    .collect())
             }
-            _ => Err(VfsError::NotFound),
+            TaskNode::File(..) => Err(VfsError::NotADirectory),
         }
     }
 }
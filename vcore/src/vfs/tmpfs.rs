@@ -2,257 +2,385 @@ use alloc::{
     boxed::Box,
     collections::BTreeMap,
     string::{String, ToString},
+    sync::Arc,
     vec::Vec,
 };
+use core::{
+    any::Any,
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+};
 use spin::Mutex;
 
+use super::cache;
 use super::types::*;
+use crate::{
+    cpu::time,
+    mem::{PAGE_SIZE, pmm},
+    sched,
+};
+
+struct TmpInfo {
+    id: u64,
+    next_ino: AtomicU64,
+}
+
+enum TmpKind {
+    File { size: AtomicUsize },
+    // each directory has its own lock
+    Directory(Mutex<BTreeMap<String, Arc<TmpNode>>>),
+    Symlink(String),
+}
 
-#[derive(Clone)]
-enum Node {
-    File(Vec<u8>),
-    Directory(BTreeMap<String, Node>),
+struct Attrs {
+    uid: u32,
+    gid: u32,
+    mode: u32,
+    atime: u64,
+    mtime: u64,
+    ctime: u64,
 }
 
-impl Node {
-    fn as_dir(&self) -> Option<&BTreeMap<String, Node>> {
-        match self {
-            Node::Directory(entries) => Some(entries),
-            _ => None,
+struct TmpNode {
+    fs: Arc<TmpInfo>,
+    ino: u64,
+    kind: TmpKind,
+    attrs: Mutex<Attrs>,
+}
+
+impl Drop for TmpNode {
+    fn drop(&mut self) {
+        if let TmpKind::File { .. } = self.kind {
+            cache::invalidate(self.fs.id, self.ino);
         }
     }
+}
 
-    fn as_dir_mut(&mut self) -> Option<&mut BTreeMap<String, Node>> {
-        match self {
-            Node::Directory(entries) => Some(entries),
-            _ => None,
-        }
+impl TmpNode {
+    fn new(fs: Arc<TmpInfo>, file_type: FileType) -> Arc<Self> {
+        let kind = match file_type {
+            FileType::Directory => TmpKind::Directory(Mutex::new(BTreeMap::new())),
+            _ => TmpKind::File {
+                size: AtomicUsize::new(0),
+            },
+        };
+        Self::with_kind(fs, kind)
     }
 
-    fn as_file(&self) -> Option<&Vec<u8>> {
-        match self {
-            Node::File(data) => Some(data),
-            _ => None,
-        }
+    // owned by whoever creates it
+    fn with_kind(fs: Arc<TmpInfo>, kind: TmpKind) -> Arc<Self> {
+        let ino = fs.next_ino.fetch_add(1, Ordering::Relaxed);
+        let mode = match kind {
+            TmpKind::File { .. } => 0o644,
+            TmpKind::Directory(_) => 0o755,
+            TmpKind::Symlink(_) => 0o777,
+        };
+        let creds = sched::credentials();
+        let now = time::realtime_now();
+        Arc::new(Self {
+            fs,
+            ino,
+            kind,
+            attrs: Mutex::new(Attrs {
+                uid: creds.uid,
+                gid: creds.gid,
+                mode,
+                atime: now,
+                mtime: now,
+                ctime: now,
+            }),
+        })
+    }
+
+    // contents changed
+    fn modified(&self) {
+        let now = time::realtime_now();
+        let mut attrs = self.attrs.lock();
+        (attrs.mtime, attrs.ctime) = (now, now);
+    }
+
+    // only the inode changed
+    fn changed(&self) {
+        self.attrs.lock().ctime = time::realtime_now();
     }
 
-    fn as_file_mut(&mut self) -> Option<&mut Vec<u8>> {
-        match self {
-            Node::File(data) => Some(data),
-            _ => None,
+    fn entries(&self) -> VfsResult<&Mutex<BTreeMap<String, Arc<TmpNode>>>> {
+        match &self.kind {
+            TmpKind::Directory(entries) => Ok(entries),
+            _ => Err(VfsError::NotADirectory),
         }
     }
 
     fn file_type(&self) -> FileType {
-        match self {
-            Node::File(_) => FileType::File,
-            Node::Directory(_) => FileType::Directory,
+        match self.kind {
+            TmpKind::File { .. } => FileType::File,
+            TmpKind::Directory(_) => FileType::Directory,
+            TmpKind::Symlink(_) => FileType::Symlink,
         }
     }
 
     fn size(&self) -> usize {
-        match self {
-            Node::File(data) => data.len(),
-            Node::Directory(entries) => entries.len(),
+        match &self.kind {
+            TmpKind::File { size } => size.load(Ordering::Relaxed),
+            TmpKind::Directory(entries) => entries.lock().len(),
+            TmpKind::Symlink(target) => target.len(),
+        }
+    }
+
+    fn metadata(&self) -> Metadata {
+        let attrs = self.attrs.lock();
+        Metadata {
+            file_type: self.file_type(),
+            size: self.size(),
+            uid: attrs.uid,
+            gid: attrs.gid,
+            mode: attrs.mode,
+            atime: attrs.atime,
+            mtime: attrs.mtime,
+            ctime: attrs.ctime,
         }
     }
 }
 
 pub struct TmpFs {
-    root: Mutex<Node>,
+    root: Arc<TmpNode>,
 }
 
 impl TmpFs {
     pub fn new() -> Self {
+        let info = Arc::new(TmpInfo {
+            id: cache::alloc_fs_id(),
+            next_ino: AtomicU64::new(1),
+        });
         Self {
-            root: Mutex::new(Node::Directory(BTreeMap::new())),
+            root: TmpNode::new(info, FileType::Directory),
         }
     }
+}
 
-    fn path_parts(path: &str) -> Vec<&str> {
-        path.split('/').filter(|s| !s.is_empty()).collect()
+impl Filesystem for TmpFs {
+    fn root(&self) -> InodeRef {
+        self.root.clone()
     }
 
-    fn navigate_to_parent<'a>(
-        root: &'a mut Node,
-        parts: &[&'a str],
-    ) -> VfsResult<(&'a mut Node, &'a str)> {
-        if parts.is_empty() {
-            return Err(VfsError::InvalidPath);
-        }
+    fn name(&self) -> &'static str {
+        "tmpfs"
+    }
 
-        let (parent_parts, name) = parts.split_at(parts.len() - 1);
-        let mut current = root;
+    // file data sits in the page cache, so the only limit is physical memory
+    fn statfs(&self) -> VfsResult<FsStats> {
+        Ok(FsStats {
+            fs_type: self.name(),
+            block_size: PAGE_SIZE,
+            total_blocks: pmm::total_pages() as u64,
+            free_blocks: pmm::free_pages() as u64,
+            max_name_len: NAME_MAX,
+        })
+    }
+}
 
-        for part in parent_parts {
-            current = current
-                .as_dir_mut()
-                .ok_or(VfsError::NotADirectory)?
-                .get_mut(*part)
-                .ok_or(VfsError::NotFound)?;
-        }
+impl Inode for TmpNode {
+    fn id(&self) -> u64 {
+        self.ino
+    }
 
-        Ok((current, name[0]))
+    fn metadata(&self) -> VfsResult<Metadata> {
+        Ok(TmpNode::metadata(self))
     }
 
-    fn navigate<'a>(root: &'a Node, parts: &[&str]) -> VfsResult<&'a Node> {
-        let mut current = root;
+    fn open(self: Arc<Self>, flags: OpenFlags) -> VfsResult<Box<dyn FileHandle>> {
+        let size = match &self.kind {
+            TmpKind::File { size } => size,
+            TmpKind::Directory(_) => return Err(VfsError::IsADirectory),
+            TmpKind::Symlink(_) => return Err(VfsError::Loop),
+        };
 
-        for part in parts {
-            current = current
-                .as_dir()
-                .ok_or(VfsError::NotADirectory)?
-                .get(*part)
-                .ok_or(VfsError::NotFound)?;
+        if flags.contains(OpenFlags::O_TRUNC) {
+            size.store(0, Ordering::Relaxed);
+            cache::truncate(self.fs.id, self.ino, 0);
+            self.modified();
         }
+        let position = if flags.contains(OpenFlags::O_APPEND) {
+            size.load(Ordering::Relaxed)
+        } else {
+            0
+        };
 
-        Ok(current)
+        Ok(Box::new(TmpFileHandle {
+            file: self,
+            position,
+            flags,
+        }))
     }
 
-    fn navigate_mut<'a>(root: &'a mut Node, parts: &[&str]) -> VfsResult<&'a mut Node> {
-        let mut current = root;
+    fn lookup(&self, name: &str) -> VfsResult<InodeRef> {
+        let node = self.entries()?.lock().get(name).cloned();
+        node.map(|n| n as InodeRef).ok_or(VfsError::NotFound)
+    }
 
-        for part in parts {
-            current = current
-                .as_dir_mut()
-                .ok_or(VfsError::NotADirectory)?
-                .get_mut(*part)
-                .ok_or(VfsError::NotFound)?;
+    fn create(&self, name: &str, file_type: FileType) -> VfsResult<InodeRef> {
+        let mut entries = self.entries()?.lock();
+        if entries.contains_key(name) {
+            return Err(VfsError::AlreadyExists);
         }
 
-        Ok(current)
+        let node = TmpNode::new(self.fs.clone(), file_type);
+        entries.insert(name.to_string(), node.clone());
+        self.modified();
+        Ok(node)
     }
-}
-
-impl Filesystem for TmpFs {
-    fn open(&self, path: &str, flags: OpenFlags) -> VfsResult<Box<dyn FileHandle>> {
-        let mut root = self.root.lock();
-        let parts = Self::path_parts(path);
 
-        if parts.is_empty() {
-            return Err(VfsError::IsADirectory);
+    fn symlink(&self, name: &str, target: &str) -> VfsResult<InodeRef> {
+        let mut entries = self.entries()?.lock();
+        if entries.contains_key(name) {
+            return Err(VfsError::AlreadyExists);
         }
 
-        let node_result = Self::navigate_mut(&mut root, &parts);
-
-        match node_result {
-            Ok(node) => {
-                if node.file_type() == FileType::Directory {
-                    return Err(VfsError::IsADirectory);
-                }
-
-                let data = node.as_file().unwrap().clone();
-                let data = if flags.contains(OpenFlags::O_TRUNC) {
-                    Vec::new()
-                } else {
-                    data
-                };
-                let position = if flags.contains(OpenFlags::O_APPEND) {
-                    data.len()
-                } else {
-                    0
-                };
-
-                Ok(Box::new(TmpFileHandle {
-                    data,
-                    position,
-                    path: path.to_string(),
-                    flags,
-                    fs: self as *const TmpFs,
-                }))
-            }
-            Err(VfsError::NotFound) if flags.contains(OpenFlags::O_CREAT) => {
-                let (parent, name) = Self::navigate_to_parent(&mut root, &parts)?;
-                let dir = parent.as_dir_mut().ok_or(VfsError::NotADirectory)?;
-                dir.insert(name.to_string(), Node::File(Vec::new()));
-
-                Ok(Box::new(TmpFileHandle {
-                    data: Vec::new(),
-                    position: 0,
-                    path: path.to_string(),
-                    flags,
-                    fs: self as *const TmpFs,
-                }))
-            }
-            Err(e) => Err(e),
-        }
+        let node = TmpNode::with_kind(self.fs.clone(), TmpKind::Symlink(target.to_string()));
+        entries.insert(name.to_string(), node.clone());
+        self.modified();
+        Ok(node)
     }
 
-    fn mkdir(&self, path: &str) -> VfsResult<()> {
-        let mut root = self.root.lock();
-        let parts = Self::path_parts(path);
+    fn link(&self, name: &str, inode: &InodeRef) -> VfsResult<()> {
+        let node = (inode.clone() as Arc<dyn Any + Send + Sync>)
+            .downcast::<TmpNode>()
+            .ok()
+            .filter(|node| Arc::ptr_eq(&node.fs, &self.fs))
+            .ok_or(VfsError::CrossDevice)?;
+        if node.file_type() == FileType::Directory {
+            return Err(VfsError::PermissionDenied);
+        }
 
-        if parts.is_empty() {
+        let mut entries = self.entries()?.lock();
+        if entries.contains_key(name) {
             return Err(VfsError::AlreadyExists);
         }
+        node.changed();
+        entries.insert(name.to_string(), node);
+        self.modified();
+        Ok(())
+    }
 
-        let (parent, name) = Self::navigate_to_parent(&mut root, &parts)?;
-        let dir = parent.as_dir_mut().ok_or(VfsError::NotADirectory)?;
-
-        if dir.contains_key(name) {
-            return Err(VfsError::AlreadyExists);
+    fn readlink(&self) -> VfsResult<String> {
+        match &self.kind {
+            TmpKind::Symlink(target) => Ok(target.clone()),
+            _ => Err(VfsError::InvalidPath),
         }
+    }
 
-        dir.insert(name.to_string(), Node::Directory(BTreeMap::new()));
+    fn chmod(&self, mode: u32) -> VfsResult<()> {
+        self.attrs.lock().mode = mode;
+        self.changed();
         Ok(())
     }
 
-    fn remove(&self, path: &str) -> VfsResult<()> {
-        let mut root = self.root.lock();
-        let parts = Self::path_parts(path);
-
-        if parts.is_empty() {
-            return Err(VfsError::PermissionDenied);
-        }
+    fn chown(&self, uid: u32, gid: u32) -> VfsResult<()> {
+        let mut attrs = self.attrs.lock();
+        (attrs.uid, attrs.gid) = (uid, gid);
+        drop(attrs);
+        self.changed();
+        Ok(())
+    }
 
-        let (parent, name) = Self::navigate_to_parent(&mut root, &parts)?;
-        let dir = parent.as_dir_mut().ok_or(VfsError::NotADirectory)?;
+    fn set_times(&self, atime: Option<u64>, mtime: Option<u64>) -> VfsResult<()> {
+        let mut attrs = self.attrs.lock();
+        attrs.atime = atime.unwrap_or(attrs.atime);
+        attrs.mtime = mtime.unwrap_or(attrs.mtime);
+        attrs.ctime = time::realtime_now();
+        Ok(())
+    }
 
-        match dir.get(name) {
-            Some(Node::File(_)) => {
-                dir.remove(name);
+    fn unlink(&self, name: &str) -> VfsResult<()> {
+        let mut entries = self.entries()?.lock();
+        match entries.get(name).map(|node| node.file_type()) {
+            Some(FileType::Directory) => Err(VfsError::IsADirectory),
+            Some(_) => {
+                entries.remove(name);
+                self.modified();
                 Ok(())
             }
-            Some(Node::Directory(_)) => Err(VfsError::IsADirectory),
             None => Err(VfsError::NotFound),
         }
     }
 
-    fn rmdir(&self, path: &str) -> VfsResult<()> {
-        let mut root = self.root.lock();
-        let parts = Self::path_parts(path);
+    fn rmdir(&self, name: &str) -> VfsResult<()> {
+        let mut entries = self.entries()?.lock();
+        let node = entries.get(name).cloned().ok_or(VfsError::NotFound)?;
 
-        if parts.is_empty() {
-            return Err(VfsError::PermissionDenied);
+        // held until the entry is gone so nothing is created in between
+        let children = node.entries()?.lock();
+        if !children.is_empty() {
+            return Err(VfsError::NotEmpty);
         }
 
-        let (parent, name) = Self::navigate_to_parent(&mut root, &parts)?;
-        let dir = parent.as_dir_mut().ok_or(VfsError::NotADirectory)?;
+        entries.remove(name);
+        self.modified();
+        Ok(())
+    }
 
-        match dir.get(name) {
-            Some(Node::Directory(entries)) if entries.is_empty() => {
-                dir.remove(name);
-                Ok(())
-            }
-            Some(Node::Directory(_)) => Err(VfsError::NotEmpty),
-            Some(Node::File(_)) => Err(VfsError::NotADirectory),
-            None => Err(VfsError::NotFound),
+    fn rename(&self, name: &str, new_dir: &InodeRef, new_name: &str) -> VfsResult<()> {
+        let target = (new_dir.as_ref() as &dyn Any)
+            .downcast_ref::<TmpNode>()
+            .filter(|dir| Arc::ptr_eq(&dir.fs, &self.fs))
+            .ok_or(VfsError::CrossDevice)?;
+        let (from, to) = (self.entries()?, target.entries()?);
+        let same_dir = self.ino == target.ino;
+
+        // rmdir locks a parent and then its child, and either directory here
+        // may be the other's ancestor, so the second lock is only tried
+        loop {
+            let mut src = from.lock();
+            let mut dst = if same_dir {
+                None
+            } else if let Some(dst) = to.try_lock() {
+                Some(dst)
+            } else {
+                continue;
+            };
+
+            let node = src.get(name).cloned().ok_or(VfsError::NotFound)?;
+            let old = dst.as_deref().unwrap_or(&src).get(new_name).cloned();
+
+            // a replaced directory stays locked until it is gone, so nothing
+            // is created in it in between
+            let _emptied = match &old {
+                None => None,
+                Some(old) if Arc::ptr_eq(old, &node) => return Ok(()),
+                Some(old) => match (node.file_type(), old.file_type()) {
+                    (FileType::Directory, FileType::Directory) => {
+                        // the directory we already hold is not empty either
+                        if old.ino == self.ino {
+                            return Err(VfsError::NotEmpty);
+                        }
+                        let Some(children) = old.entries()?.try_lock() else {
+                            continue;
+                        };
+                        if !children.is_empty() {
+                            return Err(VfsError::NotEmpty);
+                        }
+                        Some(children)
+                    }
+                    (FileType::Directory, _) => return Err(VfsError::NotADirectory),
+                    (_, FileType::Directory) => return Err(VfsError::IsADirectory),
+                    _ => None,
+                },
+            };
+
+            src.remove(name);
+            node.changed();
+            dst.as_deref_mut()
+                .unwrap_or(&mut src)
+                .insert(new_name.to_string(), node);
+            self.modified();
+            target.modified();
+            return Ok(());
         }
     }
 
-    fn readdir(&self, path: &str) -> VfsResult<Vec<DirEntry>> {
-        let root = self.root.lock();
-        let parts = Self::path_parts(path);
-
-        let node = if parts.is_empty() {
-            &*root
-        } else {
-            Self::navigate(&root, &parts)?
-        };
-
-        let dir = node.as_dir().ok_or(VfsError::NotADirectory)?;
-
-        Ok(dir
+    fn readdir(&self) -> VfsResult<Vec<DirEntry>> {
+        Ok(self
+            .entries()?
+            .lock()
             .iter()
             .map(|(name, node)| DirEntry {
                 name: name.clone(),
@@ -260,34 +388,19 @@ impl Filesystem for TmpFs {
             })
             .collect())
     }
-
-    fn metadata(&self, path: &str) -> VfsResult<Metadata> {
-        let root = self.root.lock();
-        let parts = Self::path_parts(path);
-
-        let node = if parts.is_empty() {
-            &*root
-        } else {
-            Self::navigate(&root, &parts)?
-        };
-
-        Ok(Metadata {
-            file_type: node.file_type(),
-            size: node.size(),
-        })
-    }
 }
 
 struct TmpFileHandle {
-    data: Vec<u8>,
+    file: Arc<TmpNode>,
     position: usize,
-    path: String,
     flags: OpenFlags,
-    fs: *const TmpFs,
 }
 
-unsafe impl Send for TmpFileHandle {}
-unsafe impl Sync for TmpFileHandle {}
+impl TmpFileHandle {
+    fn size(&self) -> usize {
+        self.file.size()
+    }
+}
 
 impl FileHandle for TmpFileHandle {
     fn read(&mut self, buf: &mut [u8]) -> VfsResult<usize> {
@@ -295,13 +408,18 @@ impl FileHandle for TmpFileHandle {
             return Err(VfsError::PermissionDenied);
         }
 
-        let available = self.data.len().saturating_sub(self.position);
+        let available = self.size().saturating_sub(self.position);
         let to_read = buf.len().min(available);
 
-        buf[..to_read].copy_from_slice(&self.data[self.position..self.position + to_read]);
-        self.position += to_read;
+        let read = cache::read(
+            self.file.fs.id,
+            self.file.ino,
+            self.position,
+            &mut buf[..to_read],
+        )?;
+        self.position += read;
 
-        Ok(to_read)
+        Ok(read)
     }
 
     fn write(&mut self, buf: &[u8]) -> VfsResult<usize> {
@@ -310,37 +428,24 @@ impl FileHandle for TmpFileHandle {
         }
 
         if self.flags.contains(OpenFlags::O_APPEND) {
-            self.position = self.data.len();
+            self.position = self.size();
         }
 
-        let end_position = self.position + buf.len();
-
-        if end_position > self.data.len() {
-            self.data.resize(end_position, 0);
+        let written = cache::write(self.file.fs.id, self.file.ino, self.position, buf)?;
+        self.position += written;
+        if let TmpKind::File { size } = &self.file.kind {
+            size.fetch_max(self.position, Ordering::Relaxed);
         }
+        self.file.modified();
 
-        self.data[self.position..end_position].copy_from_slice(buf);
-        self.position = end_position;
-
-        unsafe {
-            let fs = &*self.fs;
-            let mut root = fs.root.lock();
-            let parts = TmpFs::path_parts(&self.path);
-            if let Ok(node) = TmpFs::navigate_mut(&mut root, &parts) {
-                if let Some(file_data) = node.as_file_mut() {
-                    *file_data = self.data.clone();
-                }
-            }
-        }
-
-        Ok(buf.len())
+        Ok(written)
     }
 
     fn seek(&mut self, pos: SeekFrom) -> VfsResult<usize> {
         let new_pos = match pos {
             SeekFrom::Start(n) => n as isize,
             SeekFrom::Current(n) => self.position as isize + n,
-            SeekFrom::End(n) => self.data.len() as isize + n,
+            SeekFrom::End(n) => self.size() as isize + n,
         };
 
         if new_pos < 0 {
@@ -352,9 +457,22 @@ impl FileHandle for TmpFileHandle {
     }
 
     fn metadata(&self) -> VfsResult<Metadata> {
-        Ok(Metadata {
-            file_type: FileType::File,
-            size: self.data.len(),
-        })
+        Ok(self.file.metadata())
+    }
+
+    fn set_len(&mut self, len: usize) -> VfsResult<()> {
+        if !self.flags.is_writable() {
+            return Err(VfsError::PermissionDenied);
+        }
+
+        // pages that were never written read as zeros, so growing is free
+        if let TmpKind::File { size } = &self.file.kind {
+            if len < size.load(Ordering::Relaxed) {
+                cache::truncate(self.file.fs.id, self.file.ino, len);
+            }
+            size.store(len, Ordering::Relaxed);
+        }
+        self.file.modified();
+        Ok(())
     }
 }
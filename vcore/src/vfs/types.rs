@@ -1,18 +1,40 @@
-use alloc::{boxed::Box, string::String, vec::Vec};
+use alloc::{boxed::Box, string::String, sync::Arc, vec::Vec};
+use core::any::Any;
 
 pub const MAX_FDS: usize = 64;
+pub const NAME_MAX: usize = 255;
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum FileType {
     File,
     Directory,
     Device,
+    Symlink,
 }
 
 #[derive(Debug, Clone)]
 pub struct Metadata {
     pub file_type: FileType,
     pub size: usize,
+    pub uid: u32,
+    pub gid: u32,
+    /// permission bits, as in the low 12 bits of a unix mode
+    pub mode: u32,
+    /// last read, last written and last changed, in nanoseconds since the
+    /// unix epoch. 0 when the filesystem does not know
+    pub atime: u64,
+    pub mtime: u64,
+    pub ctime: u64,
+}
+
+/// capacity of a filesystem, counted in blocks of `block_size` bytes
+#[derive(Debug, Clone)]
+pub struct FsStats {
+    pub fs_type: &'static str,
+    pub block_size: usize,
+    pub total_blocks: u64,
+    pub free_blocks: u64,
+    pub max_name_len: usize,
 }
 
 #[derive(Debug, Clone)]
@@ -41,6 +63,7 @@ impl OpenFlags {
     pub const O_TRUNC: Self = Self(0o1000);
     pub const O_APPEND: Self = Self(0o2000);
     pub const O_DIRECTORY: Self = Self(0o200000);
+    pub const O_NOFOLLOW: Self = Self(0o400000);
 
     pub const fn empty() -> Self {
         Self(0)
@@ -85,6 +108,29 @@ impl OpenFlags {
     pub const APPEND: Self = Self(1 | 0o100 | 0o2000);
 }
 
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct MountFlags(u32);
+
+impl MountFlags {
+    pub const READ_ONLY: Self = Self(1);
+
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    pub const fn bits(&self) -> u32 {
+        self.0
+    }
+
+    pub const fn from_bits(bits: u32) -> Self {
+        Self(bits)
+    }
+
+    pub const fn contains(&self, other: Self) -> bool {
+        (self.0 & other.0) == other.0
+    }
+}
+
 impl core::ops::BitOr for OpenFlags {
     type Output = Self;
     fn bitor(self, rhs: Self) -> Self {
@@ -111,6 +157,18 @@ pub enum VfsError {
     InvalidFd,
     NotSupported,
     IoError,
+    Busy,
+    ReadOnly,
+    CrossDevice,
+    Loop,
+}
+
+/// one of the times handed to `utimens`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SetTime {
+    Now,
+    Omit,
+    At(u64),
 }
 
 pub type VfsResult<T> = Result<T, VfsError>;
@@ -120,16 +178,136 @@ pub trait FileHandle: Send + Sync {
     fn write(&mut self, buf: &[u8]) -> VfsResult<usize>;
     fn seek(&mut self, pos: SeekFrom) -> VfsResult<usize>;
     fn metadata(&self) -> VfsResult<Metadata>;
+
+    fn read_page_direct(&mut self, _index: u64, _buf: &mut [u8]) -> VfsResult<()> {
+        Err(VfsError::NotSupported)
+    }
+
+    fn write_page_direct(&mut self, _index: u64, _buf: &[u8]) -> VfsResult<()> {
+        Err(VfsError::NotSupported)
+    }
+
+    /// keeps the file out of the page cache until this handle is dropped:
+    /// cached pages are written back and dropped, and cached reads and
+    /// writes through any handle fail. for users of the direct calls only
+    fn claim_direct(&mut self) -> VfsResult<()> {
+        Ok(())
+    }
+
+    /// cuts the file short or grows it with zeros. the position stays put
+    fn set_len(&mut self, _len: usize) -> VfsResult<()> {
+        Err(VfsError::NotSupported)
+    }
+
+    /// writes back whatever this handle left in the page cache
+    fn flush(&mut self) -> VfsResult<()> {
+        Ok(())
+    }
+
+    /// flushes, then waits until the file has reached the device
+    fn sync(&mut self) -> VfsResult<()> {
+        self.flush()
+    }
+}
+
+pub type InodeRef = Arc<dyn Inode>;
+
+/// a file or directory within a filesystem. directory operations take a
+/// single name and default to failing, so each filesystem only implements
+/// what its nodes support
+pub trait Inode: Any + Send + Sync {
+    /// unique within the filesystem for as long as the inode is alive
+    fn id(&self) -> u64;
+    fn metadata(&self) -> VfsResult<Metadata>;
+
+    fn open(self: Arc<Self>, _flags: OpenFlags) -> VfsResult<Box<dyn FileHandle>> {
+        Err(VfsError::IsADirectory)
+    }
+
+    fn lookup(&self, _name: &str) -> VfsResult<InodeRef> {
+        Err(VfsError::NotADirectory)
+    }
+
+    fn create(&self, _name: &str, _file_type: FileType) -> VfsResult<InodeRef> {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn unlink(&self, _name: &str) -> VfsResult<()> {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn rmdir(&self, _name: &str) -> VfsResult<()> {
+        Err(VfsError::PermissionDenied)
+    }
+
+    /// creates a symbolic link called `name` pointing at `target`
+    fn symlink(&self, _name: &str, _target: &str) -> VfsResult<InodeRef> {
+        Err(VfsError::PermissionDenied)
+    }
+
+    /// adds `name` as another link to `inode`, which is on the same mount
+    fn link(&self, _name: &str, _inode: &InodeRef) -> VfsResult<()> {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn readlink(&self) -> VfsResult<String> {
+        Err(VfsError::InvalidPath)
+    }
+
+    fn chmod(&self, _mode: u32) -> VfsResult<()> {
+        Err(VfsError::NotSupported)
+    }
+
+    fn chown(&self, _uid: u32, _gid: u32) -> VfsResult<()> {
+        Err(VfsError::NotSupported)
+    }
+
+    /// `None` leaves that time alone. the change time moves to now
+    fn set_times(&self, _atime: Option<u64>, _mtime: Option<u64>) -> VfsResult<()> {
+        Err(VfsError::NotSupported)
+    }
+
+    /// moves `name` to `new_name` in `new_dir`, replacing whatever is there.
+    /// `new_dir` is on the same mount
+    fn rename(&self, _name: &str, _new_dir: &InodeRef, _new_name: &str) -> VfsResult<()> {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn readdir(&self) -> VfsResult<Vec<DirEntry>> {
+        Err(VfsError::NotADirectory)
+    }
 }
 
 pub trait Filesystem: Send + Sync {
-    fn open(&self, path: &str, flags: OpenFlags) -> VfsResult<Box<dyn FileHandle>>;
-    fn mkdir(&self, path: &str) -> VfsResult<()>;
-    fn remove(&self, path: &str) -> VfsResult<()>;
-    fn rmdir(&self, path: &str) -> VfsResult<()>;
-    fn readdir(&self, path: &str) -> VfsResult<Vec<DirEntry>>;
-    fn metadata(&self, path: &str) -> VfsResult<Metadata>;
-    fn exists(&self, path: &str) -> bool {
-        self.metadata(path).is_ok()
+    fn root(&self) -> InodeRef;
+
+    /// the type shown in the mount table
+    fn name(&self) -> &'static str;
+
+    /// filesystems whose entries appear and vanish behind the vfs's back
+    /// opt out of the dentry cache
+    fn cache_dentries(&self) -> bool {
+        true
+    }
+
+    /// writes back everything dirty and flushes the device underneath
+    fn sync(&self) -> VfsResult<()> {
+        Ok(())
+    }
+
+    /// called once the mount has no users. an error keeps it mounted
+    fn unmount(&self) -> VfsResult<()> {
+        Ok(())
+    }
+
+    /// filesystems that store nothing report no blocks at all
+    fn statfs(&self) -> VfsResult<FsStats> {
+        Ok(FsStats {
+            fs_type: self.name(),
+            block_size: crate::mem::PAGE_SIZE,
+            total_blocks: 0,
+            free_blocks: 0,
+            max_name_len: NAME_MAX,
+        })
     }
 }